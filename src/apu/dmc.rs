@@ -0,0 +1,254 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// Timer periods in CPU cycles (NTSC).
+const RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const IRQ_ENABLED: u8 = 0b1000_0000;
+const LOOP: u8 = 0b0100_0000;
+const RATE_INDEX: u8 = 0b0000_1111;
+const OUTPUT_LEVEL: u8 = 0b0111_1111;
+const SAMPLE_ADDRESS_START: u16 = 0xC000;
+const MAX_OUTPUT_LEVEL: u8 = 127;
+
+/// Delta modulation channel at $4010-$4013, samples are fetched
+/// through the bus by the `Apu` when `sample_request` asks for them.
+/// https://www.nesdev.org/wiki/APU_DMC
+pub struct Dmc {
+    is_irq_enabled: bool,
+    is_looping: bool,
+    period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    is_silenced: bool,
+    irq: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            is_irq_enabled: false,
+            is_looping: false,
+            period: RATES[0] - 1,
+            timer: 0,
+            output_level: 0,
+            sample_address: SAMPLE_ADDRESS_START,
+            sample_length: 1,
+            current_address: SAMPLE_ADDRESS_START,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            is_silenced: true,
+            irq: false,
+        }
+    }
+}
+
+impl Dmc {
+    /// Writes the channel register `register` (0-3).
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.is_irq_enabled = data & IRQ_ENABLED != 0;
+                if !self.is_irq_enabled {
+                    self.irq = false;
+                }
+                self.is_looping = data & LOOP != 0;
+                self.period = RATES[usize::from(data & RATE_INDEX)] - 1;
+            }
+            1 => self.output_level = data & OUTPUT_LEVEL,
+            2 => self.sample_address = SAMPLE_ADDRESS_START + u16::from(data) * 64,
+            _ => self.sample_length = u16::from(data) * 16 + 1,
+        }
+    }
+
+    /// Enabled through $4015, the sample restarts only when it was over.
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.irq = false;
+        if !is_enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    /// Address of the next sample byte when the sample buffer is empty.
+    pub fn sample_request(&self) -> Option<u16> {
+        (self.sample_buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    /// Fills the sample buffer with the byte read at `sample_request`.
+    pub fn load_sample(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.is_looping {
+                self.restart();
+            } else if self.is_irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+        if !self.is_silenced {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= MAX_OUTPUT_LEVEL - 2 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.is_silenced = false;
+                    self.shift_register = sample;
+                }
+                None => self.is_silenced = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+impl Snapshot for Dmc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_irq_enabled);
+        writer.write_bool(self.is_looping);
+        for value in [
+            self.period,
+            self.timer,
+            self.sample_address,
+            self.sample_length,
+            self.current_address,
+            self.bytes_remaining,
+        ] {
+            writer.write_u16(value);
+        }
+        writer.write_u8(self.output_level);
+        writer.write_bool(self.sample_buffer.is_some());
+        writer.write_u8(self.sample_buffer.unwrap_or_default());
+        writer.write_array(&[self.shift_register, self.bits_remaining]);
+        writer.write_bool(self.is_silenced);
+        writer.write_bool(self.irq);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.is_irq_enabled = reader.read_bool()?;
+        self.is_looping = reader.read_bool()?;
+        for value in [
+            &mut self.period,
+            &mut self.timer,
+            &mut self.sample_address,
+            &mut self.sample_length,
+            &mut self.current_address,
+            &mut self.bytes_remaining,
+        ] {
+            *value = reader.read_u16()?;
+        }
+        self.output_level = reader.read_u8()?;
+        let has_sample = reader.read_bool()?;
+        let sample = reader.read_u8()?;
+        self.sample_buffer = has_sample.then_some(sample);
+        [self.shift_register, self.bits_remaining] = reader.read_array()?;
+        self.is_silenced = reader.read_bool()?;
+        self.irq = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_fetch_and_irq() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, IRQ_ENABLED);
+        dmc.write(2, 0xFF);
+        dmc.write(3, 0);
+        assert_eq!(None, dmc.sample_request());
+        dmc.set_enabled(true);
+        assert_eq!(Some(0xFFC0), dmc.sample_request());
+        dmc.load_sample(0xFF);
+        assert_eq!(None, dmc.sample_request());
+        assert!(!dmc.is_active());
+        assert!(dmc.irq());
+        dmc.set_enabled(false);
+        assert!(!dmc.irq());
+    }
+
+    #[test]
+    fn test_address_wraps_and_loop() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, LOOP);
+        dmc.write(2, 0xFF);
+        dmc.write(3, 4);
+        dmc.set_enabled(true);
+        for _ in 0..64 {
+            dmc.sample_buffer = None;
+            dmc.load_sample(0);
+        }
+        assert_eq!(0x8000, dmc.current_address);
+        dmc.sample_buffer = None;
+        dmc.load_sample(0);
+        assert_eq!(0xFFC0, dmc.current_address);
+        assert!(dmc.is_active());
+    }
+
+    #[test]
+    fn test_output_unit() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, 0x0F);
+        dmc.write(1, 10);
+        dmc.sample_buffer = Some(0b0000_0101);
+        for _ in 0..8 * 54 {
+            dmc.clock_timer();
+        }
+        assert_eq!(10, dmc.output());
+        let mut levels = Vec::new();
+        for _ in 0..4 {
+            for _ in 0..54 {
+                dmc.clock_timer();
+            }
+            levels.push(dmc.output());
+        }
+        assert_eq!(vec![12, 10, 12, 10], levels);
+    }
+}
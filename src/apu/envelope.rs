@@ -0,0 +1,112 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const ENVELOPE_LOOP: u8 = 0b0010_0000;
+const CONSTANT_VOLUME: u8 = 0b0001_0000;
+const VOLUME: u8 = 0b0000_1111;
+const DECAY_START: u8 = 15;
+
+/// Volume generator shared by the pulse and noise channels.
+/// https://www.nesdev.org/wiki/APU_Envelope
+#[derive(Default)]
+pub struct Envelope {
+    is_started: bool,
+    is_looping: bool,
+    is_constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn write(&mut self, data: u8) {
+        self.is_looping = data & ENVELOPE_LOOP != 0;
+        self.is_constant = data & CONSTANT_VOLUME != 0;
+        self.volume = data & VOLUME;
+    }
+
+    pub fn restart(&mut self) {
+        self.is_started = true;
+    }
+
+    /// Clocked by the quarter frames.
+    pub fn clock(&mut self) {
+        if self.is_started {
+            self.is_started = false;
+            self.decay = DECAY_START;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.is_looping {
+                self.decay = DECAY_START;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.is_constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_started);
+        writer.write_bool(self.is_looping);
+        writer.write_bool(self.is_constant);
+        writer.write_array(&[self.volume, self.divider, self.decay]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.is_started = reader.read_bool()?;
+        self.is_looping = reader.read_bool()?;
+        self.is_constant = reader.read_bool()?;
+        [self.volume, self.divider, self.decay] = reader.read_array()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_volume() {
+        let mut envelope = Envelope::default();
+        envelope.write(CONSTANT_VOLUME | 7);
+        envelope.restart();
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(7, envelope.output());
+    }
+
+    #[test]
+    fn test_decay_and_loop() {
+        let mut envelope = Envelope::default();
+        envelope.write(1);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(14, envelope.output());
+        for _ in 0..28 {
+            envelope.clock();
+        }
+        assert_eq!(0, envelope.output());
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(0, envelope.output());
+
+        envelope.write(ENVELOPE_LOOP | 1);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+    }
+}
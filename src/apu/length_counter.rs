@@ -0,0 +1,90 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Silences a channel once its note duration elapsed.
+/// https://www.nesdev.org/wiki/APU_Length_Counter
+#[derive(Default)]
+pub struct LengthCounter {
+    is_enabled: bool,
+    is_halted: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    /// Enabled through $4015, disabling the channel clears the counter.
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+        if !is_enabled {
+            self.value = 0;
+        }
+    }
+
+    pub fn set_halted(&mut self, is_halted: bool) {
+        self.is_halted = is_halted;
+    }
+
+    /// Loads the counter from the 5 bits index written in the channel last register.
+    pub fn load(&mut self, index: u8) {
+        if self.is_enabled {
+            self.value = LENGTH_TABLE[usize::from(index & 0x1F)];
+        }
+    }
+
+    /// Clocked by the half frames.
+    pub fn clock(&mut self) {
+        if self.value > 0 && !self.is_halted {
+            self.value -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.value > 0
+    }
+}
+
+impl Snapshot for LengthCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        writer.write_bool(self.is_halted);
+        writer.write_u8(self.value);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.is_enabled = reader.read_bool()?;
+        self.is_halted = reader.read_bool()?;
+        self.value = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_counter() {
+        let mut counter = LengthCounter::default();
+        counter.load(3);
+        assert!(!counter.is_active());
+
+        counter.set_enabled(true);
+        counter.load(3);
+        counter.clock();
+        assert!(counter.is_active());
+        counter.clock();
+        assert!(!counter.is_active());
+
+        counter.load(0);
+        counter.set_halted(true);
+        for _ in 0..20 {
+            counter.clock();
+        }
+        assert!(counter.is_active());
+        counter.set_enabled(false);
+        assert!(!counter.is_active());
+    }
+}
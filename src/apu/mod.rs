@@ -0,0 +1,414 @@
+mod dmc;
+mod envelope;
+mod length_counter;
+mod noise;
+mod pulse;
+mod triangle;
+
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::traits::Device;
+use dmc::Dmc;
+use lazy_static::lazy_static;
+use noise::Noise;
+use pulse::{Pulse, PulseId};
+use triangle::Triangle;
+
+/// $4000-$4013 channels, $4015 status and $4017 frame counter.
+/// $4014 is the OAM DMA and $4016/$4017 reads belong to the joypads,
+/// they are registered before the APU so the bus reads them first.
+const MEMORY_RANGE: std::ops::Range<usize> = 0x4000..0x4018;
+const PULSE_1_START: u16 = 0x4000;
+const PULSE_2_START: u16 = 0x4004;
+const TRIANGLE_START: u16 = 0x4008;
+const NOISE_START: u16 = 0x400C;
+const DMC_START: u16 = 0x4010;
+const STATUS: u16 = 0x4015;
+const FRAME_COUNTER: u16 = 0x4017;
+
+const STATUS_PULSE_1: u8 = 0b0000_0001;
+const STATUS_PULSE_2: u8 = 0b0000_0010;
+const STATUS_TRIANGLE: u8 = 0b0000_0100;
+const STATUS_NOISE: u8 = 0b0000_1000;
+const STATUS_DMC: u8 = 0b0001_0000;
+const STATUS_FRAME_IRQ: u8 = 0b0100_0000;
+const STATUS_DMC_IRQ: u8 = 0b1000_0000;
+const STATUS_OPEN_BUS_MASK: u8 = 0b0010_0000;
+const FRAME_COUNTER_5_STEP: u8 = 0b1000_0000;
+const FRAME_COUNTER_IRQ_INHIBIT: u8 = 0b0100_0000;
+
+/// Frame counter steps in CPU cycles (NTSC).
+/// https://www.nesdev.org/wiki/APU_Frame_Counter
+const QUARTER_FRAME_1: u32 = 7457;
+const HALF_FRAME_1: u32 = 14913;
+const QUARTER_FRAME_3: u32 = 22371;
+const FOUR_STEP_LAST: u32 = 29829;
+const FIVE_STEP_LAST: u32 = 37281;
+
+pub const CPU_FREQUENCY: u32 = 1_789_773;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// Samples not taken by the front end are dropped past one second.
+const MAX_BUFFERED_SECONDS: usize = 1;
+
+lazy_static! {
+    /// https://www.nesdev.org/wiki/APU_Mixer#Lookup_Table
+    static ref PULSE_TABLE: [f32; 31] = {
+        let mut table = [0.0; 31];
+        for (n, value) in table.iter_mut().enumerate().skip(1) {
+            *value = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        table
+    };
+    static ref TND_TABLE: [f32; 203] = {
+        let mut table = [0.0; 203];
+        for (n, value) in table.iter_mut().enumerate().skip(1) {
+            *value = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        table
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FrameCounterMode {
+    FourStep,
+    FiveStep,
+}
+
+/// https://www.nesdev.org/wiki/APU
+pub struct Apu {
+    pulse_1: Pulse,
+    pulse_2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter_mode: FrameCounterMode,
+    is_frame_irq_inhibited: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    sample_rate: u32,
+    sample_clock: u32,
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            pulse_1: Pulse::new(PulseId::One),
+            pulse_2: Pulse::new(PulseId::Two),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter_mode: FrameCounterMode::FourStep,
+            is_frame_irq_inhibited: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_clock: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0;
+    }
+
+    /// Level of the frame counter and DMC interrupts.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq()
+    }
+
+    /// Mixed samples in [0.0, 1.0] produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Address of the next DMC sample byte, to be read on the CPU bus and
+    /// given to `load_sample` before the next cycle.
+    /// https://www.nesdev.org/wiki/APU_DMC#Memory_reader
+    pub fn sample_request(&self) -> Option<u16> {
+        self.dmc.sample_request()
+    }
+
+    pub fn load_sample(&mut self, data: u8) {
+        self.dmc.load_sample(data);
+    }
+
+    /// Advances the APU by `cycles` CPU cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        self.clock_frame_counter();
+        self.pulse_1.clock_timer();
+        self.pulse_2.clock_timer();
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.sample_clock += self.sample_rate;
+        if self.sample_clock >= CPU_FREQUENCY {
+            self.sample_clock -= CPU_FREQUENCY;
+            let max_samples = self.sample_rate as usize * MAX_BUFFERED_SECONDS;
+            if self.samples.len() < max_samples {
+                self.samples.push(self.output());
+            }
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        match (self.frame_counter_mode, self.frame_cycle) {
+            (_, QUARTER_FRAME_1 | QUARTER_FRAME_3) => self.clock_quarter_frame(),
+            (_, HALF_FRAME_1) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (FrameCounterMode::FourStep, FOUR_STEP_LAST) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.is_frame_irq_inhibited {
+                    self.frame_irq = true;
+                }
+                self.frame_cycle = 0;
+            }
+            (FrameCounterMode::FiveStep, FIVE_STEP_LAST) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                self.frame_cycle = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_1.clock_quarter_frame();
+        self.pulse_2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse_1.clock_half_frame();
+        self.pulse_2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// https://www.nesdev.org/wiki/APU_Mixer
+    fn output(&self) -> f32 {
+        let pulse = self.pulse_1.output() + self.pulse_2.output();
+        let tnd = 3 * self.triangle.output() + 2 * self.noise.output() + self.dmc.output();
+        PULSE_TABLE[usize::from(pulse)] + TND_TABLE[usize::from(tnd)]
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            PULSE_1_START..=0x4003 => self.pulse_1.write(addr - PULSE_1_START, data),
+            PULSE_2_START..=0x4007 => self.pulse_2.write(addr - PULSE_2_START, data),
+            TRIANGLE_START..=0x400B => self.triangle.write(addr - TRIANGLE_START, data),
+            NOISE_START..=0x400F => self.noise.write(addr - NOISE_START, data),
+            DMC_START..=0x4013 => self.dmc.write(addr - DMC_START, data),
+            STATUS => {
+                self.pulse_1
+                    .length_counter
+                    .set_enabled(data & STATUS_PULSE_1 != 0);
+                self.pulse_2
+                    .length_counter
+                    .set_enabled(data & STATUS_PULSE_2 != 0);
+                self.triangle
+                    .length_counter
+                    .set_enabled(data & STATUS_TRIANGLE != 0);
+                self.noise
+                    .length_counter
+                    .set_enabled(data & STATUS_NOISE != 0);
+                self.dmc.set_enabled(data & STATUS_DMC != 0);
+            }
+            FRAME_COUNTER => {
+                self.frame_counter_mode = if data & FRAME_COUNTER_5_STEP != 0 {
+                    FrameCounterMode::FiveStep
+                } else {
+                    FrameCounterMode::FourStep
+                };
+                self.is_frame_irq_inhibited = data & FRAME_COUNTER_IRQ_INHIBIT != 0;
+                if self.is_frame_irq_inhibited {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.frame_counter_mode == FrameCounterMode::FiveStep {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Reading the status acknowledges the frame interrupt.
+    fn read_status(&mut self) -> u8 {
+        let status = self.status();
+        self.frame_irq = false;
+        status
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        for (is_active, flag) in [
+            (self.pulse_1.length_counter.is_active(), STATUS_PULSE_1),
+            (self.pulse_2.length_counter.is_active(), STATUS_PULSE_2),
+            (self.triangle.length_counter.is_active(), STATUS_TRIANGLE),
+            (self.noise.length_counter.is_active(), STATUS_NOISE),
+            (self.dmc.is_active(), STATUS_DMC),
+            (self.frame_irq, STATUS_FRAME_IRQ),
+            (self.dmc.irq(), STATUS_DMC_IRQ),
+        ] {
+            if is_active {
+                status |= flag;
+            }
+        }
+        status
+    }
+}
+
+impl Device for Apu {
+    fn mapping_def(&self) -> std::ops::Range<usize> {
+        MEMORY_RANGE
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            STATUS => self.read_status(),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.write_register(addr, data)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            STATUS => self.status(),
+            _ => 0,
+        }
+    }
+
+    /// Only the status can be read, and its bit 5 is not driven.
+    /// https://www.nesdev.org/wiki/APU#Status_($4015)
+    fn open_bus_mask(&self, addr: u16) -> u8 {
+        match addr {
+            STATUS => STATUS_OPEN_BUS_MASK,
+            _ => 0xFF,
+        }
+    }
+}
+
+impl Snapshot for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse_1.save_state(writer);
+        self.pulse_2.save_state(writer);
+        self.triangle.save_state(writer);
+        self.noise.save_state(writer);
+        self.dmc.save_state(writer);
+        writer.write_bool(self.frame_counter_mode == FrameCounterMode::FiveStep);
+        writer.write_bool(self.is_frame_irq_inhibited);
+        writer.write_bool(self.frame_irq);
+        writer.write_u32(self.frame_cycle);
+        writer.write_u32(self.sample_clock);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.pulse_1.load_state(reader)?;
+        self.pulse_2.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.dmc.load_state(reader)?;
+        self.frame_counter_mode = if reader.read_bool()? {
+            FrameCounterMode::FiveStep
+        } else {
+            FrameCounterMode::FourStep
+        };
+        self.is_frame_irq_inhibited = reader.read_bool()?;
+        self.frame_irq = reader.read_bool()?;
+        self.frame_cycle = reader.read_u32()?;
+        self.sample_clock = reader.read_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_reports_length_counters() {
+        let mut apu = Apu::new();
+        apu.write(0x4003, 0x08);
+        assert_eq!(0, apu.read(STATUS));
+
+        apu.write(STATUS, STATUS_PULSE_1 | STATUS_NOISE);
+        apu.write(0x4003, 0x08);
+        apu.write(0x400F, 0x08);
+        apu.write(0x400B, 0x08);
+        assert_eq!(STATUS_PULSE_1 | STATUS_NOISE, apu.read(STATUS));
+
+        apu.write(STATUS, 0);
+        assert_eq!(0, apu.read(STATUS));
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = Apu::new();
+        apu.tick(FOUR_STEP_LAST - 1);
+        assert!(!apu.irq());
+        apu.tick(1);
+        assert!(apu.irq());
+        assert_eq!(STATUS_FRAME_IRQ, apu.read(STATUS));
+        assert!(!apu.irq());
+
+        apu.tick(FOUR_STEP_LAST);
+        assert_eq!(STATUS_FRAME_IRQ, apu.peek(STATUS));
+        assert!(apu.irq());
+        apu.read(STATUS);
+
+        apu.write(FRAME_COUNTER, FRAME_COUNTER_IRQ_INHIBIT);
+        apu.tick(FOUR_STEP_LAST);
+        assert!(!apu.irq());
+
+        apu.write(FRAME_COUNTER, FRAME_COUNTER_5_STEP);
+        apu.tick(FIVE_STEP_LAST);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_length_counters_follow_half_frames() {
+        let mut apu = Apu::new();
+        apu.write(STATUS, STATUS_PULSE_2);
+        // length index 3 loads 2
+        apu.write(0x4007, 3 << 3);
+        apu.tick(HALF_FRAME_1);
+        assert_eq!(STATUS_PULSE_2, apu.read(STATUS));
+        apu.tick(FOUR_STEP_LAST - HALF_FRAME_1);
+        assert_eq!(STATUS_FRAME_IRQ, apu.read(STATUS));
+    }
+
+    #[test]
+    fn test_mixer() {
+        let mut apu = Apu::new();
+        assert_eq!(TND_TABLE[3 * 15], apu.output());
+        apu.set_sample_rate(CPU_FREQUENCY / 10);
+        apu.tick(110);
+        assert_eq!(10, apu.take_samples().len());
+        assert!(apu.take_samples().is_empty());
+        assert!((PULSE_TABLE[30] - 0.2575).abs() < 0.001);
+        assert!((TND_TABLE[202] - 0.7425).abs() < 0.001);
+    }
+}
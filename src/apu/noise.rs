@@ -0,0 +1,132 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// Timer periods in CPU cycles (NTSC).
+const PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const LENGTH_HALT: u8 = 0b0010_0000;
+const SHORT_MODE: u8 = 0b1000_0000;
+const PERIOD_INDEX: u8 = 0b0000_1111;
+
+/// Pseudo-random noise channel at $400C-$400F.
+/// https://www.nesdev.org/wiki/APU_Noise
+pub struct Noise {
+    is_short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift_register: u16,
+    envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            is_short_mode: false,
+            period: PERIODS[0] - 1,
+            timer: 0,
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    /// Writes the channel register `register` (0-3), register 1 is unused.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length_counter.set_halted(data & LENGTH_HALT != 0);
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.is_short_mode = data & SHORT_MODE != 0;
+                self.period = PERIODS[usize::from(data & PERIOD_INDEX)] - 1;
+            }
+            _ => {
+                self.length_counter.load(data >> 3);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+        let tap = if self.is_short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 1 || !self.length_counter.is_active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Snapshot for Noise {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_short_mode);
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        writer.write_u16(self.shift_register);
+        self.envelope.save_state(writer);
+        self.length_counter.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.is_short_mode = reader.read_bool()?;
+        self.period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.shift_register = reader.read_u16()?;
+        self.envelope.load_state(reader)?;
+        self.length_counter.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence_length(is_short_mode: bool) -> usize {
+        let mut noise = Noise::default();
+        noise.write(2, if is_short_mode { SHORT_MODE } else { 0 });
+        noise.clock_timer();
+        let start = noise.shift_register;
+        let mut length = 1;
+        loop {
+            for _ in 0..PERIODS[0] {
+                noise.clock_timer();
+            }
+            if noise.shift_register == start {
+                return length;
+            }
+            length += 1;
+        }
+    }
+
+    #[test]
+    fn test_lfsr_modes() {
+        assert_eq!(32767, sequence_length(false));
+        assert_eq!(93, sequence_length(true));
+    }
+}
@@ -0,0 +1,234 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+const LENGTH_HALT: u8 = 0b0010_0000;
+const SWEEP_ENABLED: u8 = 0b1000_0000;
+const SWEEP_NEGATE: u8 = 0b0000_1000;
+const SWEEP_SHIFT: u8 = 0b0000_0111;
+const TIMER_HI: u8 = 0b0000_0111;
+const MIN_PERIOD: u16 = 8;
+const MAX_PERIOD: u16 = 0x07FF;
+
+/// Which of the two pulse channels, they only differ by their sweep negation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PulseId {
+    One,
+    Two,
+}
+
+/// https://www.nesdev.org/wiki/APU_Sweep
+#[derive(Default)]
+struct Sweep {
+    is_enabled: bool,
+    is_negated: bool,
+    period: u8,
+    shift: u8,
+    divider: u8,
+    is_reloaded: bool,
+}
+
+/// Square wave channel at $4000-$4003 or $4004-$4007.
+/// https://www.nesdev.org/wiki/APU_Pulse
+pub struct Pulse {
+    id: PulseId,
+    duty: usize,
+    duty_step: usize,
+    period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    pub length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(id: PulseId) -> Self {
+        Self {
+            id,
+            duty: 0,
+            duty_step: 0,
+            period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    /// Writes the channel register `register` (0-3).
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = usize::from(data >> 6);
+                self.length_counter.set_halted(data & LENGTH_HALT != 0);
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep = Sweep {
+                    is_enabled: data & SWEEP_ENABLED != 0,
+                    is_negated: data & SWEEP_NEGATE != 0,
+                    period: (data >> 4) & 0b111,
+                    shift: data & SWEEP_SHIFT,
+                    divider: self.sweep.divider,
+                    is_reloaded: true,
+                }
+            }
+            2 => self.period = (self.period & 0xFF00) | u16::from(data),
+            _ => {
+                self.period = (self.period & 0x00FF) | (u16::from(data & TIMER_HI) << 8);
+                self.length_counter.load(data >> 3);
+                self.duty_step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, the pulse timer counts APU cycles.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period * 2 + 1;
+            self.duty_step = (self.duty_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        if self.sweep.divider == 0
+            && self.sweep.is_enabled
+            && self.sweep.shift > 0
+            && !self.is_muted()
+        {
+            self.period = self.target_period();
+        }
+        if self.sweep.divider == 0 || self.sweep.is_reloaded {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.is_reloaded = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.period >> self.sweep.shift;
+        if !self.sweep.is_negated {
+            return self.period + change;
+        }
+        match self.id {
+            PulseId::One => self.period.saturating_sub(change + 1),
+            PulseId::Two => self.period.saturating_sub(change),
+        }
+    }
+
+    /// The sweep unit mutes the channel even when it is disabled.
+    fn is_muted(&self) -> bool {
+        self.period < MIN_PERIOD || self.target_period() > MAX_PERIOD
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.is_muted()
+            || !self.length_counter.is_active()
+            || DUTY_SEQUENCES[self.duty][self.duty_step] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Snapshot for Sweep {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        writer.write_bool(self.is_negated);
+        writer.write_array(&[self.period, self.shift, self.divider]);
+        writer.write_bool(self.is_reloaded);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.is_enabled = reader.read_bool()?;
+        self.is_negated = reader.read_bool()?;
+        [self.period, self.shift, self.divider] = reader.read_array()?;
+        self.is_reloaded = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Pulse {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_array(&[self.duty as u8, self.duty_step as u8]);
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        self.envelope.save_state(writer);
+        self.sweep.save_state(writer);
+        self.length_counter.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let [duty, duty_step] = reader.read_array()?;
+        self.duty = usize::from(duty) % DUTY_SEQUENCES.len();
+        self.duty_step = usize::from(duty_step) % DUTY_SEQUENCES[0].len();
+        self.period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.envelope.load_state(reader)?;
+        self.sweep.load_state(reader)?;
+        self.length_counter.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_pulse(id: PulseId, period: u16) -> Pulse {
+        let mut pulse = Pulse::new(id);
+        pulse.length_counter.set_enabled(true);
+        pulse.write(0, 0b1011_1111);
+        pulse.write(2, period as u8);
+        pulse.write(3, (period >> 8) as u8);
+        pulse
+    }
+
+    #[test]
+    fn test_duty_cycle() {
+        let mut pulse = playing_pulse(PulseId::One, 8);
+        let mut outputs = Vec::new();
+        for _ in 0..8 {
+            for _ in 0..18 {
+                pulse.clock_timer();
+            }
+            outputs.push(pulse.output());
+        }
+        assert_eq!(vec![15, 15, 15, 15, 0, 0, 0, 0], outputs);
+    }
+
+    #[test]
+    fn test_sweep_mutes_and_negates() {
+        let pulse = playing_pulse(PulseId::One, 7);
+        assert!(pulse.is_muted());
+
+        let mut pulse = playing_pulse(PulseId::One, 0x600);
+        pulse.write(1, 0b0000_0001);
+        assert!(pulse.is_muted());
+
+        pulse.write(1, SWEEP_ENABLED | SWEEP_NEGATE | 1);
+        pulse.clock_half_frame();
+        assert_eq!(0x2FF, pulse.period);
+
+        let mut pulse = playing_pulse(PulseId::Two, 0x600);
+        pulse.write(1, SWEEP_ENABLED | SWEEP_NEGATE | 1);
+        pulse.clock_half_frame();
+        assert_eq!(0x300, pulse.period);
+    }
+}
@@ -0,0 +1,125 @@
+use super::length_counter::LengthCounter;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+const CONTROL: u8 = 0b1000_0000;
+const LINEAR_COUNTER_RELOAD: u8 = 0b0111_1111;
+const TIMER_HI: u8 = 0b0000_0111;
+
+/// Triangle wave channel at $4008-$400B.
+/// https://www.nesdev.org/wiki/APU_Triangle
+#[derive(Default)]
+pub struct Triangle {
+    period: u16,
+    timer: u16,
+    step: usize,
+    is_controlled: bool,
+    linear_counter: u8,
+    linear_counter_reload: u8,
+    is_linear_counter_reloading: bool,
+    pub length_counter: LengthCounter,
+}
+
+impl Triangle {
+    /// Writes the channel register `register` (0-3), register 1 is unused.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.is_controlled = data & CONTROL != 0;
+                self.length_counter.set_halted(self.is_controlled);
+                self.linear_counter_reload = data & LINEAR_COUNTER_RELOAD;
+            }
+            1 => {}
+            2 => self.period = (self.period & 0xFF00) | u16::from(data),
+            _ => {
+                self.period = (self.period & 0x00FF) | (u16::from(data & TIMER_HI) << 8);
+                self.length_counter.load(data >> 3);
+                self.is_linear_counter_reloading = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length_counter.is_active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) % SEQUENCE.len();
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// https://www.nesdev.org/wiki/APU_Triangle#Linear_counter
+    pub fn clock_quarter_frame(&mut self) {
+        if self.is_linear_counter_reloading {
+            self.linear_counter = self.linear_counter_reload;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.is_controlled {
+            self.is_linear_counter_reloading = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// The sequencer only stops, the last value keeps being output.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step]
+    }
+}
+
+impl Snapshot for Triangle {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.step as u8);
+        writer.write_bool(self.is_controlled);
+        writer.write_array(&[self.linear_counter, self.linear_counter_reload]);
+        writer.write_bool(self.is_linear_counter_reloading);
+        self.length_counter.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.step = usize::from(reader.read_u8()?) % SEQUENCE.len();
+        self.is_controlled = reader.read_bool()?;
+        [self.linear_counter, self.linear_counter_reload] = reader.read_array()?;
+        self.is_linear_counter_reloading = reader.read_bool()?;
+        self.length_counter.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triangle_needs_both_counters() {
+        let mut triangle = Triangle::default();
+        triangle.length_counter.set_enabled(true);
+        triangle.write(0, 2);
+        triangle.write(3, 0);
+        for _ in 0..4 {
+            triangle.clock_timer();
+        }
+        assert_eq!(15, triangle.output());
+
+        triangle.clock_quarter_frame();
+        triangle.clock_timer();
+        assert_eq!(14, triangle.output());
+
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+        triangle.clock_timer();
+        assert_eq!(14, triangle.output());
+    }
+}
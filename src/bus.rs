@@ -1,66 +1,350 @@
+use crate::apu::Apu;
+use crate::bus_log::{AccessKind, BusEvent, BusLog};
+use crate::cartridge::Cartridge;
+use crate::debugger::{Access, WatchHit, Watchpoint};
+use crate::joypad::{self, Joypad};
+use crate::ppu::Ppu;
+use crate::random_gen::RandomGenerator;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
 use crate::traits::{Device, Memory};
 
-use std::ops::Range;
-use std::ptr;
-
+/// The whole CPU address space, $0000-$FFFF.
+const MEMORY_SIZE: usize = 0x10000;
 const RAM_MIRRORING_MASK: u16 = 0b0000_0111_1111_1111;
 const PPU_REGISTERS_MIRRORING_MASK: u16 = 0b0010_0000_0000_0111;
+const CARTRIDGE_SPACE_START: u16 = 0x4020;
+const OAM_DMA: u16 = 0x4014;
+const OAM_DATA: u16 = 0x2004;
+const OAM_SIZE: u16 = 0x100;
+/// The CPU is suspended while the DMA copies OAM.
+const OAM_DMA_CYCLES: u32 = 513;
+/// The CPU is halted while the DMC fetches a sample byte.
+/// https://www.nesdev.org/wiki/APU_DMC#Memory_reader
+const DMC_DMA_CYCLES: u32 = 4;
+const PPU_DOTS_PER_CPU_CYCLE: u32 = 3;
 
+/// CPU bus of the console, it owns the memory and every device mapped in it.
 pub struct Bus {
-    memory: *mut [u8; 0xFFFF],
-    devices: Vec<(Range<usize>, *mut dyn Device)>,
+    memory: Box<[u8; MEMORY_SIZE]>,
+    pub joypad_1: Joypad,
+    pub joypad_2: Joypad,
+    pub color_generator: RandomGenerator,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    /// Devices attached on top of the console ones.
+    devices: Vec<Box<dyn Device>>,
+    cartridge: Option<Cartridge>,
+    /// Last value driven on the data bus, read back from the floating bits.
+    data_bus: u8,
+    stall_cycles: u32,
+    watchpoints: Vec<Watchpoint>,
+    watch_hit: Option<WatchHit>,
+    /// What the CPU accesses the bus for, and on which cycle.
+    access_kind: AccessKind,
+    access_cycle: u64,
+    log: Option<BusLog>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bus {
-    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         Self {
-            memory: ptr::null_mut(),
+            memory: Box::new([0; MEMORY_SIZE]),
+            joypad_1: Joypad::new(0x4016),
+            joypad_2: Joypad::new(0x4017),
+            color_generator: RandomGenerator::new(0x4018, 1..16),
+            ppu: Ppu::new(),
+            apu: Apu::new(),
             devices: Vec::new(),
+            cartridge: None,
+            data_bus: 0,
+            stall_cycles: 0,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            access_kind: AccessKind::Data,
+            access_cycle: 0,
+            log: None,
         }
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn map(&mut self, memory: &mut [u8; 0xFFFF], devices: *const [*mut dyn Device]) {
-        self.memory = memory;
-        for device in (*devices).iter() {
-            let mapping = (**device).mapping_def();
-            (**device).map(&mut (*self.memory)[mapping.clone()]);
-            self.devices.push((mapping, *device));
+    /// Maps `device` after the console devices, it only sees the accesses
+    /// they do not handle.
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.devices.push(device);
+    }
+
+    pub fn ram(&self) -> &[u8; MEMORY_SIZE] {
+        &self.memory
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8; MEMORY_SIZE] {
+        &mut self.memory
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    /// Plugs `cartridge` on both the CPU and the PPU buses.
+    pub fn set_cartridge(&mut self, cartridge: Option<Cartridge>) {
+        self.ppu
+            .set_mapper(cartridge.as_ref().map(Cartridge::shared_mapper));
+        self.cartridge = cartridge;
+    }
+
+    /// Level of the IRQ line, shared by the cartridge and the APU.
+    pub fn irq(&self) -> bool {
+        self.cartridge.as_ref().is_some_and(Cartridge::irq) || self.apu.irq()
+    }
+
+    /// Steps the PPU and the APU in lockstep with the last `cycles` CPU
+    /// cycles. The DMC sample fetches are bus reads like the sprite DMA ones
+    /// and stall the CPU the same way.
+    pub fn tick(&mut self, cycles: u32) {
+        self.ppu.tick(cycles * PPU_DOTS_PER_CPU_CYCLE);
+        let first_cycle = (self.access_cycle + 1).saturating_sub(u64::from(cycles));
+        for cycle in first_cycle..first_cycle + u64::from(cycles) {
+            if let Some(addr) = self.apu.sample_request() {
+                self.set_access_context(AccessKind::Dma, cycle);
+                let data = self.mem_read_u8(addr);
+                self.apu.load_sample(data);
+                self.stall_cycles += DMC_DMA_CYCLES;
+            }
+            self.apu.tick(1);
         }
     }
 
-    fn mapped_device(&self, addr: u16) -> Option<*mut dyn Device> {
-        self.devices
-            .iter()
-            .find(|(range, _)| range.contains(&usize::from(addr)))
-            .map(|(_, device)| *device)
+    /// CPU cycles stolen by DMA transfers since the last call.
+    pub fn take_stall_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.stall_cycles)
     }
-}
 
-impl Memory for Bus {
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn load(&mut self, data: &[u8], dest: u16) {
-        (*self.memory)[usize::from(dest)..usize::from(dest) + data.len()].copy_from_slice(data);
+    pub fn watchpoints_mut(&mut self) -> &mut Vec<Watchpoint> {
+        &mut self.watchpoints
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read_u8(&mut self, addr: u16) -> u8 {
+    /// First access that matched a watchpoint since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    /// Records the accesses in `log`, see `BusLog`.
+    pub fn set_log(&mut self, log: Option<BusLog>) {
+        self.log = log;
+    }
+
+    pub fn log(&self) -> Option<&BusLog> {
+        self.log.as_ref()
+    }
+
+    pub fn log_mut(&mut self) -> Option<&mut BusLog> {
+        self.log.as_mut()
+    }
+
+    fn record(&mut self, addr: u16, value: u8, access: Access) {
+        if let Some(log) = self.log.as_mut() {
+            log.record(BusEvent {
+                cycle: self.access_cycle,
+                addr,
+                value,
+                access,
+                kind: self.access_kind,
+            });
+        }
+    }
+
+    fn watch(&mut self, addr: u16, value: u8, access: Access) {
+        if self.watch_hit.is_none()
+            && self.watchpoints.iter().any(|watchpoint| {
+                watchpoint.matches(addr, value, access)
+                    || watchpoint.matches(mirror_address(addr), value, access)
+            })
+        {
+            self.watch_hit = Some(WatchHit {
+                addr,
+                value,
+                access,
+            });
+        }
+    }
+
+    fn devices(&self) -> impl Iterator<Item = &dyn Device> + '_ {
+        [
+            &self.joypad_1 as &dyn Device,
+            &self.joypad_2,
+            &self.color_generator,
+            &self.ppu,
+            &self.apu,
+        ]
+        .into_iter()
+        .chain(
+            self.devices
+                .iter()
+                .map(|device| device.as_ref() as &dyn Device),
+        )
+    }
+
+    fn devices_mut(&mut self) -> impl Iterator<Item = &mut dyn Device> + '_ {
+        [
+            &mut self.joypad_1 as &mut dyn Device,
+            &mut self.joypad_2,
+            &mut self.color_generator,
+            &mut self.ppu,
+            &mut self.apu,
+        ]
+        .into_iter()
+        .chain(
+            self.devices
+                .iter_mut()
+                .map(|device| device.as_mut() as &mut dyn Device),
+        )
+    }
+
+    fn mapped_device(&self, addr: u16) -> Option<&dyn Device> {
+        self.devices()
+            .find(|device| device.mapping_def().contains(&usize::from(addr)))
+    }
+
+    fn mapped_device_mut(&mut self, addr: u16) -> Option<&mut dyn Device> {
+        self.devices_mut()
+            .find(|device| device.mapping_def().contains(&usize::from(addr)))
+    }
+
+    /// Every device sharing `addr` sees the writes and ignores the registers
+    /// it does not decode, like the second joypad read at $4017 where the
+    /// writes go to the APU frame counter.
+    fn mapped_devices_mut(&mut self, addr: u16) -> impl Iterator<Item = &mut dyn Device> + '_ {
+        self.devices_mut()
+            .filter(move |device| device.mapping_def().contains(&usize::from(addr)))
+    }
+
+    /// Copies the 256 bytes page `page` into the PPU OAM through OAMDATA.
+    /// https://www.nesdev.org/wiki/PPU_registers#OAMDMA
+    fn oam_dma(&mut self, page: u8) {
+        let start = u16::from(page) << 8;
+        // a read and a write per cycle pair, after the halt cycle
+        let mut cycle = self.access_cycle + 1;
+        for addr in start..start + OAM_SIZE {
+            self.set_access_context(AccessKind::Dma, cycle + 1);
+            let data = self.mem_read_u8(addr);
+            self.set_access_context(AccessKind::Dma, cycle + 2);
+            self.mem_write_u8(OAM_DATA, data);
+            cycle += 2;
+        }
+        self.stall_cycles += OAM_DMA_CYCLES;
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
         let addr = mirror_address(addr);
-        if let Some(device) = self.mapped_device(addr) {
-            (*device).mem_write();
+        let data_bus = self.data_bus;
+        let data = if let Some(data) = cartridge_read(self.cartridge.as_ref(), addr) {
+            data
+        } else if let Some(device) = self.mapped_device_mut(addr) {
+            let mask = device.open_bus_mask(addr);
+            merge_open_bus(device.read(addr), mask, data_bus)
+        } else if self.is_memory_backed(addr) {
+            self.memory[usize::from(addr)]
+        } else {
+            data_bus
         };
-        (*self.memory)[usize::from(addr)]
+        self.data_bus = data;
+        data
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write_u8(&mut self, addr: u16, data: u8) {
+    fn write(&mut self, addr: u16, data: u8) {
         let addr = mirror_address(addr);
-        (*self.memory)[usize::from(addr)] = data;
-        if let Some(device) = self.mapped_device(addr) {
-            (*device).mem_read();
-        };
+        self.data_bus = data;
+        if addr == OAM_DMA {
+            self.oam_dma(data);
+            return;
+        }
+        if let Some(cartridge) = self.cartridge.as_ref() {
+            if addr >= CARTRIDGE_SPACE_START {
+                let mut mapper = cartridge.mapper();
+                mapper.set_cpu_cycle(self.access_cycle);
+                mapper.cpu_write(addr, data);
+                return;
+            }
+        }
+        if addr == joypad::STROBE {
+            // the second port is read at $4017 but strobed at $4016
+            self.joypad_2.write(addr, data);
+        }
+        let mut is_mapped = false;
+        for device in self.mapped_devices_mut(addr) {
+            device.write(addr, data);
+            is_mapped = true;
+        }
+        if !is_mapped && self.is_memory_backed(addr) {
+            self.memory[usize::from(addr)] = data;
+        }
     }
+
+    /// `memory` holds the internal RAM and, with no cartridge plugged, the
+    /// cartridge space so programs can be loaded there. Reads elsewhere
+    /// return the open bus.
+    /// https://www.nesdev.org/wiki/CPU_memory_map
+    fn is_memory_backed(&self, addr: u16) -> bool {
+        addr <= RAM_MIRRORING_MASK || (self.cartridge.is_none() && addr >= CARTRIDGE_SPACE_START)
+    }
+}
+
+impl Memory for Bus {
+    fn load(&mut self, data: &[u8], dest: u16) {
+        self.memory[usize::from(dest)..usize::from(dest) + data.len()].copy_from_slice(data);
+    }
+
+    fn mem_read_u8(&mut self, addr: u16) -> u8 {
+        let data = self.read(addr);
+        self.watch(addr, data, Access::Read);
+        self.record(addr, data, Access::Read);
+        data
+    }
+
+    fn mem_write_u8(&mut self, addr: u16, data: u8) {
+        self.watch(addr, data, Access::Write);
+        self.record(addr, data, Access::Write);
+        self.write(addr, data);
+    }
+
+    fn peek_u8(&self, addr: u16) -> u8 {
+        let addr = mirror_address(addr);
+        if let Some(data) = cartridge_read(self.cartridge.as_ref(), addr) {
+            return data;
+        }
+        match self.mapped_device(addr) {
+            Some(device) => {
+                merge_open_bus(device.peek(addr), device.open_bus_mask(addr), self.data_bus)
+            }
+            None if self.is_memory_backed(addr) => self.memory[usize::from(addr)],
+            None => self.data_bus,
+        }
+    }
+
+    fn set_access_context(&mut self, kind: AccessKind, cycle: u64) {
+        self.access_kind = kind;
+        self.access_cycle = cycle;
+    }
+}
+
+/// Keeps the `data_bus` bits the device leaves floating.
+fn merge_open_bus(data: u8, open_bus_mask: u8, data_bus: u8) -> u8 {
+    (data & !open_bus_mask) | (data_bus & open_bus_mask)
+}
+
+fn cartridge_read(cartridge: Option<&Cartridge>, addr: u16) -> Option<u8> {
+    cartridge
+        .filter(|_| addr >= CARTRIDGE_SPACE_START)
+        .and_then(|cartridge| cartridge.mapper().cpu_read(addr))
 }
 
 fn mirror_address(addr: u16) -> u16 {
@@ -71,95 +355,202 @@ fn mirror_address(addr: u16) -> u16 {
     }
 }
 
+impl Snapshot for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data_bus);
+        writer.write_u32(self.stall_cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.data_bus = reader.read_u8()?;
+        self.stall_cycles = reader.read_u32()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::ops::Range;
 
+    /// Two registers, the first one counts its reads and the second one
+    /// only drives its low nibble.
     struct MockDevice {
-        start: usize,
-        memory: [*mut u8; 2],
+        start: u16,
+        registers: [u8; 2],
     }
 
     impl MockDevice {
-        fn new(start: usize) -> Self {
+        fn new(start: u16) -> Self {
             Self {
                 start,
-                memory: [std::ptr::null_mut(); 2],
+                registers: [0; 2],
             }
         }
     }
 
     impl Device for MockDevice {
         fn mapping_def(&self) -> Range<usize> {
-            self.start..self.start + 2
+            usize::from(self.start)..usize::from(self.start + 2)
         }
 
-        fn map(&mut self, memory: &mut [u8]) {
-            for (count, pointer) in self.memory.iter_mut().enumerate() {
-                *pointer = &mut memory[count];
+        fn read(&mut self, addr: u16) -> u8 {
+            let data = self.peek(addr);
+            if addr == self.start {
+                self.registers[0] += 1;
             }
+            data
         }
 
-        #[allow(clippy::missing_safety_doc)]
-        unsafe fn mem_read(&mut self) {
-            for (count, i) in (self.start..self.start + 2).into_iter().enumerate() {
-                (*self.memory[count]) = (i + 1) as u8;
-            }
+        fn write(&mut self, addr: u16, data: u8) {
+            self.registers[usize::from(addr - self.start)] = data;
         }
 
-        #[allow(clippy::missing_safety_doc)]
-        unsafe fn mem_write(&mut self) {
-            for (count, i) in (self.start..self.start + 2).into_iter().enumerate() {
-                let val = (2 * i + 1) as u8;
-                (*self.memory[count]) = val;
+        fn peek(&self, addr: u16) -> u8 {
+            self.registers[usize::from(addr - self.start)]
+        }
+
+        fn open_bus_mask(&self, addr: u16) -> u8 {
+            if addr == self.start {
+                0
+            } else {
+                0xF0
             }
         }
     }
 
     #[test]
     fn test_bus_mapping_read() {
-        let mut memory = [99; 0xFFFF];
-        let mut expected = [99; 0xFFFF];
-        expected[0] = 1;
-        expected[1] = 3;
-        expected[10] = 21;
-        expected[11] = 23;
-
-        for i in 0..=1u8 {
-            let mut device_1 = MockDevice::new(0);
-            let mut device_2 = MockDevice::new(10);
-            let mut bus = Bus::new();
-            unsafe {
-                memory[0] = 10;
-                bus.map(&mut memory, &[&mut device_1, &mut device_2]);
-                bus.mem_read_u8(i as u16);
-                bus.mem_read_u8((i + 10) as u16);
-            }
-            assert_eq!(expected, memory);
-            memory = [99; 0xFFFF];
-        }
+        let mut bus = Bus::new();
+        bus.ram_mut().fill(99);
+        bus.attach(Box::new(MockDevice::new(0x5000)));
+        bus.attach(Box::new(MockDevice::new(0x5010)));
+        assert_eq!(0, bus.mem_read_u8(0x5000));
+        assert_eq!(1, bus.mem_read_u8(0x5000));
+        assert_eq!(0, bus.mem_read_u8(0x5010));
+        assert_eq!(99, bus.mem_read_u8(0x5002));
+        assert_eq!([99, 99], bus.ram()[0x5000..0x5002]);
+    }
+
+    #[test]
+    fn test_peek_skips_device_hooks() {
+        let mut bus = Bus::new();
+        bus.ram_mut().fill(99);
+        bus.attach(Box::new(MockDevice::new(0x5000)));
+        assert_eq!(0, bus.peek_u8(0x5000));
+        assert_eq!(vec![0, 0], bus.peek_range(0x5000..0x5002));
+        assert_eq!(0, bus.mem_read_u8(0x5000));
+        bus.mem_write_u8(0x0801, 7);
+        assert_eq!(7, bus.peek_u8(0x0001));
+        assert_eq!(1, bus.peek_u8(0x5000));
     }
 
     #[test]
     fn test_bus_mapping_write() {
-        let mut memory = [99; 0xFFFF];
-        let mut expected = [99; 0xFFFF];
-        expected[5] = 6;
-        expected[6] = 7;
-        expected[15] = 16;
-        expected[16] = 17;
-
-        for i in 0..=1u8 {
-            let mut device_1 = MockDevice::new(5);
-            let mut device_2 = MockDevice::new(15);
-            let mut bus = Bus::new();
-            unsafe {
-                bus.map(&mut memory, &[&mut device_1, &mut device_2]);
-                bus.mem_write_u8((i + 5) as u16, 0);
-                bus.mem_write_u8((i + 15) as u16, 0);
-            }
-            assert_eq!(expected, memory);
-            memory = [99; 0xFFFF];
+        let mut bus = Bus::new();
+        bus.ram_mut().fill(99);
+        bus.attach(Box::new(MockDevice::new(0x5000)));
+        bus.mem_write_u8(0x5000, 5);
+        bus.mem_write_u8(0x5001, 6);
+        assert_eq!([99, 99], bus.ram()[0x5000..0x5002]);
+        assert_eq!(vec![5, 6], bus.peek_range(0x5000..0x5002));
+    }
+
+    #[test]
+    fn test_open_bus_bits() {
+        let mut bus = Bus::new();
+        bus.attach(Box::new(MockDevice::new(0x5000)));
+        bus.mem_write_u8(0x5001, 0xFF);
+        bus.mem_write_u8(0x0000, 0xA0);
+        assert_eq!(0xAF, bus.peek_u8(0x5001));
+        assert_eq!(0xAF, bus.mem_read_u8(0x5001));
+        bus.mem_read_u8(0x0000);
+        assert_eq!(0xAF, bus.mem_read_u8(0x5001));
+        bus.ram_mut()[0] = 0x30;
+        bus.mem_read_u8(0x0000);
+        assert_eq!(0x3F, bus.mem_read_u8(0x5001));
+    }
+
+    #[test]
+    fn test_joypad_open_bus() {
+        let mut bus = Bus::new();
+        bus.joypad_1.press(crate::joypad::Button::A);
+        bus.ram_mut()[0] = 0x40;
+        bus.mem_read_u8(0x0000);
+        assert_eq!(0x41, bus.mem_read_u8(0x4016));
+        assert_eq!(0x40, bus.mem_read_u8(0x4016));
+    }
+
+    #[test]
+    fn test_strobe_reaches_both_joypads() {
+        let mut bus = Bus::new();
+        bus.joypad_2.press(crate::joypad::Button::START);
+        for _ in 0..4 {
+            bus.mem_read_u8(0x4017);
         }
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        // most games write the frame counter while reading the joypads
+        bus.mem_write_u8(0x4017, 0x40);
+        let buttons: Vec<u8> = (0..8).map(|_| bus.mem_read_u8(0x4017) & 1).collect();
+        assert_eq!(vec![0, 0, 0, 1, 0, 0, 0, 0], buttons);
+    }
+
+    #[test]
+    fn test_dmc_fetch_is_a_bus_read() {
+        let mut bus = Bus::new();
+        bus.ram_mut()[0xC000] = 0x5A;
+        // 1 byte sample at $C000
+        bus.mem_write_u8(0x4012, 0);
+        bus.mem_write_u8(0x4013, 0);
+        bus.mem_write_u8(0x4015, 0x10);
+        bus.set_log(Some(BusLog::default()));
+        bus.set_access_context(AccessKind::Data, 20);
+        bus.tick(2);
+        let events: Vec<_> = bus.log().unwrap().events().copied().collect();
+        assert_eq!(
+            vec![BusEvent {
+                cycle: 19,
+                addr: 0xC000,
+                value: 0x5A,
+                access: Access::Read,
+                kind: AccessKind::Dma,
+            }],
+            events
+        );
+        assert_eq!(0x5A, bus.mem_read_u8(0x4019));
+        assert_eq!(DMC_DMA_CYCLES, bus.take_stall_cycles());
+    }
+
+    #[test]
+    fn test_read_u16_wraps_around_the_address_space() {
+        let mut bus = Bus::new();
+        bus.ram_mut()[0xFFFF] = 0x34;
+        bus.ram_mut()[0x0000] = 0x12;
+        assert_eq!(0x1234, bus.mem_read_u16(0xFFFF));
+        bus.mem_write_u16(0xFFFF, 0xABCD);
+        assert_eq!(0xCD, bus.ram()[0xFFFF]);
+        assert_eq!(0xAB, bus.ram()[0x0000]);
+    }
+
+    #[test]
+    fn test_unmapped_reads_return_the_open_bus() {
+        let mut bus = Bus::new();
+        bus.mem_write_u8(0x4019, 0x12);
+        bus.mem_write_u8(0x0000, 0x5A);
+        assert_eq!(0x5A, bus.mem_read_u8(0x4019));
+        assert_eq!(0x5A, bus.peek_u8(0x4019));
+        // write-only APU register
+        bus.mem_write_u8(0x4000, 0x3C);
+        assert_eq!(0x3C, bus.mem_read_u8(0x4000));
+
+        let mut rom = b"NES\x1A\x01\x01".to_vec();
+        rom.resize(16, 0);
+        rom.resize(16 + 0x4000 + 0x2000, 0xEA);
+        bus.set_cartridge(Some(Cartridge::new(&rom).unwrap()));
+        bus.mem_write_u8(0x5000, 0x77);
+        assert_eq!(0xEA, bus.mem_read_u8(0x8000));
+        assert_eq!(0xEA, bus.mem_read_u8(0x5000));
+        assert_eq!(0, bus.ram()[0x5000]);
     }
 }
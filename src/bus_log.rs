@@ -0,0 +1,258 @@
+//! Recorder of the CPU bus accesses, to diagnose timing issues like how a
+//! game polls $2002 or $4016. The events go to a bounded ring buffer and
+//! can be exported as CSV to be graphed.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::debugger::Access;
+
+/// About 3 frames of CPU accesses.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+const CSV_HEADER: &str = "cycle,address,value,access,kind";
+
+/// What the CPU accesses the bus for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    OpcodeFetch,
+    /// Instruction bytes following the opcode.
+    Operand,
+    /// Effective address, pointer and vector accesses.
+    Data,
+    /// Accesses whose value is discarded, like the first write of a
+    /// read-modify-write instruction.
+    Dummy,
+    Stack,
+    /// Sprite DMA copying a page to OAMDATA and DMC sample fetches, the
+    /// CPU is halted.
+    Dma,
+}
+
+impl AccessKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AccessKind::OpcodeFetch => "opcode_fetch",
+            AccessKind::Operand => "operand",
+            AccessKind::Data => "data",
+            AccessKind::Dummy => "dummy",
+            AccessKind::Stack => "stack",
+            AccessKind::Dma => "dma",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusEvent {
+    /// CPU cycle since power on.
+    pub cycle: u64,
+    pub addr: u16,
+    pub value: u8,
+    pub access: Access,
+    pub kind: AccessKind,
+}
+
+/// Keeps the last `capacity` accesses matching the address filters.
+pub struct BusLog {
+    capacity: usize,
+    filters: Vec<RangeInclusive<u16>>,
+    events: VecDeque<BusEvent>,
+}
+
+impl Default for BusLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl BusLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            filters: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Only records the accesses in the filtered ranges, everything is
+    /// recorded while there is no filter.
+    pub fn add_filter(&mut self, range: RangeInclusive<u16>) {
+        self.filters.push(range);
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &BusEvent> + '_ {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn record(&mut self, event: BusEvent) {
+        if !self.filters.is_empty() && !self.filters.iter().any(|range| range.contains(&event.addr))
+        {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// One line per event, oldest first, after a `cycle,address,value,access,kind`
+    /// header. The address and the value are in hexadecimal.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "{CSV_HEADER}")?;
+        for event in &self.events {
+            let access = match event.access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            writeln!(
+                writer,
+                "{},{:04X},{:02X},{},{}",
+                event.cycle,
+                event.addr,
+                event.value,
+                access,
+                event.kind.name()
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = Vec::new();
+        self.write_csv(&mut csv).unwrap();
+        String::from_utf8(csv).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Nes;
+
+    fn read(cycle: u64, addr: u16) -> BusEvent {
+        BusEvent {
+            cycle,
+            addr,
+            value: 0,
+            access: Access::Read,
+            kind: AccessKind::Data,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut log = BusLog::new(3);
+        for cycle in 0..5 {
+            log.record(read(cycle, 0x2002));
+        }
+        assert_eq!(
+            vec![2, 3, 4],
+            log.events().map(|event| event.cycle).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_address_filters() {
+        let mut log = BusLog::default();
+        log.add_filter(0x2002..=0x2002);
+        log.add_filter(0x4016..=0x4017);
+        for (cycle, addr) in [0x2002, 0x2007, 0x4016, 0x0000, 0x4017]
+            .into_iter()
+            .enumerate()
+        {
+            log.record(read(cycle as u64, addr));
+        }
+        assert_eq!(
+            vec![0x2002, 0x4016, 0x4017],
+            log.events().map(|event| event.addr).collect::<Vec<_>>()
+        );
+        log.clear_filters();
+        log.record(read(5, 0x0000));
+        assert_eq!(4, log.len());
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let mut log = BusLog::default();
+        log.record(BusEvent {
+            cycle: 7,
+            addr: 0x8000,
+            value: 0xAD,
+            access: Access::Read,
+            kind: AccessKind::OpcodeFetch,
+        });
+        log.record(BusEvent {
+            cycle: 12,
+            addr: 0x01FD,
+            value: 0x80,
+            access: Access::Write,
+            kind: AccessKind::Stack,
+        });
+        assert_eq!(
+            "cycle,address,value,access,kind\n\
+             7,8000,AD,read,opcode_fetch\n\
+             12,01FD,80,write,stack\n",
+            log.to_csv()
+        );
+    }
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 7] = [
+        0xAD, 0x02, 0x20, // LDA $2002
+        0x48,             // PHA
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+
+    #[test]
+    fn test_cpu_accesses_are_labeled() {
+        let mut nes = Nes::new();
+        nes.load(&PROGRAM, 0x8000);
+        nes.set_bus_log(Some(BusLog::default()));
+        nes.run();
+        let events: Vec<_> = nes
+            .bus_log()
+            .unwrap()
+            .events()
+            .map(|event| (event.cycle, event.addr, event.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                (7, 0x8000, AccessKind::OpcodeFetch),
+                (8, 0x8001, AccessKind::Operand),
+                (9, 0x8002, AccessKind::Operand),
+                (10, 0x2002, AccessKind::Data),
+            ],
+            events
+        );
+
+        let log = nes.bus_log_mut().unwrap();
+        log.clear();
+        log.add_filter(0x0100..=0x01FF);
+        nes.run();
+        let event = *nes.bus_log().unwrap().events().next().unwrap();
+        assert_eq!(
+            (0x01FD, Access::Write, AccessKind::Stack),
+            (event.addr, event.access, event.kind)
+        );
+    }
+}
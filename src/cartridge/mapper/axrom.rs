@@ -0,0 +1,93 @@
+use super::{bank_offset, read_bank, ChrMemory, Mapper, PRG_ROM_START};
+use crate::cartridge::Mirroring;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const PRG_WINDOW_SIZE: usize = 0x8000;
+const CHR_WINDOW_SIZE: usize = 0x2000;
+const PRG_BANK_MASK: u8 = 0b0000_0111;
+const NAMETABLE_SELECT: u8 = 0b0001_0000;
+
+/// Mapper 7, switchable 32KiB PRG bank and single screen mirroring.
+/// https://www.nesdev.org/wiki/AxROM
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(prg_rom: Vec<u8>, chr: ChrMemory) -> Self {
+        Self {
+            prg_rom,
+            chr,
+            bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        (addr >= PRG_ROM_START).then(|| {
+            self.prg_rom[bank_offset(self.bank, PRG_WINDOW_SIZE, addr, self.prg_rom.len())]
+        })
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            self.bank = usize::from(data & PRG_BANK_MASK);
+            self.mirroring = if data & NAMETABLE_SELECT == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_WINDOW_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(0, CHR_WINDOW_SIZE, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Axrom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.chr.save_state(writer);
+        writer.write_u32(self.bank as u32);
+        self.mirroring.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(reader)?;
+        self.bank = read_bank(reader, usize::from(PRG_BANK_MASK))?;
+        self.mirroring.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_header;
+
+    #[test]
+    fn test_axrom_bank_and_mirroring() {
+        let header = test_header(7, 0);
+        let prg_rom = (0..4 * PRG_WINDOW_SIZE)
+            .map(|i| (i / PRG_WINDOW_SIZE) as u8)
+            .collect();
+        let mut axrom = Axrom::new(prg_rom, ChrMemory::new(&header, vec![]));
+        assert_eq!(Mirroring::SingleScreenLower, axrom.mirroring());
+        axrom.cpu_write(0x8000, NAMETABLE_SELECT | 2);
+        assert_eq!(Some(2), axrom.cpu_read(0xFFFC));
+        assert_eq!(Mirroring::SingleScreenUpper, axrom.mirroring());
+    }
+}
@@ -0,0 +1,93 @@
+use super::{has_bus_conflicts, read_bank, ChrMemory, Mapper, PRG_ROM_START};
+use crate::cartridge::{Header, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const CHR_WINDOW_SIZE: usize = 0x2000;
+
+/// Mapper 3, fixed PRG and switchable 8KiB CHR bank.
+/// https://www.nesdev.org/wiki/CNROM
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    mirroring: Mirroring,
+    chr_bank: usize,
+    has_bus_conflicts: bool,
+}
+
+impl Cnrom {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr: ChrMemory) -> Self {
+        Self {
+            prg_rom,
+            chr,
+            mirroring: header.mirroring,
+            chr_bank: 0,
+            has_bus_conflicts: has_bus_conflicts(header),
+        }
+    }
+
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[usize::from(addr - PRG_ROM_START) % self.prg_rom.len()]
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        (addr >= PRG_ROM_START).then(|| self.read_prg(addr))
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            let data = if self.has_bus_conflicts {
+                data & self.read_prg(addr)
+            } else {
+                data
+            };
+            self.chr_bank = usize::from(data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank, CHR_WINDOW_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(self.chr_bank, CHR_WINDOW_SIZE, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Cnrom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.chr.save_state(writer);
+        writer.write_u32(self.chr_bank as u32);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(reader)?;
+        self.chr_bank = read_bank(reader, usize::from(u8::MAX))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_header;
+
+    #[test]
+    fn test_cnrom_chr_bank_switching() {
+        let header = test_header(3, 0);
+        let chr_rom = (0..4 * CHR_WINDOW_SIZE)
+            .map(|i| (i / CHR_WINDOW_SIZE) as u8)
+            .collect();
+        let mut cnrom = Cnrom::new(&header, vec![0; 0x8000], ChrMemory::new(&header, chr_rom));
+        assert_eq!(0, cnrom.ppu_read(0x0000));
+        cnrom.cpu_write(0x8000, 3);
+        assert_eq!(3, cnrom.ppu_read(0x1FFF));
+        cnrom.ppu_write(0x1FFF, 0);
+        assert_eq!(3, cnrom.ppu_read(0x1FFF));
+    }
+}
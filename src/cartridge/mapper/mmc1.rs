@@ -0,0 +1,348 @@
+use super::{bank_offset, ChrMemory, Mapper, PRG_RAM_START, PRG_ROM_START};
+use crate::cartridge::{Header, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const PRG_WINDOW_SIZE: usize = 0x4000;
+const CHR_WINDOW_SIZE: usize = 0x1000;
+const PRG_RAM_WINDOW_SIZE: usize = 0x2000;
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+/// Room for the bank fixed at $C000 in the power-on PRG mode.
+pub(super) const MIN_PRG_ROM_SIZE: usize = PRG_WINDOW_SIZE;
+
+const SHIFT_REGISTER_RESET: u8 = 0b1000_0000;
+const SHIFT_REGISTER_INITIAL: u8 = 0b0001_0000;
+const CONTROL_INITIAL: u8 = 0b0000_1100;
+const CONTROL_MIRRORING: u8 = 0b0000_0011;
+const CONTROL_PRG_MODE: u8 = 0b0000_1100;
+const CONTROL_CHR_4K_MODE: u8 = 0b0001_0000;
+const PRG_BANK_MASK: u8 = 0b0000_1111;
+const PRG_RAM_DISABLE: u8 = 0b0001_0000;
+const CHR_PRG_OUTER_BANK: u8 = 0b0001_0000;
+const CHR_PRG_RAM_BANK: u8 = 0b0000_1100;
+
+/// Mapper 1, banks are selected through a 5 bits serial register.
+/// The SNROM/SOROM/SUROM/SXROM variants reuse the CHR bank registers
+/// to select the 256KiB PRG outer bank and the 8KiB PRG RAM bank.
+/// https://www.nesdev.org/wiki/MMC1
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: ChrMemory,
+    has_battery: bool,
+    shift_register: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    cpu_cycle: u64,
+    /// The serial port ignores the writes on the cycle following a write,
+    /// like the second write of `INC $FFFF`.
+    last_write_cycle: Option<u64>,
+}
+
+impl Mmc1 {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr: ChrMemory) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            has_battery: header.has_battery,
+            shift_register: SHIFT_REGISTER_INITIAL,
+            control: CONTROL_INITIAL,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            cpu_cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    fn write_serial(&mut self, addr: u16, data: u8) {
+        let is_consecutive =
+            matches!(self.last_write_cycle, Some(cycle) if cycle + 1 == self.cpu_cycle);
+        self.last_write_cycle = Some(self.cpu_cycle);
+        if is_consecutive {
+            return;
+        }
+        if data & SHIFT_REGISTER_RESET != 0 {
+            self.shift_register = SHIFT_REGISTER_INITIAL;
+            self.control |= CONTROL_INITIAL;
+            return;
+        }
+        let is_last_write = self.shift_register & 1 == 1;
+        self.shift_register = (self.shift_register >> 1) | ((data & 1) << 4);
+        if is_last_write {
+            let value = self.shift_register;
+            match addr {
+                0x8000..=0x9FFF => self.control = value,
+                0xA000..=0xBFFF => self.chr_bank_0 = value,
+                0xC000..=0xDFFF => self.chr_bank_1 = value,
+                _ => self.prg_bank = value,
+            }
+            self.shift_register = SHIFT_REGISTER_INITIAL;
+        }
+    }
+
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            usize::from(self.chr_bank_0 & CHR_PRG_OUTER_BANK != 0) * PRG_OUTER_BANK_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let bank = usize::from(self.prg_bank & PRG_BANK_MASK);
+        let last_bank = (PRG_OUTER_BANK_SIZE.min(self.prg_rom.len()) / PRG_WINDOW_SIZE) - 1;
+        let is_upper_window = addr >= 0xC000;
+        match (self.control & CONTROL_PRG_MODE) >> 2 {
+            0 | 1 => (bank & !1) | usize::from(is_upper_window),
+            2 if is_upper_window => bank,
+            2 => 0,
+            _ if is_upper_window => last_bank,
+            _ => bank,
+        }
+    }
+
+    fn read_prg(&self, addr: u16) -> u8 {
+        let offset = self.prg_outer_bank()
+            + bank_offset(
+                self.prg_bank(addr),
+                PRG_WINDOW_SIZE,
+                addr,
+                PRG_OUTER_BANK_SIZE,
+            );
+        self.prg_rom[offset % self.prg_rom.len()]
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        if self.prg_ram.is_empty() || self.prg_bank & PRG_RAM_DISABLE != 0 {
+            return None;
+        }
+        let bank = usize::from((self.chr_bank_0 & CHR_PRG_RAM_BANK) >> 2);
+        Some(bank_offset(
+            bank,
+            PRG_RAM_WINDOW_SIZE,
+            addr,
+            self.prg_ram.len(),
+        ))
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        if self.control & CONTROL_CHR_4K_MODE != 0 {
+            usize::from(if addr < 0x1000 {
+                self.chr_bank_0
+            } else {
+                self.chr_bank_1
+            })
+        } else {
+            usize::from(self.chr_bank_0 & !1) | usize::from(addr >= 0x1000)
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=0xFFFF => Some(self.read_prg(addr)),
+            PRG_RAM_START..=0x7FFF => self.prg_ram_offset(addr).map(|offset| self.prg_ram[offset]),
+            _ => None,
+        }
+    }
+
+    fn set_cpu_cycle(&mut self, cycle: u64) {
+        self.cpu_cycle = cycle;
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=0xFFFF => self.write_serial(addr, data),
+            PRG_RAM_START..=0x7FFF => {
+                if let Some(offset) = self.prg_ram_offset(addr) {
+                    self.prg_ram[offset] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank(addr), CHR_WINDOW_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr
+            .write(self.chr_bank(addr), CHR_WINDOW_SIZE, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & CONTROL_MIRRORING {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then(|| &self.prg_ram[..])
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+impl Snapshot for Mmc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        self.chr.save_state(writer);
+        writer.write_u8(self.shift_register);
+        writer.write_u8(self.control);
+        writer.write_u8(self.chr_bank_0);
+        writer.write_u8(self.chr_bank_1);
+        writer.write_u8(self.prg_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.prg_ram)?;
+        self.chr.load_state(reader)?;
+        self.shift_register = reader.read_u8()?;
+        self.control = reader.read_u8()?;
+        self.chr_bank_0 = reader.read_u8()?;
+        self.chr_bank_1 = reader.read_u8()?;
+        self.prg_bank = reader.read_u8()?;
+        self.last_write_cycle = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_header;
+
+    fn banked_prg_rom(banks: usize) -> Vec<u8> {
+        (0..banks * PRG_WINDOW_SIZE)
+            .map(|i| (i / PRG_WINDOW_SIZE) as u8)
+            .collect()
+    }
+
+    fn write_register(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.cpu_write(addr, value >> bit);
+        }
+    }
+
+    #[test]
+    fn test_mmc1_power_on_state() {
+        let header = test_header(1, 0);
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        assert_eq!(Some(0), mmc1.cpu_read(0x8000));
+        assert_eq!(Some(7), mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc1_serial_write_and_reset() {
+        let header = test_header(1, 0);
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        mmc1.cpu_write(0xE000, 1);
+        mmc1.cpu_write(0xE000, 1);
+        mmc1.cpu_write(0xE000, SHIFT_REGISTER_RESET);
+        write_register(&mut mmc1, 0xE000, 3);
+        assert_eq!(Some(3), mmc1.cpu_read(0x8000));
+        assert_eq!(Some(7), mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc1_ignores_consecutive_writes() {
+        let header = test_header(1, 0);
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        mmc1.set_cpu_cycle(10);
+        mmc1.cpu_write(0xE000, 1);
+        // the dummy write of a read-modify-write instruction
+        mmc1.set_cpu_cycle(11);
+        mmc1.cpu_write(0xE000, 1);
+        for bit in 1..5 {
+            mmc1.set_cpu_cycle(11 + 2 * bit);
+            mmc1.cpu_write(0xE000, 3 >> bit);
+        }
+        assert_eq!(Some(3), mmc1.cpu_read(0x8000));
+    }
+
+    #[test]
+    fn test_mmc1_prg_modes() {
+        let header = test_header(1, 0);
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        write_register(&mut mmc1, 0xE000, 5);
+
+        write_register(&mut mmc1, 0x8000, 0b0_1000);
+        assert_eq!(Some(0), mmc1.cpu_read(0x8000));
+        assert_eq!(Some(5), mmc1.cpu_read(0xC000));
+
+        write_register(&mut mmc1, 0x8000, 0b0_0000);
+        assert_eq!(Some(4), mmc1.cpu_read(0x8000));
+        assert_eq!(Some(5), mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc1_chr_modes_and_mirroring() {
+        let header = test_header(1, 0);
+        let chr_rom = (0..8 * CHR_WINDOW_SIZE)
+            .map(|i| (i / CHR_WINDOW_SIZE) as u8)
+            .collect();
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(2), ChrMemory::new(&header, chr_rom));
+        write_register(&mut mmc1, 0xA000, 5);
+        write_register(&mut mmc1, 0xC000, 2);
+        assert_eq!(4, mmc1.ppu_read(0x0000));
+        assert_eq!(5, mmc1.ppu_read(0x1000));
+
+        write_register(&mut mmc1, 0x8000, CONTROL_CHR_4K_MODE | 2);
+        assert_eq!(5, mmc1.ppu_read(0x0000));
+        assert_eq!(2, mmc1.ppu_read(0x1000));
+        assert_eq!(Mirroring::Vertical, mmc1.mirroring());
+    }
+
+    #[test]
+    fn test_sxrom_outer_bank_and_prg_ram_banks() {
+        let mut header = test_header(1, 0);
+        header.prg_nvram_size = 0x8000;
+        header.has_battery = true;
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(32), ChrMemory::new(&header, vec![]));
+        assert_eq!(Some(15), mmc1.cpu_read(0xC000));
+
+        write_register(&mut mmc1, 0xA000, CHR_PRG_OUTER_BANK | 0b0100);
+        assert_eq!(Some(16), mmc1.cpu_read(0x8000));
+        assert_eq!(Some(31), mmc1.cpu_read(0xC000));
+
+        mmc1.cpu_write(0x6000, 42);
+        write_register(&mut mmc1, 0xA000, 0);
+        assert_eq!(Some(0), mmc1.cpu_read(0x6000));
+        assert_eq!(42, mmc1.save_ram().unwrap()[PRG_RAM_WINDOW_SIZE]);
+
+        write_register(&mut mmc1, 0xE000, PRG_RAM_DISABLE);
+        assert_eq!(None, mmc1.cpu_read(0x6000));
+    }
+
+    #[test]
+    fn test_state_restores_banks_and_ram() {
+        let mut header = test_header(1, 0);
+        header.prg_ram_size = 0x2000;
+        let mut mmc1 = Mmc1::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        write_register(&mut mmc1, 0xE000, 2);
+        mmc1.cpu_write(0x6000, 42);
+        mmc1.ppu_write(0x0000, 7);
+        let mut writer = StateWriter::new();
+        mmc1.save_state(&mut writer);
+        let state = writer.into_inner();
+
+        write_register(&mut mmc1, 0xE000, 5);
+        mmc1.cpu_write(0x6000, 0);
+        mmc1.ppu_write(0x0000, 0);
+        mmc1.load_state(&mut StateReader::new(&state)).unwrap();
+        assert_eq!(Some(2), mmc1.cpu_read(0x8000));
+        assert_eq!(Some(42), mmc1.cpu_read(0x6000));
+        assert_eq!(7, mmc1.ppu_read(0x0000));
+    }
+}
@@ -0,0 +1,357 @@
+use super::{bank_offset, ChrMemory, Mapper, PRG_RAM_START, PRG_ROM_START};
+use crate::cartridge::{Header, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const PRG_WINDOW_SIZE: usize = 0x2000;
+const CHR_WINDOW_SIZE: usize = 0x0400;
+/// Room for the second-last and last banks, both fixed.
+pub(super) const MIN_PRG_ROM_SIZE: usize = 2 * PRG_WINDOW_SIZE;
+
+const BANK_SELECT_REGISTER: u8 = 0b0000_0111;
+const BANK_SELECT_PRG_MODE: u8 = 0b0100_0000;
+const BANK_SELECT_CHR_INVERSION: u8 = 0b1000_0000;
+const PRG_RAM_ENABLE: u8 = 0b1000_0000;
+const PRG_RAM_WRITE_PROTECT: u8 = 0b0100_0000;
+const PPU_A12: u16 = 0x1000;
+/// Number of consecutive PPU accesses with A12 low before a rising edge
+/// clocks the counter, this filters out the sprite fetches in a scanline.
+const A12_LOW_FILTER: u8 = 3;
+/// NES 2.0 submapper of the MMC3A boards.
+const OLD_IRQ_SUBMAPPER: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IrqBehavior {
+    /// MMC3A : the IRQ is raised only when the counter reaches 0 by being
+    /// decremented or reloaded after a write to $C001.
+    Old,
+    /// MMC3B/C : the IRQ is raised every time the counter is clocked at 0.
+    New,
+}
+
+/// Mapper 4, 8KiB PRG banks, 1/2KiB CHR banks and a scanline counter
+/// clocked by the PPU A12 line.
+/// https://www.nesdev.org/wiki/MMC3
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: ChrMemory,
+    has_battery: bool,
+    is_four_screen: bool,
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_protect: u8,
+    irq_behavior: IrqBehavior,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_low_count: u8,
+}
+
+impl Mmc3 {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr: ChrMemory) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            has_battery: header.has_battery,
+            is_four_screen: header.mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: header.mirroring,
+            prg_ram_protect: PRG_RAM_ENABLE,
+            irq_behavior: if header.submapper == OLD_IRQ_SUBMAPPER {
+                IrqBehavior::Old
+            } else {
+                IrqBehavior::New
+            },
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_low_count: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        let is_even = addr & 1 == 0;
+        match (addr, is_even) {
+            (0x8000..=0x9FFF, true) => self.bank_select = data,
+            (0x8000..=0x9FFF, false) => {
+                self.bank_registers[usize::from(self.bank_select & BANK_SELECT_REGISTER)] = data
+            }
+            (0xA000..=0xBFFF, true) if !self.is_four_screen => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            (0xA000..=0xBFFF, true) => {}
+            (0xA000..=0xBFFF, false) => self.prg_ram_protect = data,
+            (0xC000..=0xDFFF, true) => self.irq_latch = data,
+            (0xC000..=0xDFFF, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (_, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (_, false) => self.irq_enabled = true,
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let was_reloaded = self.irq_reload;
+        let previous_counter = self.irq_counter;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        let should_raise = match self.irq_behavior {
+            IrqBehavior::New => self.irq_counter == 0,
+            IrqBehavior::Old => self.irq_counter == 0 && (previous_counter != 0 || was_reloaded),
+        };
+        if should_raise && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let second_last_bank = self.prg_rom.len() / PRG_WINDOW_SIZE - 2;
+        let is_swapped = self.bank_select & BANK_SELECT_PRG_MODE != 0;
+        match (addr, is_swapped) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => {
+                usize::from(self.bank_registers[6])
+            }
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last_bank,
+            (0xA000..=0xBFFF, _) => usize::from(self.bank_registers[7]),
+            _ => second_last_bank + 1,
+        }
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        let addr = if self.bank_select & BANK_SELECT_CHR_INVERSION != 0 {
+            addr ^ PPU_A12
+        } else {
+            addr
+        };
+        let slot = usize::from(addr / CHR_WINDOW_SIZE as u16);
+        match slot {
+            0..=3 => usize::from(self.bank_registers[slot / 2] & !1) | (slot & 1),
+            _ => usize::from(self.bank_registers[slot - 2]),
+        }
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        (!self.prg_ram.is_empty() && self.prg_ram_protect & PRG_RAM_ENABLE != 0)
+            .then(|| usize::from(addr - PRG_RAM_START) % self.prg_ram.len())
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=0xFFFF => {
+                let bank = self.prg_bank(addr);
+                Some(self.prg_rom[bank_offset(bank, PRG_WINDOW_SIZE, addr, self.prg_rom.len())])
+            }
+            PRG_RAM_START..=0x7FFF => self.prg_ram_offset(addr).map(|offset| self.prg_ram[offset]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=0xFFFF => self.write_register(addr, data),
+            PRG_RAM_START..=0x7FFF if self.prg_ram_protect & PRG_RAM_WRITE_PROTECT == 0 => {
+                if let Some(offset) = self.prg_ram_offset(addr) {
+                    self.prg_ram[offset] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.ppu_address(addr);
+        self.chr.read(self.chr_bank(addr), CHR_WINDOW_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.ppu_address(addr);
+        self.chr
+            .write(self.chr_bank(addr), CHR_WINDOW_SIZE, addr, data)
+    }
+
+    fn ppu_address(&mut self, addr: u16) {
+        if addr & PPU_A12 == 0 {
+            self.a12_low_count = self.a12_low_count.saturating_add(1);
+            return;
+        }
+        if self.a12_low_count >= A12_LOW_FILTER {
+            self.clock_irq_counter();
+        }
+        self.a12_low_count = 0;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then(|| &self.prg_ram[..])
+    }
+
+    fn load_save_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+impl Snapshot for Mmc3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        self.chr.save_state(writer);
+        writer.write_u8(self.bank_select);
+        writer.write_array(&self.bank_registers);
+        self.mirroring.save_state(writer);
+        writer.write_u8(self.prg_ram_protect);
+        writer.write_u8(self.irq_latch);
+        writer.write_u8(self.irq_counter);
+        writer.write_bool(self.irq_reload);
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.irq_pending);
+        writer.write_u8(self.a12_low_count);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.prg_ram)?;
+        self.chr.load_state(reader)?;
+        self.bank_select = reader.read_u8()?;
+        self.bank_registers = reader.read_array()?;
+        self.mirroring.load_state(reader)?;
+        self.prg_ram_protect = reader.read_u8()?;
+        self.irq_latch = reader.read_u8()?;
+        self.irq_counter = reader.read_u8()?;
+        self.irq_reload = reader.read_bool()?;
+        self.irq_enabled = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+        self.a12_low_count = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_header;
+
+    fn new_mmc3(submapper: u8) -> Mmc3 {
+        let mut header = test_header(4, submapper);
+        header.prg_ram_size = 0x2000;
+        let prg_rom = (0..16 * PRG_WINDOW_SIZE)
+            .map(|i| (i / PRG_WINDOW_SIZE) as u8)
+            .collect();
+        let chr_rom = (0..64 * CHR_WINDOW_SIZE)
+            .map(|i| (i / CHR_WINDOW_SIZE) as u8)
+            .collect();
+        Mmc3::new(&header, prg_rom, ChrMemory::new(&header, chr_rom))
+    }
+
+    fn scanline(mmc3: &mut Mmc3) {
+        for _ in 0..A12_LOW_FILTER {
+            mmc3.ppu_address(0x2000);
+        }
+        mmc3.ppu_read(0x1000);
+        mmc3.ppu_read(0x1008);
+    }
+
+    #[test]
+    fn test_mmc3_prg_banking() {
+        let mut mmc3 = new_mmc3(0);
+        mmc3.cpu_write(0x8000, 6);
+        mmc3.cpu_write(0x8001, 3);
+        mmc3.cpu_write(0x8000, 7);
+        mmc3.cpu_write(0x8001, 4);
+        assert_eq!(Some(3), mmc3.cpu_read(0x8000));
+        assert_eq!(Some(4), mmc3.cpu_read(0xA000));
+        assert_eq!(Some(14), mmc3.cpu_read(0xC000));
+        assert_eq!(Some(15), mmc3.cpu_read(0xE000));
+
+        mmc3.cpu_write(0x8000, BANK_SELECT_PRG_MODE);
+        assert_eq!(Some(14), mmc3.cpu_read(0x8000));
+        assert_eq!(Some(3), mmc3.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc3_chr_banking_and_inversion() {
+        let mut mmc3 = new_mmc3(0);
+        mmc3.cpu_write(0x8000, 0);
+        mmc3.cpu_write(0x8001, 9);
+        mmc3.cpu_write(0x8000, 5);
+        mmc3.cpu_write(0x8001, 42);
+        assert_eq!(8, mmc3.ppu_read(0x0000));
+        assert_eq!(9, mmc3.ppu_read(0x0400));
+        assert_eq!(42, mmc3.ppu_read(0x1C00));
+
+        mmc3.cpu_write(0x8000, BANK_SELECT_CHR_INVERSION);
+        assert_eq!(8, mmc3.ppu_read(0x1000));
+        assert_eq!(42, mmc3.ppu_read(0x0C00));
+    }
+
+    #[test]
+    fn test_mmc3_mirroring_and_prg_ram_protect() {
+        let mut mmc3 = new_mmc3(0);
+        mmc3.cpu_write(0xA000, 1);
+        assert_eq!(Mirroring::Horizontal, mmc3.mirroring());
+        mmc3.cpu_write(0x6000, 42);
+        mmc3.cpu_write(0xA001, PRG_RAM_ENABLE | PRG_RAM_WRITE_PROTECT);
+        mmc3.cpu_write(0x6000, 0);
+        assert_eq!(Some(42), mmc3.cpu_read(0x6000));
+        mmc3.cpu_write(0xA001, 0);
+        assert_eq!(None, mmc3.cpu_read(0x6000));
+    }
+
+    #[test]
+    fn test_mmc3_scanline_irq() {
+        let mut mmc3 = new_mmc3(0);
+        mmc3.cpu_write(0xC000, 2);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+        scanline(&mut mmc3);
+        scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mut mmc3);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xE000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn test_mmc3_irq_with_zero_latch() {
+        let mut new_mmc3_board = new_mmc3(0);
+        let mut old_mmc3_board = new_mmc3(OLD_IRQ_SUBMAPPER);
+        for mmc3 in [&mut new_mmc3_board, &mut old_mmc3_board] {
+            mmc3.cpu_write(0xC000, 0);
+            mmc3.cpu_write(0xE001, 0);
+            scanline(mmc3);
+        }
+        assert!(new_mmc3_board.irq());
+        assert!(!old_mmc3_board.irq());
+
+        old_mmc3_board.cpu_write(0xC001, 0);
+        scanline(&mut old_mmc3_board);
+        assert!(old_mmc3_board.irq());
+    }
+}
@@ -0,0 +1,176 @@
+mod axrom;
+mod cnrom;
+mod mmc1;
+mod mmc3;
+mod nrom;
+mod uxrom;
+
+use super::{CartridgeError, Header, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const PRG_RAM_START: u16 = 0x6000;
+pub const PRG_ROM_START: u16 = 0x8000;
+const CHR_RAM_DEFAULT_SIZE: usize = 0x2000;
+
+/// Cartridge board logic sitting between the consoles buses and the
+/// cartridge chips.
+/// https://www.nesdev.org/wiki/Mapper
+/// The snapshot holds the registers and the RAM chips, not the ROM.
+pub trait Mapper: Snapshot {
+    /// CPU read in $4020-$FFFF, `None` when the board does not drive the bus.
+    fn cpu_read(&mut self, addr: u16) -> Option<u8>;
+
+    /// CPU write in $4020-$FFFF.
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// CPU cycle of the next `cpu_write`, for boards sensitive to the write timing.
+    fn set_cpu_cycle(&mut self, _cycle: u64) {}
+
+    /// PPU read in the pattern tables ($0000-$1FFF).
+    fn ppu_read(&mut self, addr: u16) -> u8;
+
+    /// PPU write in the pattern tables ($0000-$1FFF).
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    /// Address driven on the PPU bus, for boards snooping it.
+    fn ppu_address(&mut self, _addr: u16) {}
+
+    /// Level of the IRQ line of the board.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// Battery backed PRG RAM content, to be persisted between sessions.
+    fn save_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn load_save_ram(&mut self, _data: &[u8]) {}
+}
+
+/// Board wired to both the CPU and the PPU buses.
+pub type SharedMapper = Rc<RefCell<Box<dyn Mapper>>>;
+
+pub fn new(
+    header: &Header,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+) -> Result<Box<dyn Mapper>, CartridgeError> {
+    if prg_rom.len() < min_prg_rom_size(header.mapper) {
+        return Err(CartridgeError::InvalidRomSize);
+    }
+    let chr = ChrMemory::new(header, chr_rom);
+    match header.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(header, prg_rom, chr))),
+        1 => Ok(Box::new(mmc1::Mmc1::new(header, prg_rom, chr))),
+        4 => Ok(Box::new(mmc3::Mmc3::new(header, prg_rom, chr))),
+        2 => Ok(Box::new(uxrom::Uxrom::new(header, prg_rom, chr))),
+        3 => Ok(Box::new(cnrom::Cnrom::new(header, prg_rom, chr))),
+        7 => Ok(Box::new(axrom::Axrom::new(prg_rom, chr))),
+        mapper => Err(CartridgeError::UnsupportedMapper(mapper)),
+    }
+}
+
+/// Smallest PRG ROM holding the banks a board indexes from the end of the chip.
+fn min_prg_rom_size(mapper: u16) -> usize {
+    match mapper {
+        1 => mmc1::MIN_PRG_ROM_SIZE,
+        2 => uxrom::MIN_PRG_ROM_SIZE,
+        4 => mmc3::MIN_PRG_ROM_SIZE,
+        _ => 0,
+    }
+}
+
+/// Offset in a chip of `len` bytes of the byte `addr` in the window of
+/// `window_size` bytes switched to `bank`.
+fn bank_offset(bank: usize, window_size: usize, addr: u16, len: usize) -> usize {
+    (bank * window_size + usize::from(addr) % window_size) % len
+}
+
+/// Bank register of a discrete board saved as a `u32`, `max` is the
+/// largest bank the board can latch from a write.
+fn read_bank(reader: &mut StateReader, max: usize) -> Result<usize, StateError> {
+    match usize::try_from(reader.read_u32()?) {
+        Ok(bank) if bank <= max => Ok(bank),
+        _ => Err(StateError::InvalidValue("bank")),
+    }
+}
+
+/// NES 2.0 submapper 2 of discrete boards : the written value is ANDed with
+/// the ROM byte at the same address.
+fn has_bus_conflicts(header: &Header) -> bool {
+    header.submapper == 2
+}
+
+/// CHR ROM, or CHR RAM when the board has no ROM.
+struct ChrMemory {
+    data: Vec<u8>,
+    is_ram: bool,
+}
+
+impl ChrMemory {
+    fn new(header: &Header, chr_rom: Vec<u8>) -> Self {
+        if chr_rom.is_empty() {
+            let size = header.chr_ram_size + header.chr_nvram_size;
+            Self {
+                data: vec![0; size.max(CHR_RAM_DEFAULT_SIZE)],
+                is_ram: true,
+            }
+        } else {
+            Self {
+                data: chr_rom,
+                is_ram: false,
+            }
+        }
+    }
+
+    fn read(&self, bank: usize, window_size: usize, addr: u16) -> u8 {
+        self.data[bank_offset(bank, window_size, addr, self.data.len())]
+    }
+
+    fn write(&mut self, bank: usize, window_size: usize, addr: u16, data: u8) {
+        if self.is_ram {
+            let offset = bank_offset(bank, window_size, addr, self.data.len());
+            self.data[offset] = data;
+        }
+    }
+}
+
+impl Snapshot for ChrMemory {
+    fn save_state(&self, writer: &mut StateWriter) {
+        if self.is_ram {
+            writer.write_bytes(&self.data);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if self.is_ram {
+            reader.read_bytes_into(&mut self.data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_header(mapper: u16, submapper: u8) -> Header {
+    Header {
+        format: super::Format::Nes2,
+        prg_rom_size: 0,
+        chr_rom_size: 0,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+        mapper,
+        submapper,
+        mirroring: Mirroring::Horizontal,
+        has_battery: false,
+        has_trainer: false,
+        console_type: super::ConsoleType::Nes,
+        timing: super::Timing::Ntsc,
+    }
+}
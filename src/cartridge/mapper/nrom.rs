@@ -0,0 +1,100 @@
+use super::{ChrMemory, Mapper, PRG_RAM_START, PRG_ROM_START};
+use crate::cartridge::{Header, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const CHR_WINDOW_SIZE: usize = 0x2000;
+
+/// Mapper 0, no bank switching.
+/// https://www.nesdev.org/wiki/NROM
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: ChrMemory,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr: ChrMemory) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            mirroring: header.mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=0xFFFF => {
+                let offset = usize::from(addr - PRG_ROM_START) % self.prg_rom.len();
+                Some(self.prg_rom[offset])
+            }
+            PRG_RAM_START..=0x7FFF if !self.prg_ram.is_empty() => {
+                let offset = usize::from(addr - PRG_RAM_START) % self.prg_ram.len();
+                Some(self.prg_ram[offset])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if (PRG_RAM_START..PRG_ROM_START).contains(&addr) && !self.prg_ram.is_empty() {
+            let offset = usize::from(addr - PRG_RAM_START) % self.prg_ram.len();
+            self.prg_ram[offset] = data;
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_WINDOW_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(0, CHR_WINDOW_SIZE, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Nrom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        self.chr.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes_into(&mut self.prg_ram)?;
+        self.chr.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_header;
+
+    #[test]
+    fn test_nrom_128_is_mirrored() {
+        let prg_rom = (0..0x4000).map(|i| (i / 0x100) as u8).collect();
+        let header = test_header(0, 0);
+        let mut nrom = Nrom::new(&header, prg_rom, ChrMemory::new(&header, vec![]));
+        assert_eq!(Some(0x3F), nrom.cpu_read(0xBFFF));
+        assert_eq!(Some(0x3F), nrom.cpu_read(0xFFFF));
+        assert_eq!(Some(0x00), nrom.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_nrom_prg_ram_and_chr_ram() {
+        let mut header = test_header(0, 0);
+        header.prg_ram_size = 0x800;
+        let mut nrom = Nrom::new(&header, vec![0; 0x8000], ChrMemory::new(&header, vec![]));
+        nrom.cpu_write(0x6001, 42);
+        assert_eq!(Some(42), nrom.cpu_read(0x6801));
+        nrom.ppu_write(0x1234, 99);
+        assert_eq!(99, nrom.ppu_read(0x1234));
+    }
+}
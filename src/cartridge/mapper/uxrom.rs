@@ -0,0 +1,133 @@
+use super::{bank_offset, has_bus_conflicts, read_bank, ChrMemory, Mapper, PRG_ROM_START};
+use crate::cartridge::{Header, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+const PRG_WINDOW_SIZE: usize = 0x4000;
+const CHR_WINDOW_SIZE: usize = 0x2000;
+const FIXED_BANK_START: u16 = 0xC000;
+/// Room for the fixed last bank.
+pub(super) const MIN_PRG_ROM_SIZE: usize = PRG_WINDOW_SIZE;
+
+/// Mapper 2, switchable 16KiB bank at $8000 and last bank fixed at $C000.
+/// https://www.nesdev.org/wiki/UxROM
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    mirroring: Mirroring,
+    bank: usize,
+    has_bus_conflicts: bool,
+}
+
+impl Uxrom {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr: ChrMemory) -> Self {
+        Self {
+            prg_rom,
+            chr,
+            mirroring: header.mirroring,
+            bank: 0,
+            has_bus_conflicts: has_bus_conflicts(header),
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        self.prg_rom.len() / PRG_WINDOW_SIZE - 1
+    }
+
+    fn read_prg(&self, addr: u16) -> u8 {
+        let bank = if addr >= FIXED_BANK_START {
+            self.last_bank()
+        } else {
+            self.bank
+        };
+        self.prg_rom[bank_offset(bank, PRG_WINDOW_SIZE, addr, self.prg_rom.len())]
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        (addr >= PRG_ROM_START).then(|| self.read_prg(addr))
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            let data = if self.has_bus_conflicts {
+                data & self.read_prg(addr)
+            } else {
+                data
+            };
+            self.bank = usize::from(data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_WINDOW_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(0, CHR_WINDOW_SIZE, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+impl Snapshot for Uxrom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.chr.save_state(writer);
+        writer.write_u32(self.bank as u32);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(reader)?;
+        self.bank = read_bank(reader, usize::from(u8::MAX))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper::test_header;
+
+    fn banked_prg_rom(banks: usize) -> Vec<u8> {
+        (0..banks * PRG_WINDOW_SIZE)
+            .map(|i| (i / PRG_WINDOW_SIZE) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_uxrom_bank_switching() {
+        let header = test_header(2, 0);
+        let mut uxrom = Uxrom::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        assert_eq!(Some(0), uxrom.cpu_read(0x8000));
+        assert_eq!(Some(7), uxrom.cpu_read(0xC000));
+        uxrom.cpu_write(0x8000, 5);
+        assert_eq!(Some(5), uxrom.cpu_read(0xBFFF));
+        assert_eq!(Some(7), uxrom.cpu_read(0xFFFF));
+    }
+
+    #[test]
+    fn test_uxrom_bus_conflicts() {
+        let header = test_header(2, 2);
+        let mut uxrom = Uxrom::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        // the fixed bank holds 7 everywhere, 5 & 7 = 5
+        uxrom.cpu_write(0xC000, 0xFD);
+        assert_eq!(Some(5), uxrom.cpu_read(0x8000));
+    }
+
+    #[test]
+    fn test_uxrom_rejects_out_of_range_bank_state() {
+        let header = test_header(2, 0);
+        let mut uxrom = Uxrom::new(&header, banked_prg_rom(8), ChrMemory::new(&header, vec![]));
+        let mut writer = StateWriter::new();
+        uxrom.save_state(&mut writer);
+        let mut state = writer.into_inner();
+        let bank = state.len() - 4;
+        state[bank..].copy_from_slice(&0x100u32.to_le_bytes());
+        assert_eq!(
+            Err(StateError::InvalidValue("bank")),
+            uxrom.load_state(&mut StateReader::new(&state))
+        );
+    }
+}
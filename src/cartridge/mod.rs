@@ -0,0 +1,472 @@
+pub mod mapper;
+
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use mapper::{Mapper, SharedMapper};
+use std::cell::{RefCell, RefMut};
+use std::fmt;
+use std::rc::Rc;
+
+const INES_MAGIC: [u8; 4] = [b'N', b'E', b'S', 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_BANK_SIZE: usize = 0x4000;
+const CHR_ROM_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem { ppu: u8, hardware: u8 },
+    Playchoice10,
+    Extended(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CartridgeError {
+    InvalidMagic,
+    TruncatedHeader,
+    TruncatedData { expected: usize, actual: usize },
+    InvalidRomSize,
+    MissingPrgRom,
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::InvalidMagic => write!(f, "missing iNES magic number"),
+            CartridgeError::TruncatedHeader => {
+                write!(f, "file is shorter than the {HEADER_SIZE} bytes header")
+            }
+            CartridgeError::TruncatedData { expected, actual } => write!(
+                f,
+                "header announces {expected} bytes of data but the file only holds {actual}"
+            ),
+            CartridgeError::InvalidRomSize => write!(f, "ROM size does not fit in memory"),
+            CartridgeError::MissingPrgRom => write!(f, "cartridge has no PRG ROM"),
+            CartridgeError::UnsupportedMapper(mapper) => {
+                write!(f, "mapper {mapper} is not supported")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+/// Content of the 16 bytes iNES / NES 2.0 header.
+/// https://www.nesdev.org/wiki/NES_2.0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub format: Format,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    pub console_type: ConsoleType,
+    pub timing: Timing,
+}
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < HEADER_SIZE {
+            return Err(CartridgeError::TruncatedHeader);
+        }
+        if data[0..4] != INES_MAGIC {
+            return Err(CartridgeError::InvalidMagic);
+        }
+        let flags_6 = data[6];
+        let flags_7 = data[7];
+        let format = if flags_7 & 0b0000_1100 == 0b0000_1000 {
+            Format::Nes2
+        } else {
+            Format::INes
+        };
+        let mirroring = if flags_6 & 0b0000_1000 != 0 {
+            Mirroring::FourScreen
+        } else if flags_6 & 0b0000_0001 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mut header = Self {
+            format,
+            prg_rom_size: usize::from(data[4]) * PRG_ROM_BANK_SIZE,
+            chr_rom_size: usize::from(data[5]) * CHR_ROM_BANK_SIZE,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            mapper: u16::from(flags_6 >> 4) | u16::from(flags_7 & 0xF0),
+            submapper: 0,
+            mirroring,
+            has_battery: flags_6 & 0b0000_0010 != 0,
+            has_trainer: flags_6 & 0b0000_0100 != 0,
+            console_type: console_type(flags_7 & 0b0000_0011, data[13]),
+            timing: Timing::Ntsc,
+        };
+        match format {
+            Format::INes => header.parse_ines(data),
+            Format::Nes2 => header.parse_nes2(data)?,
+        }
+        Ok(header)
+    }
+
+    fn parse_ines(&mut self, data: &[u8]) {
+        // Bytes 8 to 15 are only trusted when the tail of the header is clean,
+        // old dumping tools used to write their name in there.
+        let is_tail_clean = data[12..HEADER_SIZE].iter().all(|byte| *byte == 0);
+        if is_tail_clean {
+            self.prg_ram_size = usize::from(data[8].max(1)) * PRG_RAM_BANK_SIZE;
+            if data[9] & 1 == 1 {
+                self.timing = Timing::Pal;
+            }
+        } else {
+            self.prg_ram_size = PRG_RAM_BANK_SIZE;
+            self.mapper &= 0x0F;
+            self.console_type = ConsoleType::Nes;
+        }
+        if self.has_battery {
+            self.prg_nvram_size = self.prg_ram_size;
+            self.prg_ram_size = 0;
+        }
+        if self.chr_rom_size == 0 {
+            self.chr_ram_size = CHR_ROM_BANK_SIZE;
+        }
+    }
+
+    fn parse_nes2(&mut self, data: &[u8]) -> Result<(), CartridgeError> {
+        self.mapper |= u16::from(data[8] & 0x0F) << 8;
+        self.submapper = data[8] >> 4;
+        self.prg_rom_size = nes2_rom_size(data[4], data[9] & 0x0F, PRG_ROM_BANK_SIZE)?;
+        self.chr_rom_size = nes2_rom_size(data[5], data[9] >> 4, CHR_ROM_BANK_SIZE)?;
+        self.prg_ram_size = nes2_ram_size(data[10] & 0x0F);
+        self.prg_nvram_size = nes2_ram_size(data[10] >> 4);
+        self.chr_ram_size = nes2_ram_size(data[11] & 0x0F);
+        self.chr_nvram_size = nes2_ram_size(data[11] >> 4);
+        self.timing = match data[12] & 0b0000_0011 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        };
+        Ok(())
+    }
+}
+
+fn console_type(bits: u8, byte_13: u8) -> ConsoleType {
+    match bits {
+        0 => ConsoleType::Nes,
+        1 => ConsoleType::VsSystem {
+            ppu: byte_13 & 0x0F,
+            hardware: byte_13 >> 4,
+        },
+        2 => ConsoleType::Playchoice10,
+        _ => ConsoleType::Extended(byte_13 & 0x0F),
+    }
+}
+
+fn nes2_rom_size(lsb: u8, msb: u8, bank_size: usize) -> Result<usize, CartridgeError> {
+    if msb == 0x0F {
+        // exponent-multiplier notation : 2^E * (MM * 2 + 1)
+        let exponent = u32::from(lsb >> 2);
+        let multiplier = usize::from(lsb & 0b0000_0011) * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .ok_or(CartridgeError::InvalidRomSize)
+    } else {
+        Ok((usize::from(msb) << 8 | usize::from(lsb)) * bank_size)
+    }
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+pub struct Cartridge {
+    pub header: Header,
+    pub trainer: Option<Vec<u8>>,
+    mapper: SharedMapper,
+}
+
+impl Cartridge {
+    pub fn new(data: &[u8]) -> Result<Self, CartridgeError> {
+        let header = Header::parse(data)?;
+        if header.prg_rom_size == 0 {
+            return Err(CartridgeError::MissingPrgRom);
+        }
+        let trainer_size = if header.has_trainer { TRAINER_SIZE } else { 0 };
+        let expected = HEADER_SIZE
+            .checked_add(trainer_size)
+            .and_then(|size| size.checked_add(header.prg_rom_size))
+            .and_then(|size| size.checked_add(header.chr_rom_size))
+            .ok_or(CartridgeError::InvalidRomSize)?;
+        if data.len() < expected {
+            return Err(CartridgeError::TruncatedData {
+                expected,
+                actual: data.len(),
+            });
+        }
+        let prg_start = HEADER_SIZE + trainer_size;
+        let chr_start = prg_start + header.prg_rom_size;
+        let mapper = mapper::new(
+            &header,
+            data[prg_start..chr_start].to_vec(),
+            data[chr_start..chr_start + header.chr_rom_size].to_vec(),
+        )?;
+        Ok(Self {
+            trainer: header
+                .has_trainer
+                .then(|| data[HEADER_SIZE..prg_start].to_vec()),
+            header,
+            mapper: Rc::new(RefCell::new(mapper)),
+        })
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.borrow().irq()
+    }
+
+    pub fn mapper(&self) -> RefMut<'_, Box<dyn Mapper>> {
+        self.mapper.borrow_mut()
+    }
+
+    /// Handle on the board for the PPU bus.
+    pub fn shared_mapper(&self) -> SharedMapper {
+        Rc::clone(&self.mapper)
+    }
+}
+
+impl Snapshot for Cartridge {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.header.mapper);
+        writer.write_u32(self.header.prg_rom_size as u32);
+        writer.write_u32(self.header.chr_rom_size as u32);
+        self.mapper.borrow().save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if reader.read_u16()? != self.header.mapper
+            || reader.read_u32()? as usize != self.header.prg_rom_size
+            || reader.read_u32()? as usize != self.header.chr_rom_size
+        {
+            return Err(StateError::CartridgeMismatch);
+        }
+        self.mapper.borrow_mut().load_state(reader)
+    }
+}
+
+impl Snapshot for Mirroring {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self {
+            Mirroring::Horizontal => 0,
+            Mirroring::Vertical => 1,
+            Mirroring::FourScreen => 2,
+            Mirroring::SingleScreenLower => 3,
+            Mirroring::SingleScreenUpper => 4,
+        });
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        *self = match reader.read_u8()? {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::FourScreen,
+            3 => Mirroring::SingleScreenLower,
+            4 => Mirroring::SingleScreenUpper,
+            _ => return Err(StateError::InvalidValue("mirroring")),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_bytes(fields: [u8; HEADER_SIZE - 4]) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&INES_MAGIC);
+        header[4..].copy_from_slice(&fields);
+        header
+    }
+
+    fn build_rom(header: [u8; HEADER_SIZE], data_len: usize) -> Vec<u8> {
+        let mut rom = header.to_vec();
+        rom.extend((0..data_len).map(|i| i as u8));
+        rom
+    }
+
+    #[test]
+    fn test_parse_ines_header() {
+        let header =
+            Header::parse(&header_bytes([2, 1, 0x13, 0x40, 0, 1, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(Format::INes, header.format);
+        assert_eq!(0x8000, header.prg_rom_size);
+        assert_eq!(0x2000, header.chr_rom_size);
+        assert_eq!(0x41, header.mapper);
+        assert_eq!(Mirroring::Vertical, header.mirroring);
+        assert!(header.has_battery);
+        assert!(!header.has_trainer);
+        assert_eq!(0x2000, header.prg_nvram_size);
+        assert_eq!(Timing::Pal, header.timing);
+    }
+
+    #[test]
+    fn test_parse_ines_header_with_dirty_tail() {
+        let mut bytes = header_bytes([1, 0, 0x18, 0x40, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes[8..].copy_from_slice(b"DiskDude");
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(0x01, header.mapper);
+        assert_eq!(Mirroring::FourScreen, header.mirroring);
+        assert_eq!(0x2000, header.chr_ram_size);
+        assert_eq!(Timing::Ntsc, header.timing);
+    }
+
+    #[test]
+    fn test_parse_nes2_header() {
+        let header = Header::parse(&header_bytes([
+            0x02, 0x00, 0x40, 0x09, 0x31, 0x10, 0x70, 0x07, 0x03, 0x00, 0x00, 0x00,
+        ]))
+        .unwrap();
+        assert_eq!(Format::Nes2, header.format);
+        assert_eq!(0x104, header.mapper);
+        assert_eq!(3, header.submapper);
+        assert_eq!(0x8000, header.prg_rom_size);
+        assert_eq!(0x100 * CHR_ROM_BANK_SIZE, header.chr_rom_size);
+        assert_eq!(0, header.prg_ram_size);
+        assert_eq!(0x2000, header.prg_nvram_size);
+        assert_eq!(0x2000, header.chr_ram_size);
+        assert_eq!(Timing::Dendy, header.timing);
+        assert_eq!(
+            ConsoleType::VsSystem {
+                ppu: 0,
+                hardware: 0
+            },
+            header.console_type
+        );
+    }
+
+    #[test]
+    fn test_parse_nes2_exponent_size() {
+        assert_eq!(
+            Ok(3 * 64),
+            nes2_rom_size(0b0001_1001, 0x0F, PRG_ROM_BANK_SIZE)
+        );
+        assert_eq!(
+            Err(CartridgeError::InvalidRomSize),
+            nes2_rom_size(0xFF, 0x0F, PRG_ROM_BANK_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        assert_eq!(Err(CartridgeError::TruncatedHeader), Header::parse(b"NES"));
+        assert_eq!(
+            Err(CartridgeError::InvalidMagic),
+            Header::parse(&[0; HEADER_SIZE])
+        );
+    }
+
+    #[test]
+    fn test_load_cartridge_with_trainer() {
+        let header = header_bytes([1, 1, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let rom = build_rom(header, TRAINER_SIZE + PRG_ROM_BANK_SIZE + CHR_ROM_BANK_SIZE);
+        let cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(TRAINER_SIZE, cartridge.trainer.as_ref().unwrap().len());
+        let prg_start = HEADER_SIZE + TRAINER_SIZE;
+        assert_eq!(Some(rom[prg_start]), cartridge.mapper().cpu_read(0x8000));
+        assert_eq!(Some(rom[prg_start]), cartridge.mapper().cpu_read(0xC000));
+        assert_eq!(
+            rom[prg_start + PRG_ROM_BANK_SIZE],
+            cartridge.mapper().ppu_read(0x0000)
+        );
+    }
+
+    #[test]
+    fn test_load_unsupported_mapper() {
+        let header = header_bytes([1, 0, 0xF0, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            Err(CartridgeError::UnsupportedMapper(0xFF)),
+            Cartridge::new(&build_rom(header, PRG_ROM_BANK_SIZE)).map(|cartridge| cartridge.header)
+        );
+    }
+
+    #[test]
+    fn test_load_too_small_prg_rom() {
+        for (mapper, is_valid) in [
+            (0, true),
+            (1, false),
+            (2, false),
+            (3, true),
+            (4, false),
+            (7, true),
+        ] {
+            // NES 2.0 header of an 8 bytes PRG ROM in exponent-multiplier notation
+            let header = header_bytes([0x0C, 0, mapper << 4, 0x08, 0, 0x0F, 0, 0, 0, 0, 0, 0]);
+            match Cartridge::new(&build_rom(header, 8)) {
+                Ok(cartridge) => {
+                    assert!(is_valid, "mapper {mapper}");
+                    assert_eq!(Some(4), cartridge.mapper().cpu_read(0xFFFC));
+                }
+                Err(error) => {
+                    assert!(!is_valid, "mapper {mapper}");
+                    assert_eq!(CartridgeError::InvalidRomSize, error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_truncated_cartridge() {
+        let mut header = header_bytes([2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let rom = build_rom(header, PRG_ROM_BANK_SIZE);
+        assert_eq!(
+            Err(CartridgeError::TruncatedData {
+                expected: HEADER_SIZE + 2 * PRG_ROM_BANK_SIZE,
+                actual: HEADER_SIZE + PRG_ROM_BANK_SIZE
+            }),
+            Cartridge::new(&rom).map(|cartridge| cartridge.header)
+        );
+
+        header[4] = 0;
+        assert_eq!(
+            Err(CartridgeError::MissingPrgRom),
+            Cartridge::new(&build_rom(header, 0)).map(|cartridge| cartridge.header)
+        );
+    }
+}
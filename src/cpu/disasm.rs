@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::instruction::{Mode, Name, INSTRUCTION_MAP};
+use crate::traits::Memory;
+
+/// Labels substituted to the addresses they name.
+pub type SymbolTable = HashMap<u16, String>;
+
+/// One decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub name: Name,
+    pub mode: Mode,
+    /// Immediate value, address or resolved branch target, `None` without operand.
+    pub operand: Option<u16>,
+    pub is_unofficial: bool,
+}
+
+impl Line {
+    /// Address following the instruction.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// Assembly text, the addresses found in `symbols` are replaced by their label.
+    pub fn text(&self, symbols: Option<&SymbolTable>) -> String {
+        let label = |addr: u16, width: usize| match symbols.and_then(|symbols| symbols.get(&addr)) {
+            Some(label) => label.clone(),
+            None => format!("${addr:0width$X}"),
+        };
+        let prefix = if self.is_unofficial { "*" } else { "" };
+        let operand = match (self.mode, self.operand) {
+            (Mode::Accumulator, _) => "A".to_string(),
+            (Mode::Implicit, _) | (_, None) => String::new(),
+            (Mode::Immediate, Some(value)) => format!("#${value:02X}"),
+            (Mode::ZeroPage, Some(addr)) => label(addr, 2),
+            (Mode::ZeroPageX, Some(addr)) => format!("{},X", label(addr, 2)),
+            (Mode::ZeroPageY, Some(addr)) => format!("{},Y", label(addr, 2)),
+            (Mode::Absolute | Mode::Relative, Some(addr)) => label(addr, 4),
+            (Mode::AbsoluteX, Some(addr)) => format!("{},X", label(addr, 4)),
+            (Mode::AbsoluteY, Some(addr)) => format!("{},Y", label(addr, 4)),
+            (Mode::Indirect, Some(addr)) => format!("({})", label(addr, 4)),
+            (Mode::IndirectX, Some(addr)) => format!("({},X)", label(addr, 2)),
+            (Mode::IndirectY, Some(addr)) => format!("({}),Y", label(addr, 2)),
+        };
+        format!("{prefix}{} {operand}", self.name.mnemonic())
+            .trim_end()
+            .to_string()
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text(None))
+    }
+}
+
+/// Decodes the instruction at `addr`, `peek` must read without side effects.
+pub fn decode(mut peek: impl FnMut(u16) -> u8, addr: u16) -> Line {
+    let instruct = INSTRUCTION_MAP.get(&peek(addr)).unwrap();
+    let bytes: Vec<u8> = (0..u16::from(instruct.len))
+        .map(|i| peek(addr.wrapping_add(i)))
+        .collect();
+    let operand = match bytes[1..] {
+        [] => None,
+        [lo] if instruct.mode == Mode::Relative => {
+            Some(addr.wrapping_add(2).wrapping_add(lo as i8 as u16))
+        }
+        [lo] => Some(u16::from(lo)),
+        [lo, hi, ..] => Some(u16::from_le_bytes([lo, hi])),
+    };
+    Line {
+        addr,
+        bytes,
+        name: instruct.name,
+        mode: instruct.mode,
+        operand: operand.filter(|_| instruct.mode != Mode::Implicit),
+        is_unofficial: instruct.is_unofficial(),
+    }
+}
+
+/// Decodes `count` consecutive instructions from `start`.
+pub fn disassemble_with(mut peek: impl FnMut(u16) -> u8, start: u16, count: usize) -> Vec<Line> {
+    let mut addr = start;
+    (0..count)
+        .map(|_| {
+            let line = decode(&mut peek, addr);
+            addr = line.next_addr();
+            line
+        })
+        .collect()
+}
+
+/// Decodes `count` instructions from a live memory, without side effects.
+pub fn disassemble_memory(memory: &dyn Memory, start: u16, count: usize) -> Vec<Line> {
+    disassemble_with(|addr| memory.peek_u8(addr), start, count)
+}
+
+/// Decodes `bytes` loaded at `origin`, a last instruction cut by the end
+/// of the range is left out.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let line = decode(
+            |addr| {
+                let index = usize::from(addr.wrapping_sub(origin));
+                bytes.get(index).copied().unwrap_or_default()
+            },
+            origin.wrapping_add(offset as u16),
+        );
+        offset += line.bytes.len();
+        if offset > bytes.len() {
+            break;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Listing with addresses, raw bytes and the labels on their own line.
+pub fn listing(lines: &[Line], symbols: Option<&SymbolTable>) -> String {
+    let mut listing = String::new();
+    for line in lines {
+        if let Some(label) = symbols.and_then(|symbols| symbols.get(&line.addr)) {
+            listing.push_str(&format!("{label}:\n"));
+        }
+        let bytes = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        listing.push_str(&format!(
+            "{:04X}  {bytes:<8}  {}\n",
+            line.addr,
+            line.text(symbols)
+        ));
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(bytes: &[u8], origin: u16) -> Vec<String> {
+        disassemble(bytes, origin)
+            .iter()
+            .map(Line::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_every_mode_syntax() {
+        #[rustfmt::skip]
+        let program = [
+            0xEA,             // NOP
+            0x0A,             // ASL A
+            0xA9, 0x10,       // LDA #$10
+            0xA5, 0x20,       // LDA $20
+            0xB5, 0x20,       // LDA $20,X
+            0xB6, 0x20,       // LDX $20,Y
+            0xAD, 0x00, 0x02, // LDA $0200
+            0xBD, 0x00, 0x02, // LDA $0200,X
+            0xB9, 0x00, 0x02, // LDA $0200,Y
+            0x6C, 0xFC, 0xFF, // JMP ($FFFC)
+            0xA1, 0x40,       // LDA ($40,X)
+            0xB1, 0x40,       // LDA ($40),Y
+            0xD0, 0xFE,       // BNE to itself
+            0xA7, 0x10,       // *LAX $10
+        ];
+        assert_eq!(
+            vec![
+                "NOP",
+                "ASL A",
+                "LDA #$10",
+                "LDA $20",
+                "LDA $20,X",
+                "LDX $20,Y",
+                "LDA $0200",
+                "LDA $0200,X",
+                "LDA $0200,Y",
+                "JMP ($FFFC)",
+                "LDA ($40,X)",
+                "LDA ($40),Y",
+                "BNE $801A",
+                "*LAX $10",
+            ],
+            texts(&program, 0x8000)
+        );
+    }
+
+    #[test]
+    fn test_branch_targets_are_absolute() {
+        // BPL +4 ; BMI -128 at the start of the address space
+        let lines = disassemble(&[0x10, 0x04, 0x30, 0x80], 0x0000);
+        assert_eq!(Some(0x0006), lines[0].operand);
+        assert_eq!(Some(0xFF84), lines[1].operand);
+        assert_eq!(0x0004, lines[1].next_addr());
+    }
+
+    #[test]
+    fn test_cut_instruction_is_left_out() {
+        // INX ; LDA absolute missing its high byte
+        assert_eq!(vec!["INX"], texts(&[0xE8, 0xAD, 0x00], 0x8000));
+    }
+
+    #[test]
+    fn test_labels() {
+        let symbols = SymbolTable::from([
+            (0x8000, "reset".to_string()),
+            (0x0010, "counter".to_string()),
+        ]);
+        // INC $10 ; JMP $8000
+        let lines = disassemble(&[0xE6, 0x10, 0x4C, 0x00, 0x80], 0x8000);
+        assert_eq!("INC counter", lines[0].text(Some(&symbols)));
+        assert_eq!("JMP reset", lines[1].text(Some(&symbols)));
+        assert_eq!(
+            "reset:\n8000  E6 10     INC counter\n8002  4C 00 80  JMP reset\n",
+            listing(&lines, Some(&symbols))
+        );
+    }
+
+    #[test]
+    fn test_disassemble_with_reader() {
+        let memory = [0xA2, 0x01, 0xCA, 0x00];
+        let lines = disassemble_with(|addr| memory[usize::from(addr) % 4], 0, 3);
+        assert_eq!(
+            vec![0, 2, 3],
+            lines.iter().map(|line| line.addr).collect::<Vec<_>>()
+        );
+        assert_eq!(Name::Brk, lines[2].name);
+    }
+}
@@ -2,7 +2,7 @@ use lazy_static::lazy_static;
 
 pub type Opcode = u8;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Name {
     Adc,
     And,
@@ -60,9 +60,36 @@ pub enum Name {
     Nop,
     Rti,
     Rts,
+    // unofficial
+    Lax,
+    Lxa,
+    Sax,
+    Dcp,
+    Isc,
+    Slo,
+    Rla,
+    Sre,
+    Rra,
+    Anc,
+    Alr,
+    Arr,
+    Axs,
+    Xaa,
+    Sha,
+    Shx,
+    Shy,
+    Tas,
+    Las,
+    Jam,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Name {
+    pub fn mnemonic(&self) -> String {
+        format!("{self:?}").to_uppercase()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Immediate,
     ZeroPage,
@@ -79,203 +106,422 @@ pub enum Mode {
     Relative,
 }
 
+/// What an instruction does at its effective address, it decides the
+/// dummy accesses around it.
+/// https://www.nesdev.org/6502_cpu.txt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Implied, stack, branch and jump instructions.
+    None,
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
 #[derive(Debug)]
 pub struct Instruction {
     pub name: Name,
     pub opcode: Opcode,
     pub mode: Mode,
     pub len: u8,
+    /// Base cycle count, without the page crossing and taken branch penalties.
+    pub cycles: u8,
 }
 
 impl Instruction {
-    const fn new(name: Name, opcode: Opcode, mode: Mode, len: u8) -> Instruction {
+    const fn new(name: Name, opcode: Opcode, mode: Mode, len: u8, cycles: u8) -> Instruction {
         Instruction {
             name,
             opcode,
             mode,
             len,
+            cycles,
         }
     }
+
+    /// Opcodes outside of the documented 151, the disassembly prefixes them with `*`.
+    /// https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    pub fn is_unofficial(&self) -> bool {
+        match self.name {
+            Name::Nop => self.opcode != 0xEA,
+            Name::Sbc => self.opcode == 0xEB,
+            Name::Lax
+            | Name::Lxa
+            | Name::Sax
+            | Name::Dcp
+            | Name::Isc
+            | Name::Slo
+            | Name::Rla
+            | Name::Sre
+            | Name::Rra
+            | Name::Anc
+            | Name::Alr
+            | Name::Arr
+            | Name::Axs
+            | Name::Xaa
+            | Name::Sha
+            | Name::Shx
+            | Name::Shy
+            | Name::Tas
+            | Name::Las
+            | Name::Jam => true,
+            _ => false,
+        }
+    }
+
+    pub fn access(&self) -> Access {
+        if matches!(
+            self.mode,
+            Mode::Implicit | Mode::Accumulator | Mode::Relative
+        ) {
+            return Access::None;
+        }
+        match self.name {
+            Name::Jmp | Name::Jsr => Access::None,
+            Name::Sta
+            | Name::Stx
+            | Name::Sty
+            | Name::Sax
+            | Name::Sha
+            | Name::Shx
+            | Name::Shy
+            | Name::Tas => Access::Write,
+            Name::Asl
+            | Name::Lsr
+            | Name::Rol
+            | Name::Ror
+            | Name::Inc
+            | Name::Dec
+            | Name::Slo
+            | Name::Rla
+            | Name::Sre
+            | Name::Rra
+            | Name::Dcp
+            | Name::Isc => Access::ReadModifyWrite,
+            _ => Access::Read,
+        }
+    }
+
+    /// Indexed reads take one more cycle when the effective address
+    /// crosses a page, stores and read-modify-write always take it.
+    /// https://www.nesdev.org/wiki/6502_cycle_times
+    pub fn has_page_cross_penalty(&self) -> bool {
+        matches!(
+            self.mode,
+            Mode::AbsoluteX | Mode::AbsoluteY | Mode::IndirectY
+        ) && matches!(
+            self.name,
+            Name::Adc
+                | Name::And
+                | Name::Cmp
+                | Name::Eor
+                | Name::Lda
+                | Name::Ldx
+                | Name::Ldy
+                | Name::Ora
+                | Name::Sbc
+                | Name::Lax
+                | Name::Las
+                | Name::Nop
+        )
+    }
 }
 
 #[rustfmt::skip] 
-const INSTRUCTIONS: [Instruction; 151] = [
-    Instruction::new(Name::Adc, 0x69, Mode::Immediate, 2),
-    Instruction::new(Name::Adc, 0x65, Mode::ZeroPage, 2),
-    Instruction::new(Name::Adc, 0x75, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Adc, 0x6D, Mode::Absolute, 3),
-    Instruction::new(Name::Adc, 0x7D, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Adc, 0x79, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Adc, 0x61, Mode::IndirectX, 2),
-    Instruction::new(Name::Adc, 0x71, Mode::IndirectY, 2),
+const INSTRUCTIONS: [Instruction; 256] = [
+    Instruction::new(Name::Adc, 0x69, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Adc, 0x65, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Adc, 0x75, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Adc, 0x6D, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Adc, 0x7D, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Adc, 0x79, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Adc, 0x61, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Adc, 0x71, Mode::IndirectY, 2, 5),
     //AND_SET
-    Instruction::new(Name::And, 0x29, Mode::Immediate, 2),
-    Instruction::new(Name::And, 0x25, Mode::ZeroPage, 2),
-    Instruction::new(Name::And, 0x35, Mode::ZeroPageX, 2),
-    Instruction::new(Name::And, 0x2D, Mode::Absolute, 3),
-    Instruction::new(Name::And, 0x3D, Mode::AbsoluteX, 3),
-    Instruction::new(Name::And, 0x39, Mode::AbsoluteY, 3),
-    Instruction::new(Name::And, 0x21, Mode::IndirectX, 2),
-    Instruction::new(Name::And, 0x31, Mode::IndirectY, 2),
+    Instruction::new(Name::And, 0x29, Mode::Immediate, 2, 2),
+    Instruction::new(Name::And, 0x25, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::And, 0x35, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::And, 0x2D, Mode::Absolute, 3, 4),
+    Instruction::new(Name::And, 0x3D, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::And, 0x39, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::And, 0x21, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::And, 0x31, Mode::IndirectY, 2, 5),
     //ASL_SET
-    Instruction::new(Name::Asl, 0x0A, Mode::Accumulator, 1),
-    Instruction::new(Name::Asl, 0x06, Mode::ZeroPage, 2),
-    Instruction::new(Name::Asl, 0x16, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Asl, 0x0E, Mode::Absolute, 3),
-    Instruction::new(Name::Asl, 0x1E, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Asl, 0x0A, Mode::Accumulator, 1, 2),
+    Instruction::new(Name::Asl, 0x06, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Asl, 0x16, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Asl, 0x0E, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Asl, 0x1E, Mode::AbsoluteX, 3, 7),
     //BIT_SET
-    Instruction::new(Name::Bit, 0x24, Mode::ZeroPage, 2),
-    Instruction::new(Name::Bit, 0x2C, Mode::Absolute, 3),
+    Instruction::new(Name::Bit, 0x24, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Bit, 0x2C, Mode::Absolute, 3, 4),
     //BRANCHES_SET
-    Instruction::new(Name::Bpl, 0x10, Mode::Relative, 2),
-    Instruction::new(Name::Bmi, 0x30, Mode::Relative, 2),
-    Instruction::new(Name::Bvc, 0x50, Mode::Relative, 2),
-    Instruction::new(Name::Bvs, 0x70, Mode::Relative, 2),
-    Instruction::new(Name::Bcc, 0x90, Mode::Relative, 2),
-    Instruction::new(Name::Bcs, 0xB0, Mode::Relative, 2),
-    Instruction::new(Name::Bne, 0xD0, Mode::Relative, 2),
-    Instruction::new(Name::Beq, 0xF0, Mode::Relative, 2),
+    Instruction::new(Name::Bpl, 0x10, Mode::Relative, 2, 2),
+    Instruction::new(Name::Bmi, 0x30, Mode::Relative, 2, 2),
+    Instruction::new(Name::Bvc, 0x50, Mode::Relative, 2, 2),
+    Instruction::new(Name::Bvs, 0x70, Mode::Relative, 2, 2),
+    Instruction::new(Name::Bcc, 0x90, Mode::Relative, 2, 2),
+    Instruction::new(Name::Bcs, 0xB0, Mode::Relative, 2, 2),
+    Instruction::new(Name::Bne, 0xD0, Mode::Relative, 2, 2),
+    Instruction::new(Name::Beq, 0xF0, Mode::Relative, 2, 2),
     //CMP_SET
-    Instruction::new(Name::Cmp, 0xC9, Mode::Immediate, 2),
-    Instruction::new(Name::Cmp, 0xC5, Mode::ZeroPage, 2),
-    Instruction::new(Name::Cmp, 0xD5, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Cmp, 0xCD, Mode::Absolute, 3),
-    Instruction::new(Name::Cmp, 0xDD, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Cmp, 0xD9, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Cmp, 0xC1, Mode::IndirectX, 2),
-    Instruction::new(Name::Cmp, 0xD1, Mode::IndirectY, 2),
+    Instruction::new(Name::Cmp, 0xC9, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Cmp, 0xC5, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Cmp, 0xD5, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Cmp, 0xCD, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Cmp, 0xDD, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Cmp, 0xD9, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Cmp, 0xC1, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Cmp, 0xD1, Mode::IndirectY, 2, 5),
     //CPX_SET
-    Instruction::new(Name::Cpx, 0xE0, Mode::Immediate, 2),
-    Instruction::new(Name::Cpx, 0xE4, Mode::ZeroPage, 2),
-    Instruction::new(Name::Cpx, 0xEC, Mode::Absolute, 3),
+    Instruction::new(Name::Cpx, 0xE0, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Cpx, 0xE4, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Cpx, 0xEC, Mode::Absolute, 3, 4),
     //CPY_SET
-    Instruction::new(Name::Cpy, 0xC0, Mode::Immediate, 2),
-    Instruction::new(Name::Cpy, 0xC4, Mode::ZeroPage, 2),
-    Instruction::new(Name::Cpy, 0xCC, Mode::Absolute, 3),
+    Instruction::new(Name::Cpy, 0xC0, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Cpy, 0xC4, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Cpy, 0xCC, Mode::Absolute, 3, 4),
     //DEC_SET
-    Instruction::new(Name::Dec, 0xC6, Mode::ZeroPage, 2),
-    Instruction::new(Name::Dec, 0xD6, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Dec, 0xCE, Mode::Absolute, 3),
-    Instruction::new(Name::Dec, 0xDE, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Dec, 0xC6, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Dec, 0xD6, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Dec, 0xCE, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Dec, 0xDE, Mode::AbsoluteX, 3, 7),
     //EOR_SET
-    Instruction::new(Name::Eor, 0x49, Mode::Immediate, 2),
-    Instruction::new(Name::Eor, 0x45, Mode::ZeroPage, 2),
-    Instruction::new(Name::Eor, 0x55, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Eor, 0x4D, Mode::Absolute, 3),
-    Instruction::new(Name::Eor, 0x5D, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Eor, 0x59, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Eor, 0x41, Mode::IndirectX, 2),
-    Instruction::new(Name::Eor, 0x51, Mode::IndirectY, 2),
+    Instruction::new(Name::Eor, 0x49, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Eor, 0x45, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Eor, 0x55, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Eor, 0x4D, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Eor, 0x5D, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Eor, 0x59, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Eor, 0x41, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Eor, 0x51, Mode::IndirectY, 2, 5),
     //PROC_STATUS_SET
-    Instruction::new(Name::Clc, 0x18, Mode::Implicit, 1),
-    Instruction::new(Name::Sec, 0x38, Mode::Implicit, 1),
-    Instruction::new(Name::Cli, 0x58, Mode::Implicit, 1),
-    Instruction::new(Name::Sei, 0x78, Mode::Implicit, 1),
-    Instruction::new(Name::Clv, 0xB8, Mode::Implicit, 1),
-    Instruction::new(Name::Cld, 0xD8, Mode::Implicit, 1),
-    Instruction::new(Name::Sed, 0xF8, Mode::Implicit, 1),
+    Instruction::new(Name::Clc, 0x18, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Sec, 0x38, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Cli, 0x58, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Sei, 0x78, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Clv, 0xB8, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Cld, 0xD8, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Sed, 0xF8, Mode::Implicit, 1, 2),
     //INC_SET
-    Instruction::new(Name::Inc, 0xE6, Mode::ZeroPage, 2),
-    Instruction::new(Name::Inc, 0xF6, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Inc, 0xEE, Mode::Absolute, 3),
-    Instruction::new(Name::Inc, 0xFE, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Inc, 0xE6, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Inc, 0xF6, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Inc, 0xEE, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Inc, 0xFE, Mode::AbsoluteX, 3, 7),
     //JMP_SET
-    Instruction::new(Name::Jmp, 0x4C, Mode::Absolute, 3),
-    Instruction::new(Name::Jmp, 0x6C, Mode::Indirect, 3),
+    Instruction::new(Name::Jmp, 0x4C, Mode::Absolute, 3, 3),
+    Instruction::new(Name::Jmp, 0x6C, Mode::Indirect, 3, 5),
     //LDA_SET
-    Instruction::new(Name::Lda, 0xA9, Mode::Immediate, 2),
-    Instruction::new(Name::Lda, 0xA5, Mode::ZeroPage, 2),
-    Instruction::new(Name::Lda, 0xB5, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Lda, 0xAD, Mode::Absolute, 3),
-    Instruction::new(Name::Lda, 0xBD, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Lda, 0xB9, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Lda, 0xA1, Mode::IndirectX, 2),
-    Instruction::new(Name::Lda, 0xB1, Mode::IndirectY, 2),
+    Instruction::new(Name::Lda, 0xA9, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Lda, 0xA5, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Lda, 0xB5, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Lda, 0xAD, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Lda, 0xBD, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Lda, 0xB9, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Lda, 0xA1, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Lda, 0xB1, Mode::IndirectY, 2, 5),
     //LDX_SET
-    Instruction::new(Name::Ldx, 0xA2, Mode::Immediate, 2),
-    Instruction::new(Name::Ldx, 0xA6, Mode::ZeroPage, 2),
-    Instruction::new(Name::Ldx, 0xB6, Mode::ZeroPageY, 2),
-    Instruction::new(Name::Ldx, 0xAE, Mode::Absolute, 3),
-    Instruction::new(Name::Ldx, 0xBE, Mode::AbsoluteY, 3),
+    Instruction::new(Name::Ldx, 0xA2, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Ldx, 0xA6, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Ldx, 0xB6, Mode::ZeroPageY, 2, 4),
+    Instruction::new(Name::Ldx, 0xAE, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Ldx, 0xBE, Mode::AbsoluteY, 3, 4),
     //LDY_SET
-    Instruction::new(Name::Ldy, 0xA0, Mode::Immediate, 2),
-    Instruction::new(Name::Ldy, 0xA4, Mode::ZeroPage, 2),
-    Instruction::new(Name::Ldy, 0xB4, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Ldy, 0xAC, Mode::Absolute, 3),
-    Instruction::new(Name::Ldy, 0xBC, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Ldy, 0xA0, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Ldy, 0xA4, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Ldy, 0xB4, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Ldy, 0xAC, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Ldy, 0xBC, Mode::AbsoluteX, 3, 4),
     //LSR_SET
-    Instruction::new(Name::Lsr, 0x4A, Mode::Accumulator, 1),
-    Instruction::new(Name::Lsr, 0x46, Mode::ZeroPage, 2),
-    Instruction::new(Name::Lsr, 0x56, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Lsr, 0x4E, Mode::Absolute, 3),
-    Instruction::new(Name::Lsr, 0x5E, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Lsr, 0x4A, Mode::Accumulator, 1, 2),
+    Instruction::new(Name::Lsr, 0x46, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Lsr, 0x56, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Lsr, 0x4E, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Lsr, 0x5E, Mode::AbsoluteX, 3, 7),
     //ORA_SET
-    Instruction::new(Name::Ora, 0x09, Mode::Immediate, 2),
-    Instruction::new(Name::Ora, 0x05, Mode::ZeroPage, 2),
-    Instruction::new(Name::Ora, 0x15, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Ora, 0x0D, Mode::Absolute, 3),
-    Instruction::new(Name::Ora, 0x1D, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Ora, 0x19, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Ora, 0x01, Mode::IndirectX, 2),
-    Instruction::new(Name::Ora, 0x11, Mode::IndirectY, 2),
+    Instruction::new(Name::Ora, 0x09, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Ora, 0x05, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Ora, 0x15, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Ora, 0x0D, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Ora, 0x1D, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Ora, 0x19, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Ora, 0x01, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Ora, 0x11, Mode::IndirectY, 2, 5),
     //REGISTER_SET
-    Instruction::new(Name::Tax, 0xAA, Mode::Implicit, 1),
-    Instruction::new(Name::Txa, 0x8A, Mode::Implicit, 1),
-    Instruction::new(Name::Dex, 0xCA, Mode::Implicit, 1),
-    Instruction::new(Name::Inx, 0xE8, Mode::Implicit, 1),
-    Instruction::new(Name::Tay, 0xA8, Mode::Implicit, 1),
-    Instruction::new(Name::Tya, 0x98, Mode::Implicit, 1),
-    Instruction::new(Name::Dey, 0x88, Mode::Implicit, 1),
-    Instruction::new(Name::Iny, 0xC8, Mode::Implicit, 1),
+    Instruction::new(Name::Tax, 0xAA, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Txa, 0x8A, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Dex, 0xCA, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Inx, 0xE8, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Tay, 0xA8, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Tya, 0x98, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Dey, 0x88, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Iny, 0xC8, Mode::Implicit, 1, 2),
     //ROL_SET
-    Instruction::new(Name::Rol, 0x2A, Mode::Accumulator, 1),
-    Instruction::new(Name::Rol, 0x26, Mode::ZeroPage, 2),
-    Instruction::new(Name::Rol, 0x36, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Rol, 0x2E, Mode::Absolute, 3),
-    Instruction::new(Name::Rol, 0x3E, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Rol, 0x2A, Mode::Accumulator, 1, 2),
+    Instruction::new(Name::Rol, 0x26, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Rol, 0x36, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Rol, 0x2E, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Rol, 0x3E, Mode::AbsoluteX, 3, 7),
     //ROR_SET
-    Instruction::new(Name::Ror, 0x6A, Mode::Accumulator, 1),
-    Instruction::new(Name::Ror, 0x66, Mode::ZeroPage, 2),
-    Instruction::new(Name::Ror, 0x76, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Ror, 0x6E, Mode::Absolute, 3),
-    Instruction::new(Name::Ror, 0x7E, Mode::AbsoluteX, 3),
+    Instruction::new(Name::Ror, 0x6A, Mode::Accumulator, 1, 2),
+    Instruction::new(Name::Ror, 0x66, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Ror, 0x76, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Ror, 0x6E, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Ror, 0x7E, Mode::AbsoluteX, 3, 7),
     //SBC_SET
-    Instruction::new(Name::Sbc, 0xE9, Mode::Immediate, 2),
-    Instruction::new(Name::Sbc, 0xE5, Mode::ZeroPage, 2),
-    Instruction::new(Name::Sbc, 0xF5, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Sbc, 0xED, Mode::Absolute, 3),
-    Instruction::new(Name::Sbc, 0xFD, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Sbc, 0xF9, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Sbc, 0xE1, Mode::IndirectX, 2),
-    Instruction::new(Name::Sbc, 0xF1, Mode::IndirectY, 2),
+    Instruction::new(Name::Sbc, 0xE9, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Sbc, 0xE5, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Sbc, 0xF5, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Sbc, 0xED, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Sbc, 0xFD, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Sbc, 0xF9, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Sbc, 0xE1, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Sbc, 0xF1, Mode::IndirectY, 2, 5),
     //STA_SET
-    Instruction::new(Name::Sta, 0x85, Mode::ZeroPage, 2),
-    Instruction::new(Name::Sta, 0x95, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Sta, 0x8D, Mode::Absolute, 3),
-    Instruction::new(Name::Sta, 0x9D, Mode::AbsoluteX, 3),
-    Instruction::new(Name::Sta, 0x99, Mode::AbsoluteY, 3),
-    Instruction::new(Name::Sta, 0x81, Mode::IndirectX, 2),
-    Instruction::new(Name::Sta, 0x91, Mode::IndirectY, 2),
+    Instruction::new(Name::Sta, 0x85, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Sta, 0x95, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Sta, 0x8D, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Sta, 0x9D, Mode::AbsoluteX, 3, 5),
+    Instruction::new(Name::Sta, 0x99, Mode::AbsoluteY, 3, 5),
+    Instruction::new(Name::Sta, 0x81, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Sta, 0x91, Mode::IndirectY, 2, 6),
     //STACK_SET
-    Instruction::new(Name::Txs, 0x9A, Mode::Implicit, 1),
-    Instruction::new(Name::Tsx, 0xBA, Mode::Implicit, 1),
-    Instruction::new(Name::Pha, 0x48, Mode::Implicit, 1),
-    Instruction::new(Name::Pla, 0x68, Mode::Implicit, 1),
-    Instruction::new(Name::Php, 0x08, Mode::Implicit, 1),
-    Instruction::new(Name::Plp, 0x28, Mode::Implicit, 1),
+    Instruction::new(Name::Txs, 0x9A, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Tsx, 0xBA, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Pha, 0x48, Mode::Implicit, 1, 3),
+    Instruction::new(Name::Pla, 0x68, Mode::Implicit, 1, 4),
+    Instruction::new(Name::Php, 0x08, Mode::Implicit, 1, 3),
+    Instruction::new(Name::Plp, 0x28, Mode::Implicit, 1, 4),
     //STX_SET
-    Instruction::new(Name::Stx, 0x86, Mode::ZeroPage, 2),
-    Instruction::new(Name::Stx, 0x96, Mode::ZeroPageY, 2),
-    Instruction::new(Name::Stx, 0x8E, Mode::Absolute, 3),
-    Instruction::new(Name::Sty, 0x84, Mode::ZeroPage, 2),
-    Instruction::new(Name::Sty, 0x94, Mode::ZeroPageX, 2),
-    Instruction::new(Name::Sty, 0x8C, Mode::Absolute, 3),
+    Instruction::new(Name::Stx, 0x86, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Stx, 0x96, Mode::ZeroPageY, 2, 4),
+    Instruction::new(Name::Stx, 0x8E, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Sty, 0x84, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Sty, 0x94, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Sty, 0x8C, Mode::Absolute, 3, 4),
     //OTHER_SET
-    Instruction::new(Name::Brk, 0x00, Mode::Implicit, 1),
-    Instruction::new(Name::Jsr, 0x20, Mode::Absolute, 3),
-    Instruction::new(Name::Nop, 0xEA, Mode::Implicit, 1),
-    Instruction::new(Name::Rti, 0x40, Mode::Implicit, 1),
-    Instruction::new(Name::Rts, 0x60, Mode::Implicit, 1)
+    Instruction::new(Name::Brk, 0x00, Mode::Implicit, 1, 7),
+    Instruction::new(Name::Jsr, 0x20, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Nop, 0xEA, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Rti, 0x40, Mode::Implicit, 1, 6),
+    Instruction::new(Name::Rts, 0x60, Mode::Implicit, 1, 6),
+    //UNOFFICIAL_NOP_SET
+    Instruction::new(Name::Nop, 0x1A, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Nop, 0x3A, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Nop, 0x5A, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Nop, 0x7A, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Nop, 0xDA, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Nop, 0xFA, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Nop, 0x80, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Nop, 0x82, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Nop, 0x89, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Nop, 0xC2, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Nop, 0xE2, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Nop, 0x04, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Nop, 0x44, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Nop, 0x64, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Nop, 0x14, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Nop, 0x34, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Nop, 0x54, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Nop, 0x74, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Nop, 0xD4, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Nop, 0xF4, Mode::ZeroPageX, 2, 4),
+    Instruction::new(Name::Nop, 0x0C, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Nop, 0x1C, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Nop, 0x3C, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Nop, 0x5C, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Nop, 0x7C, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Nop, 0xDC, Mode::AbsoluteX, 3, 4),
+    Instruction::new(Name::Nop, 0xFC, Mode::AbsoluteX, 3, 4),
+    //JAM_SET
+    Instruction::new(Name::Jam, 0x02, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x12, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x22, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x32, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x42, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x52, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x62, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x72, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0x92, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0xB2, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0xD2, Mode::Implicit, 1, 2),
+    Instruction::new(Name::Jam, 0xF2, Mode::Implicit, 1, 2),
+    //LAX_SET
+    Instruction::new(Name::Lax, 0xA7, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Lax, 0xB7, Mode::ZeroPageY, 2, 4),
+    Instruction::new(Name::Lax, 0xAF, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Lax, 0xBF, Mode::AbsoluteY, 3, 4),
+    Instruction::new(Name::Lax, 0xA3, Mode::IndirectX, 2, 6),
+    Instruction::new(Name::Lax, 0xB3, Mode::IndirectY, 2, 5),
+    Instruction::new(Name::Lxa, 0xAB, Mode::Immediate, 2, 2),
+    //SAX_SET
+    Instruction::new(Name::Sax, 0x87, Mode::ZeroPage, 2, 3),
+    Instruction::new(Name::Sax, 0x97, Mode::ZeroPageY, 2, 4),
+    Instruction::new(Name::Sax, 0x8F, Mode::Absolute, 3, 4),
+    Instruction::new(Name::Sax, 0x83, Mode::IndirectX, 2, 6),
+    //SLO_SET
+    Instruction::new(Name::Slo, 0x07, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Slo, 0x17, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Slo, 0x0F, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Slo, 0x1F, Mode::AbsoluteX, 3, 7),
+    Instruction::new(Name::Slo, 0x1B, Mode::AbsoluteY, 3, 7),
+    Instruction::new(Name::Slo, 0x03, Mode::IndirectX, 2, 8),
+    Instruction::new(Name::Slo, 0x13, Mode::IndirectY, 2, 8),
+    //RLA_SET
+    Instruction::new(Name::Rla, 0x27, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Rla, 0x37, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Rla, 0x2F, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Rla, 0x3F, Mode::AbsoluteX, 3, 7),
+    Instruction::new(Name::Rla, 0x3B, Mode::AbsoluteY, 3, 7),
+    Instruction::new(Name::Rla, 0x23, Mode::IndirectX, 2, 8),
+    Instruction::new(Name::Rla, 0x33, Mode::IndirectY, 2, 8),
+    //SRE_SET
+    Instruction::new(Name::Sre, 0x47, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Sre, 0x57, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Sre, 0x4F, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Sre, 0x5F, Mode::AbsoluteX, 3, 7),
+    Instruction::new(Name::Sre, 0x5B, Mode::AbsoluteY, 3, 7),
+    Instruction::new(Name::Sre, 0x43, Mode::IndirectX, 2, 8),
+    Instruction::new(Name::Sre, 0x53, Mode::IndirectY, 2, 8),
+    //RRA_SET
+    Instruction::new(Name::Rra, 0x67, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Rra, 0x77, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Rra, 0x6F, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Rra, 0x7F, Mode::AbsoluteX, 3, 7),
+    Instruction::new(Name::Rra, 0x7B, Mode::AbsoluteY, 3, 7),
+    Instruction::new(Name::Rra, 0x63, Mode::IndirectX, 2, 8),
+    Instruction::new(Name::Rra, 0x73, Mode::IndirectY, 2, 8),
+    //DCP_SET
+    Instruction::new(Name::Dcp, 0xC7, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Dcp, 0xD7, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Dcp, 0xCF, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Dcp, 0xDF, Mode::AbsoluteX, 3, 7),
+    Instruction::new(Name::Dcp, 0xDB, Mode::AbsoluteY, 3, 7),
+    Instruction::new(Name::Dcp, 0xC3, Mode::IndirectX, 2, 8),
+    Instruction::new(Name::Dcp, 0xD3, Mode::IndirectY, 2, 8),
+    //ISC_SET
+    Instruction::new(Name::Isc, 0xE7, Mode::ZeroPage, 2, 5),
+    Instruction::new(Name::Isc, 0xF7, Mode::ZeroPageX, 2, 6),
+    Instruction::new(Name::Isc, 0xEF, Mode::Absolute, 3, 6),
+    Instruction::new(Name::Isc, 0xFF, Mode::AbsoluteX, 3, 7),
+    Instruction::new(Name::Isc, 0xFB, Mode::AbsoluteY, 3, 7),
+    Instruction::new(Name::Isc, 0xE3, Mode::IndirectX, 2, 8),
+    Instruction::new(Name::Isc, 0xF3, Mode::IndirectY, 2, 8),
+    //UNOFFICIAL_IMMEDIATE_SET
+    Instruction::new(Name::Anc, 0x0B, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Anc, 0x2B, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Alr, 0x4B, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Arr, 0x6B, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Axs, 0xCB, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Sbc, 0xEB, Mode::Immediate, 2, 2),
+    Instruction::new(Name::Xaa, 0x8B, Mode::Immediate, 2, 2),
+    //UNSTABLE_STORE_SET
+    Instruction::new(Name::Sha, 0x93, Mode::IndirectY, 2, 6),
+    Instruction::new(Name::Sha, 0x9F, Mode::AbsoluteY, 3, 5),
+    Instruction::new(Name::Shy, 0x9C, Mode::AbsoluteX, 3, 5),
+    Instruction::new(Name::Shx, 0x9E, Mode::AbsoluteY, 3, 5),
+    Instruction::new(Name::Tas, 0x9B, Mode::AbsoluteY, 3, 5),
+    Instruction::new(Name::Las, 0xBB, Mode::AbsoluteY, 3, 4),
 ];
 
 lazy_static! {
@@ -318,6 +564,11 @@ mod tests {
         assert_eq!(Name::Brk, INSTRUCTION_MAP.get(&0x00).unwrap().name)
     }
 
+    #[test]
+    fn all_opcodes_are_defined() {
+        assert_eq!(256, INSTRUCTION_MAP.len());
+    }
+
     #[test]
     fn all_modes_and_sizes_are_coherent() {
         let bad_instructions: Vec<&&Instruction> = INSTRUCTION_MAP
@@ -1,27 +1,77 @@
+pub mod disasm;
 mod instruction;
 mod register;
+mod trace;
 
-use instruction::INSTRUCTION_MAP;
+use instruction::{Access, INSTRUCTION_MAP};
+pub use instruction::{Mode, Name};
+pub use register::Status;
+pub use trace::Tracer;
 
+use crate::bus_log::AccessKind;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
 use crate::traits::Memory;
 
+pub const NMI_VECTOR: u16 = 0xFFFA;
 pub const PROGRAM_POINTER: u16 = 0xFFFC;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
 const STACK_ADDR_HI: register::StackPointer = 0x01;
 pub const STACK_TOP: register::StackPointer = 0xFF;
+/// The power-up runs a RESET sequence from a cleared stack pointer.
+pub const POWER_UP_STACK_POINTER: register::StackPointer = 0xFD;
 const IMPLICIT_MODE_ADDR: u16 = u16::MAX;
+/// RESET decrements the stack pointer like an interrupt without writing.
+const RESET_STACK_DECREMENT: u8 = 3;
+/// A jammed CPU stops fetching but the clock keeps running.
+const JAMMED_CYCLES: u32 = 1;
+/// Value ORed to A by the unstable LXA and XAA, it depends on the chip.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+const INTERRUPT_CYCLES: u32 = 7;
+const BRANCH_TAKEN_CYCLES: u32 = 1;
+const PAGE_CROSS_CYCLES: u32 = 1;
+
+/// Snapshot of the programmer visible registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+}
 
-pub struct Cpu {
+/// MOS 6502 core of the Ricoh 2A03, it owns the bus it drives.
+pub struct Cpu<M> {
     counter: register::ProgramCounter,
     stack_pointer: register::StackPointer,
     a: register::A,
     x: register::X,
     y: register::Y,
     status: register::Status,
-    memory: *mut dyn Memory,
+    irq_line: bool,
+    nmi_line: bool,
+    is_nmi_pending: bool,
+    reset_line: bool,
+    is_reset_pending: bool,
+    /// Interrupt disable flag as seen by the last interrupt polling.
+    is_irq_masked: bool,
+    is_polling_delayed: bool,
+    has_executed_brk: bool,
+    is_jammed: bool,
+    is_page_crossed: bool,
+    /// The instruction moved the program counter, a jump may target the
+    /// byte following its opcode.
+    has_branched: bool,
+    cycles: u64,
+    /// Bus accesses since the start of the instruction, one per cycle.
+    bus_cycle: u32,
+    tracer: Option<Tracer>,
+    memory: M,
 }
 
-impl Cpu {
-    pub fn new(memory: *mut dyn Memory) -> Self {
+impl<M: Memory> Cpu<M> {
+    pub fn new(memory: M) -> Self {
         Self {
             counter: 0,
             stack_pointer: STACK_TOP,
@@ -29,46 +79,186 @@ impl Cpu {
             x: 0,
             y: 0,
             status: register::Status::INITIAL_STATE,
+            irq_line: false,
+            nmi_line: false,
+            is_nmi_pending: false,
+            reset_line: false,
+            is_reset_pending: false,
+            is_irq_masked: true,
+            is_polling_delayed: false,
+            has_executed_brk: false,
+            is_jammed: false,
+            is_page_crossed: false,
+            has_branched: false,
+            cycles: 0,
+            bus_cycle: 0,
+            tracer: None,
             memory,
         }
     }
 
-    pub fn set_mem(&mut self, memory: *mut dyn Memory) {
-        self.memory = memory;
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    pub fn into_memory(self) -> M {
+        self.memory
+    }
+
+    /// Level of the IRQ line, shared by all the devices able to interrupt.
+    pub fn set_irq(&mut self, level: bool) {
+        self.irq_line = level;
+    }
+
+    /// Level of the NMI line, the interrupt is latched on the rising edge.
+    pub fn set_nmi(&mut self, level: bool) {
+        if level && !self.nmi_line {
+            self.is_nmi_pending = true;
+        }
+        self.nmi_line = level;
+    }
+
+    /// Level of the RESET line, the CPU restarts from $FFFC once it is asserted.
+    pub fn set_reset(&mut self, level: bool) {
+        if level && !self.reset_line {
+            self.is_reset_pending = true;
+        }
+        self.reset_line = level;
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn reset(&mut self) {
-        self.counter = (*self.memory).mem_read_u16(PROGRAM_POINTER);
-        self.stack_pointer = STACK_TOP;
+    /// Power-up state, see `set_reset` for the RESET line.
+    pub fn reset(&mut self) {
+        self.bus_cycle = 0;
+        self.counter = self.read_u16(PROGRAM_POINTER, AccessKind::Data);
+        self.stack_pointer = POWER_UP_STACK_POINTER;
         self.a = 0;
         self.x = 0;
         self.y = 0;
         self.status = register::Status::INITIAL_STATE;
+        self.is_irq_masked = true;
+        self.is_polling_delayed = false;
+        self.is_jammed = false;
+        self.cycles += u64::from(INTERRUPT_CYCLES);
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.counter
+    }
+
+    /// Moves the execution, like the nestest automation mode starting at $C000.
+    pub fn set_program_counter(&mut self, addr: u16) {
+        self.counter = addr;
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn run_loop(&mut self) {
-        while self.run() {}
+    pub fn registers(&self) -> Registers {
+        Registers {
+            program_counter: self.counter,
+            stack_pointer: self.stack_pointer,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.status.bits(),
+        }
+    }
+
+    /// Loads a whole CPU state, like the initial state of a conformance test.
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.counter = registers.program_counter;
+        self.stack_pointer = registers.stack_pointer;
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.status = register::Status::from_bits_truncate(registers.status);
+        self.is_irq_masked = self.status.is_set(register::Status::INTERRUPT_DISABLE);
+    }
+
+    /// Cycles elapsed since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// A JAM opcode froze the CPU, only RESET recovers from it.
+    pub fn is_jammed(&self) -> bool {
+        self.is_jammed
+    }
+
+    /// Traces every executed instruction into the tracer sink, see `Tracer`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Runs until a BRK is executed or the CPU jams, the BRK still vectors
+    /// through $FFFE like `run` does. Meant for programs ending on a BRK.
+    pub fn run_until_brk(&mut self) {
+        loop {
+            self.run();
+            if self.has_executed_brk || self.is_jammed {
+                break;
+            }
+        }
+    }
+
+    /// Executes one instruction or interrupt and returns the cycles it took.
+    pub fn run(&mut self) -> u32 {
+        self.bus_cycle = 0;
+        let cycles = self.execute();
+        self.cycles += u64::from(cycles);
+        cycles
     }
 
-    #[allow(clippy::missing_safety_doc)]
     #[rustfmt::skip]
-    pub unsafe fn run(&mut self) -> bool
+    fn execute(&mut self) -> u32
     {
-        let opcode = &(*self.memory).mem_read_u8(self.counter);
+        self.has_executed_brk = false;
+        if self.is_jammed && !self.is_reset_pending {
+            return JAMMED_CYCLES;
+        }
+        if self.poll_interrupts() {
+            return INTERRUPT_CYCLES;
+        }
+        self.trace();
+        let opcode = &self.read(self.counter, AccessKind::OpcodeFetch);
         let instruct = INSTRUCTION_MAP.get(opcode).unwrap();
         if instruct.opcode == 0 {
             self.brk();
-            return false;
+            self.is_irq_masked = true;
+            self.has_executed_brk = true;
+            return u32::from(instruct.cycles);
         }
-        self.counter += 1;
+        let was_irq_masked = self.status.is_set(register::Status::INTERRUPT_DISABLE);
+        self.counter = self.counter.wrapping_add(1);
         let previous_position = self.counter;
-        let addr = self.get_operand_address(&instruct.mode);
-        let operand = if addr != IMPLICIT_MODE_ADDR {
-            (*self.memory).mem_read_u8(addr)
-        } else {
-            0
+        self.is_page_crossed = false;
+        self.has_branched = false;
+        let addr = match instruct.name {
+            // JSR pushes the return address between the bytes of its operand
+            instruction::Name::Jsr => IMPLICIT_MODE_ADDR,
+            _ => self.get_operand_address(instruct),
+        };
+        let mut cycles = u32::from(instruct.cycles);
+        if self.is_page_crossed && instruct.has_page_cross_penalty() {
+            cycles += PAGE_CROSS_CYCLES;
+        }
+        let operand = match instruct.access() {
+            Access::Read if instruct.mode == instruction::Mode::Immediate => {
+                self.read(addr, AccessKind::Operand)
+            }
+            Access::Read => self.read(addr, AccessKind::Data),
+            Access::ReadModifyWrite => {
+                // the unmodified value is written back while the ALU works
+                let operand = self.read(addr, AccessKind::Data);
+                self.write(addr, operand, AccessKind::Dummy);
+                operand
+            }
+            Access::Write | Access::None => 0,
         };
         match instruct.name {
             instruction::Name::Adc => self.adc(operand),//tested
@@ -100,7 +290,7 @@ impl Cpu {
             instruction::Name::Inx => self.inx(),//tested
             instruction::Name::Iny => self.iny(),//tested
             instruction::Name::Jmp => self.jmp(addr),
-            instruction::Name::Jsr => self.jsr(addr),
+            instruction::Name::Jsr => self.jsr(),
             instruction::Name::Lda => self.lda(operand),//tested
             instruction::Name::Ldx => self.ldx(operand),//tested
             instruction::Name::Ldy => self.ldy(operand),//tested
@@ -134,55 +324,160 @@ impl Cpu {
             instruction::Name::Txa => self.txa(),//tested
             instruction::Name::Txs => self.txs(),
             instruction::Name::Tya => self.tya(),//tested
-            _ => todo!()
+            instruction::Name::Lax => self.lax(operand),
+            instruction::Name::Lxa => self.lxa(operand),
+            instruction::Name::Sax => self.sax(addr),
+            instruction::Name::Dcp => self.dcp(operand, addr),
+            instruction::Name::Isc => self.isc(operand, addr),
+            instruction::Name::Slo => self.slo(operand, addr),
+            instruction::Name::Rla => self.rla(operand, addr),
+            instruction::Name::Sre => self.sre(operand, addr),
+            instruction::Name::Rra => self.rra(operand, addr),
+            instruction::Name::Anc => self.anc(operand),
+            instruction::Name::Alr => self.alr(operand),
+            instruction::Name::Arr => self.arr(operand),
+            instruction::Name::Axs => self.axs(operand),
+            instruction::Name::Xaa => self.xaa(operand),
+            instruction::Name::Sha => self.sha(addr),
+            instruction::Name::Shx => self.shx(addr),
+            instruction::Name::Shy => self.shy(addr),
+            instruction::Name::Tas => self.tas(addr),
+            instruction::Name::Las => self.las(operand),
+            instruction::Name::Jam => self.jam(),
+            instruction::Name::Brk => unreachable!(),
+        }
+        if !self.has_branched {
+            self.counter = self.counter.wrapping_add(u16::from(instruct.len - 1));
+        } else if instruct.mode == instruction::Mode::Relative {
+            cycles += BRANCH_TAKEN_CYCLES;
+            let next_instruction = previous_position.wrapping_add(1);
+            if next_instruction & 0xFF00 != self.counter & 0xFF00 {
+                cycles += PAGE_CROSS_CYCLES;
+            } else {
+                // a taken branch that stays in its page polls before its last cycle
+                self.is_polling_delayed = true;
+            }
         }
-        if !self.has_branched(previous_position) {
-            self.counter += u16::from(instruct.len - 1);
+        // CLI, SEI and PLP change the flag after the interrupts are polled
+        self.is_irq_masked = match instruct.name {
+            instruction::Name::Cli | instruction::Name::Sei | instruction::Name::Plp => was_irq_masked,
+            _ => self.status.is_set(register::Status::INTERRUPT_DISABLE),
+        };
+        cycles
+    }
+
+    /// Services the pending interrupt by priority, the lines are polled
+    /// at the end of the previous instruction.
+    /// https://www.nesdev.org/wiki/CPU_interrupts
+    fn poll_interrupts(&mut self) -> bool {
+        if self.is_reset_pending {
+            self.is_reset_pending = false;
+            self.is_jammed = false;
+            self.read(self.counter, AccessKind::Dummy);
+            self.read(self.counter, AccessKind::Dummy);
+            // the pushes are reads while RESET is asserted
+            for _ in 0..RESET_STACK_DECREMENT {
+                self.read(self.get_stack_addr(), AccessKind::Dummy);
+                self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+            }
+            self.status.insert(register::Status::INTERRUPT_DISABLE);
+            self.counter = self.read_u16(PROGRAM_POINTER, AccessKind::Data);
+        } else if self.is_polling_delayed {
+            self.is_polling_delayed = false;
+            return false;
+        } else if self.is_nmi_pending {
+            self.is_nmi_pending = false;
+            self.interrupt(NMI_VECTOR);
+        } else if self.irq_line && !self.is_irq_masked {
+            self.interrupt(IRQ_VECTOR);
+        } else {
+            return false;
         }
-        return true;
-    }
-
-    unsafe fn get_operand_address(&mut self, mode: &instruction::Mode) -> u16 {
-        match mode {
-            instruction::Mode::Absolute => (*self.memory).mem_read_u16(self.counter),
-            instruction::Mode::AbsoluteX => (*self.memory)
-                .mem_read_u16(self.counter)
-                .wrapping_add(self.x as u16),
-            instruction::Mode::AbsoluteY => (*self.memory)
-                .mem_read_u16(self.counter)
-                .wrapping_add(self.y as u16),
+        self.is_irq_masked = true;
+        true
+    }
+
+    /// Effective address of `instruct`, with the dummy reads of the
+    /// addressing mode.
+    /// https://www.nesdev.org/6502_cpu.txt
+    fn get_operand_address(&mut self, instruct: &instruction::Instruction) -> u16 {
+        match instruct.mode {
+            instruction::Mode::Absolute => self.read_u16(self.counter, AccessKind::Operand),
+            instruction::Mode::AbsoluteX => {
+                let base = self.read_u16(self.counter, AccessKind::Operand);
+                self.indexed_address(base, self.x, instruct)
+            }
+            instruction::Mode::AbsoluteY => {
+                let base = self.read_u16(self.counter, AccessKind::Operand);
+                self.indexed_address(base, self.y, instruct)
+            }
             instruction::Mode::Indirect => {
-                let addr = (*self.memory).mem_read_u16(self.counter);
-                (*self.memory).mem_read_u16(addr)
+                // the pointer high byte is fetched without carrying into the next page
+                let addr = self.read_u16(self.counter, AccessKind::Operand);
+                let hi_addr = (addr & 0xFF00) | u16::from((addr as u8).wrapping_add(1));
+                u16::from_le_bytes([
+                    self.read(addr, AccessKind::Data),
+                    self.read(hi_addr, AccessKind::Data),
+                ])
             }
             instruction::Mode::IndirectX => {
-                let addr = (*self.memory)
-                    .mem_read_u8(self.counter)
-                    .wrapping_add(self.x);
-                (*self.memory).mem_read_u16(addr as u16)
+                let pointer = self.read(self.counter, AccessKind::Operand);
+                self.read(u16::from(pointer), AccessKind::Dummy);
+                self.read_zero_page_u16(pointer.wrapping_add(self.x))
             }
             instruction::Mode::IndirectY => {
-                let addr =
-                    (*self.memory).mem_read_u16((*self.memory).mem_read_u8(self.counter) as u16);
-                addr.wrapping_add(self.y as u16)
+                let pointer = self.read(self.counter, AccessKind::Operand);
+                let base = self.read_zero_page_u16(pointer);
+                self.indexed_address(base, self.y, instruct)
+            }
+            instruction::Mode::ZeroPage => u16::from(self.read(self.counter, AccessKind::Operand)),
+            instruction::Mode::ZeroPageX => {
+                let base = self.read(self.counter, AccessKind::Operand);
+                self.read(u16::from(base), AccessKind::Dummy);
+                u16::from(base.wrapping_add(self.x))
+            }
+            instruction::Mode::ZeroPageY => {
+                let base = self.read(self.counter, AccessKind::Operand);
+                self.read(u16::from(base), AccessKind::Dummy);
+                u16::from(base.wrapping_add(self.y))
             }
-            instruction::Mode::ZeroPage => (*self.memory).mem_read_u8(self.counter) as u16,
-            instruction::Mode::ZeroPageX => (*self.memory)
-                .mem_read_u8(self.counter)
-                .wrapping_add(self.x) as u16,
-            instruction::Mode::ZeroPageY => (*self.memory)
-                .mem_read_u8(self.counter)
-                .wrapping_add(self.y) as u16,
             instruction::Mode::Immediate => self.counter,
             instruction::Mode::Relative => {
-                let offset = (*self.memory).mem_read_u8(self.counter) as i8;
+                let offset = self.read(self.counter, AccessKind::Operand) as i8;
                 self.counter.wrapping_add(1).wrapping_add(offset as u16)
             }
-            instruction::Mode::Implicit => IMPLICIT_MODE_ADDR,
-            instruction::Mode::Accumulator => IMPLICIT_MODE_ADDR,
+            instruction::Mode::Implicit | instruction::Mode::Accumulator => {
+                // the byte after the opcode is fetched and ignored
+                self.read(self.counter, AccessKind::Dummy);
+                IMPLICIT_MODE_ADDR
+            }
         }
     }
 
+    /// Pointers stored at $FF wrap to $00 for their high byte.
+    fn read_zero_page_u16(&mut self, addr: u8) -> u16 {
+        u16::from_le_bytes([
+            self.read(u16::from(addr), AccessKind::Data),
+            self.read(u16::from(addr.wrapping_add(1)), AccessKind::Data),
+        ])
+    }
+
+    /// The low byte is indexed first, the address is read before the high
+    /// byte is fixed when the page is crossed, and always for the writes.
+    fn indexed_address(
+        &mut self,
+        base: u16,
+        index: u8,
+        instruct: &instruction::Instruction,
+    ) -> u16 {
+        let addr = base.wrapping_add(u16::from(index));
+        self.is_page_crossed = base & 0xFF00 != addr & 0xFF00;
+        if self.is_page_crossed || instruct.access() != Access::Read {
+            self.read((base & 0xFF00) | (addr & 0x00FF), AccessKind::Dummy);
+        }
+        addr
+    }
+
     fn adc(&mut self, operand: u8) {
         // no decimal mode.
         let sum = self.a as u16
@@ -208,12 +503,12 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn asl(&mut self, operand: u8, addr: u16) {
+    fn asl(&mut self, operand: u8, addr: u16) {
         self.status
             .set_or_unset_if(register::Status::CARRY, || operand >> 7 == 1);
         let res = operand << 1;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res)
+        self.write(addr, res, AccessKind::Data)
     }
 
     fn asl_a(&mut self) {
@@ -223,14 +518,13 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    #[allow(clippy::bad_bit_mask)]
     fn bit(&mut self, operand: u8) {
         self.status
             .set_or_unset_if(register::Status::ZERO, || self.a & operand == 0);
         self.status
-            .set_or_unset_if(register::Status::NEGATIVE, || operand & 0b1000_0000 == 1);
+            .set_or_unset_if(register::Status::NEGATIVE, || operand & 0b1000_0000 != 0);
         self.status
-            .set_or_unset_if(register::Status::OVERFLOW, || operand & 0b0100_0000 == 1);
+            .set_or_unset_if(register::Status::OVERFLOW, || operand & 0b0100_0000 != 0);
     }
 
     fn bcc(&mut self, addr: u16) {
@@ -257,12 +551,25 @@ impl Cpu {
         self.branch_if(addr, |status| status.is_unset(register::Status::NEGATIVE))
     }
 
-    unsafe fn brk(&mut self) {
+    fn brk(&mut self) {
         //https://www.nesdev.org/wiki/Status_flags
-        self.push_u16_on_stack(self.counter);
+        // the byte following BRK is read and skipped
+        self.read(self.counter.wrapping_add(1), AccessKind::Dummy);
+        self.push_u16_on_stack(self.counter.wrapping_add(2));
         self.push_u8_on_stack((self.status | register::Status::BREAK).bits());
-        // IRQ interrput vector loaded in program counter
-        // self.counter += 1;
+        self.status.insert(register::Status::INTERRUPT_DISABLE);
+        self.counter = self.read_u16(IRQ_VECTOR, AccessKind::Data);
+    }
+
+    fn interrupt(&mut self, vector: u16) {
+        //https://www.nesdev.org/wiki/CPU_interrupts
+        // the opcode fetch is discarded
+        self.read(self.counter, AccessKind::Dummy);
+        self.read(self.counter, AccessKind::Dummy);
+        self.push_u16_on_stack(self.counter);
+        self.push_u8_on_stack((self.status - register::Status::BREAK).bits());
+        self.status.insert(register::Status::INTERRUPT_DISABLE);
+        self.counter = self.read_u16(vector, AccessKind::Data);
     }
 
     fn bvc(&mut self, addr: u16) {
@@ -301,9 +608,9 @@ impl Cpu {
         self.compare(self.y, operand);
     }
 
-    unsafe fn dec(&mut self, operand: u8, addr: u16) {
+    fn dec(&mut self, operand: u8, addr: u16) {
         let val = operand.wrapping_sub(1);
-        (*self.memory).mem_write_u8(addr, val);
+        self.write(addr, val, AccessKind::Data);
         self.set_negative_and_zero_flags(val);
     }
 
@@ -322,9 +629,9 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn inc(&mut self, operand: u8, addr: u16) {
+    fn inc(&mut self, operand: u8, addr: u16) {
         let val = operand.wrapping_add(1);
-        (*self.memory).mem_write_u8(addr, val);
+        self.write(addr, val, AccessKind::Data);
         self.set_negative_and_zero_flags(val);
     }
 
@@ -342,9 +649,12 @@ impl Cpu {
         self.branch(addr);
     }
 
-    unsafe fn jsr(&mut self, addr: u16) {
+    fn jsr(&mut self) {
+        let lo = self.read(self.counter, AccessKind::Operand);
+        self.read(self.get_stack_addr(), AccessKind::Dummy);
         self.push_u16_on_stack(self.counter.wrapping_add(1));
-        self.branch(addr);
+        let hi = self.read(self.counter.wrapping_add(1), AccessKind::Operand);
+        self.branch(u16::from_le_bytes([lo, hi]));
     }
 
     fn lda(&mut self, operand: u8) {
@@ -362,12 +672,12 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.y);
     }
 
-    unsafe fn lsr(&mut self, operand: u8, addr: u16) {
+    fn lsr(&mut self, operand: u8, addr: u16) {
         self.status
             .set_or_unset_if(register::Status::CARRY, || operand & 1 == 1);
         let res = operand >> 1;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res);
+        self.write(addr, res, AccessKind::Data);
     }
 
     fn lsr_a(&mut self) {
@@ -384,27 +694,30 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn pha(&mut self) {
+    fn pha(&mut self) {
         self.push_u8_on_stack(self.a);
     }
 
-    unsafe fn php(&mut self) {
+    fn php(&mut self) {
         //https://www.nesdev.org/wiki/Status_flags
         self.push_u8_on_stack((self.status | register::Status::BREAK).bits());
     }
 
-    unsafe fn pla(&mut self) {
+    fn pla(&mut self) {
+        self.read(self.get_stack_addr(), AccessKind::Dummy);
         self.a = self.pull_u8_from_stack();
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn plp(&mut self) {
+    fn plp(&mut self) {
+        self.read(self.get_stack_addr(), AccessKind::Dummy);
         //https://www.nesdev.org/wiki/Status_flags
-        self.status = register::Status::from_bits(self.pull_u8_from_stack()).unwrap()
-            & !register::Status::BREAK;
+        self.status = (register::Status::from_bits_truncate(self.pull_u8_from_stack())
+            - register::Status::BREAK)
+            | register::Status::UNUSED;
     }
 
-    unsafe fn rol(&mut self, operand: u8, addr: u16) {
+    fn rol(&mut self, operand: u8, addr: u16) {
         let carry = if self.status.is_set(register::Status::CARRY) {
             1
         } else {
@@ -414,7 +727,7 @@ impl Cpu {
             .set_or_unset_if(register::Status::CARRY, || operand >> 7 == 1);
         let res = (operand << 1) | carry;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res);
+        self.write(addr, res, AccessKind::Data);
     }
 
     fn rol_a(&mut self) {
@@ -429,7 +742,7 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a)
     }
 
-    unsafe fn ror(&mut self, operand: u8, addr: u16) {
+    fn ror(&mut self, operand: u8, addr: u16) {
         let carry = if self.status.is_set(register::Status::CARRY) {
             1
         } else {
@@ -439,7 +752,7 @@ impl Cpu {
             .set_or_unset_if(register::Status::CARRY, || operand & 1 == 1);
         let res = operand >> 1 | carry << 7;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res)
+        self.write(addr, res, AccessKind::Data)
     }
 
     fn ror_a(&mut self) {
@@ -454,17 +767,22 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a)
     }
 
-    unsafe fn rti(&mut self) {
+    fn rti(&mut self) {
+        self.read(self.get_stack_addr(), AccessKind::Dummy);
         //https://www.nesdev.org/wiki/Status_flags
-        self.status = register::Status::from_bits(self.pull_u8_from_stack()).unwrap()
-            & !register::Status::BREAK;
+        self.status = (register::Status::from_bits_truncate(self.pull_u8_from_stack())
+            - register::Status::BREAK)
+            | register::Status::UNUSED;
         let addr = self.pull_u16_from_stack();
         self.branch(addr)
     }
 
-    unsafe fn rts(&mut self) {
-        let addr = self.pull_u16_from_stack().wrapping_add(1);
-        self.branch(addr);
+    fn rts(&mut self) {
+        self.read(self.get_stack_addr(), AccessKind::Dummy);
+        let addr = self.pull_u16_from_stack();
+        // the program counter is incremented past the JSR after a read
+        self.read(addr, AccessKind::Dummy);
+        self.branch(addr.wrapping_add(1));
     }
 
     fn sbc(&mut self, operand: u8) {
@@ -483,16 +801,16 @@ impl Cpu {
         self.status.insert(register::Status::INTERRUPT_DISABLE)
     }
 
-    unsafe fn sta(&mut self, addr: u16) {
-        (*self.memory).mem_write_u8(addr, self.a)
+    fn sta(&mut self, addr: u16) {
+        self.write(addr, self.a, AccessKind::Data)
     }
 
-    unsafe fn stx(&mut self, addr: u16) {
-        (*self.memory).mem_write_u8(addr, self.x)
+    fn stx(&mut self, addr: u16) {
+        self.write(addr, self.x, AccessKind::Data)
     }
 
-    unsafe fn sty(&mut self, addr: u16) {
-        (*self.memory).mem_write_u8(addr, self.y)
+    fn sty(&mut self, addr: u16) {
+        self.write(addr, self.y, AccessKind::Data)
     }
 
     fn tax(&mut self) {
@@ -505,7 +823,7 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.y);
     }
 
-    unsafe fn tsx(&mut self) {
+    fn tsx(&mut self) {
         self.x = self.stack_pointer;
         self.set_negative_and_zero_flags(self.x)
     }
@@ -515,7 +833,7 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn txs(&mut self) {
+    fn txs(&mut self) {
         self.stack_pointer = self.x;
     }
 
@@ -524,6 +842,132 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
+    fn lax(&mut self, operand: u8) {
+        self.a = operand;
+        self.x = operand;
+        self.set_negative_and_zero_flags(operand);
+    }
+
+    fn lxa(&mut self, operand: u8) {
+        self.lax((self.a | UNSTABLE_MAGIC) & operand);
+    }
+
+    fn sax(&mut self, addr: u16) {
+        self.write(addr, self.a & self.x, AccessKind::Data)
+    }
+
+    fn dcp(&mut self, operand: u8, addr: u16) {
+        let val = operand.wrapping_sub(1);
+        self.write(addr, val, AccessKind::Data);
+        self.compare(self.a, val);
+    }
+
+    fn isc(&mut self, operand: u8, addr: u16) {
+        let val = operand.wrapping_add(1);
+        self.write(addr, val, AccessKind::Data);
+        self.sbc(val);
+    }
+
+    fn slo(&mut self, operand: u8, addr: u16) {
+        self.asl(operand, addr);
+        self.ora(operand << 1);
+    }
+
+    fn rla(&mut self, operand: u8, addr: u16) {
+        let carry = u8::from(self.status.is_set(register::Status::CARRY));
+        self.rol(operand, addr);
+        self.and((operand << 1) | carry);
+    }
+
+    fn sre(&mut self, operand: u8, addr: u16) {
+        self.lsr(operand, addr);
+        self.eor(operand >> 1);
+    }
+
+    fn rra(&mut self, operand: u8, addr: u16) {
+        let carry = u8::from(self.status.is_set(register::Status::CARRY));
+        self.ror(operand, addr);
+        self.adc((operand >> 1) | (carry << 7));
+    }
+
+    fn anc(&mut self, operand: u8) {
+        self.and(operand);
+        let is_negative = self.status.is_set(register::Status::NEGATIVE);
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || is_negative);
+    }
+
+    fn alr(&mut self, operand: u8) {
+        self.and(operand);
+        self.lsr_a();
+    }
+
+    fn arr(&mut self, operand: u8) {
+        self.and(operand);
+        self.ror_a();
+        let res = self.a;
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || res & 0b0100_0000 != 0);
+        self.status.set_or_unset_if(register::Status::OVERFLOW, || {
+            ((res >> 6) ^ (res >> 5)) & 1 == 1
+        });
+    }
+
+    fn axs(&mut self, operand: u8) {
+        let lhs = self.a & self.x;
+        self.x = lhs.wrapping_sub(operand);
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || lhs >= operand);
+        self.set_negative_and_zero_flags(self.x);
+    }
+
+    fn xaa(&mut self, operand: u8) {
+        self.a = (self.a | UNSTABLE_MAGIC) & self.x & operand;
+        self.set_negative_and_zero_flags(self.a);
+    }
+
+    /// SHA, SHX, SHY and TAS store `value` ANDed with the high byte of the
+    /// base address plus one, a page crossing corrupts the high byte of the target.
+    fn unstable_store(&mut self, addr: u16, index: u8, value: u8) {
+        let base_hi = (addr.wrapping_sub(u16::from(index)) >> 8) as u8;
+        let value = value & base_hi.wrapping_add(1);
+        let addr = if self.is_page_crossed {
+            (u16::from(value) << 8) | (addr & 0x00FF)
+        } else {
+            addr
+        };
+        self.write(addr, value, AccessKind::Data)
+    }
+
+    fn sha(&mut self, addr: u16) {
+        self.unstable_store(addr, self.y, self.a & self.x)
+    }
+
+    fn shx(&mut self, addr: u16) {
+        self.unstable_store(addr, self.y, self.x)
+    }
+
+    fn shy(&mut self, addr: u16) {
+        self.unstable_store(addr, self.x, self.y)
+    }
+
+    fn tas(&mut self, addr: u16) {
+        self.stack_pointer = self.a & self.x;
+        self.unstable_store(addr, self.y, self.stack_pointer)
+    }
+
+    fn las(&mut self, operand: u8) {
+        let val = operand & self.stack_pointer;
+        self.stack_pointer = val;
+        self.lax(val);
+    }
+
+    fn jam(&mut self) {
+        // the program counter stays on the JAM opcode
+        self.counter = self.counter.wrapping_sub(1);
+        self.is_jammed = true;
+    }
+
     fn set_negative_and_zero_flags(&mut self, operation_res: u8) {
         self.status
             .set_or_unset_if(register::Status::NEGATIVE, || (operation_res as i8) < 0);
@@ -531,49 +975,76 @@ impl Cpu {
             .set_or_unset_if(register::Status::ZERO, || operation_res == 0);
     }
 
+    /// A taken branch fetches the next opcode, and the target with the
+    /// unfixed high byte when it crosses a page.
     fn branch_if(&mut self, addr: u16, predicate: impl Fn(&register::Status) -> bool) {
         if predicate(&self.status) {
+            let next_instruction = self.counter.wrapping_add(1);
+            self.read(next_instruction, AccessKind::Dummy);
+            if next_instruction & 0xFF00 != addr & 0xFF00 {
+                self.read(
+                    (next_instruction & 0xFF00) | (addr & 0x00FF),
+                    AccessKind::Dummy,
+                );
+            }
             self.branch(addr)
         }
     }
 
     fn branch(&mut self, addr: u16) {
         self.counter = addr;
-    }
-
-    fn has_branched(&self, previous_addr: u16) -> bool {
-        previous_addr != self.counter
+        self.has_branched = true;
     }
 
     fn compare(&mut self, lhs: u8, rhs: u8) {
         let val = lhs.wrapping_sub(rhs);
         self.status
-            .set_or_unset_if(register::Status::CARRY, || val as i8 >= 0);
+            .set_or_unset_if(register::Status::CARRY, || lhs >= rhs);
         self.set_negative_and_zero_flags(val);
     }
 
-    unsafe fn push_u8_on_stack(&mut self, byte: u8) {
-        (*self.memory).mem_write_u8(self.get_stack_addr(), byte);
-        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    /// Labels the access for the bus log, the 6502 accesses the bus on
+    /// every cycle.
+    fn set_access_context(&mut self, kind: AccessKind) {
+        let cycle = self.cycles + u64::from(self.bus_cycle);
+        self.memory.set_access_context(kind, cycle);
+        self.bus_cycle += 1;
     }
 
-    unsafe fn push_u16_on_stack(&mut self, addr: u16) {
-        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
-        (*self.memory).mem_write_u16(self.get_stack_addr(), addr);
+    fn read(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        self.set_access_context(kind);
+        self.memory.mem_read_u8(addr)
+    }
+
+    fn read_u16(&mut self, addr: u16, kind: AccessKind) -> u16 {
+        u16::from_le_bytes([self.read(addr, kind), self.read(addr.wrapping_add(1), kind)])
+    }
+
+    fn write(&mut self, addr: u16, data: u8, kind: AccessKind) {
+        self.set_access_context(kind);
+        self.memory.mem_write_u8(addr, data)
+    }
+
+    fn push_u8_on_stack(&mut self, byte: u8) {
+        self.write(self.get_stack_addr(), byte, AccessKind::Stack);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
-    unsafe fn pull_u8_from_stack(&mut self) -> u8 {
-        self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        let res = (*self.memory).mem_read_u8(self.get_stack_addr());
-        res
+    /// The high byte goes first, the stack wraps within page 1.
+    fn push_u16_on_stack(&mut self, addr: u16) {
+        let [lo, hi] = addr.to_le_bytes();
+        self.push_u8_on_stack(hi);
+        self.push_u8_on_stack(lo);
     }
 
-    unsafe fn pull_u16_from_stack(&mut self) -> u16 {
+    fn pull_u8_from_stack(&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        let res = (*self.memory).mem_read_u16(self.get_stack_addr());
-        self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        res
+        self.read(self.get_stack_addr(), AccessKind::Stack)
+    }
+
+    fn pull_u16_from_stack(&mut self) -> u16 {
+        let lo = self.pull_u8_from_stack();
+        u16::from_le_bytes([lo, self.pull_u8_from_stack()])
     }
 
     pub fn get_stack_addr(&self) -> u16 {
@@ -581,5 +1052,56 @@ impl Cpu {
     }
 }
 
+impl<M> Snapshot for Cpu<M> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+        writer.write_u8(self.stack_pointer);
+        writer.write_u8(self.a);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u8(self.status.bits());
+        for flag in [
+            self.irq_line,
+            self.nmi_line,
+            self.is_nmi_pending,
+            self.reset_line,
+            self.is_reset_pending,
+            self.is_irq_masked,
+            self.is_polling_delayed,
+            self.has_executed_brk,
+            self.is_jammed,
+            self.is_page_crossed,
+        ] {
+            writer.write_bool(flag);
+        }
+        writer.write_u64(self.cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.counter = reader.read_u16()?;
+        self.stack_pointer = reader.read_u8()?;
+        self.a = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.status = register::Status::from_bits_truncate(reader.read_u8()?);
+        for flag in [
+            &mut self.irq_line,
+            &mut self.nmi_line,
+            &mut self.is_nmi_pending,
+            &mut self.reset_line,
+            &mut self.is_reset_pending,
+            &mut self.is_irq_masked,
+            &mut self.is_polling_delayed,
+            &mut self.has_executed_brk,
+            &mut self.is_jammed,
+            &mut self.is_page_crossed,
+        ] {
+            *flag = reader.read_bool()?;
+        }
+        self.cycles = reader.read_u64()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test;
@@ -1,35 +1,43 @@
 use super::*;
+use crate::debugger::Access as BusAccess;
 use crate::traits::Memory;
 
 struct MemoryMock {
-    pub memory: [u8; 0xFFFF],
+    pub memory: [u8; 0x10000],
+    pub accesses: Vec<(u16, u8, BusAccess)>,
 }
 
 impl MemoryMock {
     pub fn new(program: &[u8], origin: u16) -> Self {
         let mut mock = Self {
-            memory: [0x00; 0xFFFF],
+            memory: [0x00; 0x10000],
+            accesses: Vec::new(),
         };
-        unsafe {
-            mock.load(program, origin);
-            mock.mem_write_u16(0xFFFC, origin);
-        }
+        mock.load(program, origin);
+        mock.mem_write_u16(0xFFFC, origin);
         mock
     }
 }
 
 impl Memory for MemoryMock {
-    unsafe fn load(&mut self, data: &[u8], dest: u16) {
+    fn load(&mut self, data: &[u8], dest: u16) {
         self.memory[usize::from(dest)..usize::from(dest) + data.len()].copy_from_slice(data);
     }
 
-    unsafe fn mem_read_u8(&mut self, addr: u16) -> u8 {
-        self.memory[usize::from(addr)]
+    fn mem_read_u8(&mut self, addr: u16) -> u8 {
+        let byte = self.memory[usize::from(addr)];
+        self.accesses.push((addr, byte, BusAccess::Read));
+        byte
     }
 
-    unsafe fn mem_write_u8(&mut self, addr: u16, byte: u8) {
+    fn mem_write_u8(&mut self, addr: u16, byte: u8) {
+        self.accesses.push((addr, byte, BusAccess::Write));
         self.memory[usize::from(addr)] = byte
     }
+
+    fn peek_u8(&self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
+    }
 }
 
 fn create_mock_from_script(script: &str) -> MemoryMock {
@@ -70,196 +78,168 @@ end:
 
 #[test]
 fn lda_immediate_and_sta_zero_page() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #10 ; load 10 into register A
            STA $5  ; store register A at address 0x00 + 0x05 = 0x05
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(10, mock.memory[0x0005])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(10, cpu.memory().memory[0x0005])
 }
 
 #[test]
 fn lda_absolute_and_sta_absolute() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $1234 ; load A at $1234
            STA $4321 ; store register A at 4321
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x1234, 42);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x4321])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x1234, 42);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x4321])
 }
 
 #[test]
 fn ldx_immediate_and_stx_zero_page() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #25 ; load 25 into register X
            STX $9  ; store register X at address 0x0 + 0x9 = 0x09
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(25, mock.memory[0x0009])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(25, cpu.memory().memory[0x0009])
 }
 
 #[test]
 fn ldx_absolute_and_stx_absolute() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX $2341 ; load X at $2341
            STX $3214 ; store register X at 3214
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x2341, 99);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(99, mock.memory[0x3214])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x2341, 99);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(99, cpu.memory().memory[0x3214])
 }
 
 #[test]
 fn ldy_immediate_and_sty_zero_page() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #33 ; load 33 into register Y
            STY $2A  ; store register A at address 0x00 + 0X2A = 0x2A
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    };
-    assert_eq!(33, mock.memory[0x002A])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(33, cpu.memory().memory[0x002A])
 }
 
 #[test]
 fn ldy_absolute_and_sty_absolute() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY $3412 ; load T at $3412
            STY $2143 ; store register X at $2143
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x3412, 89);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(89, mock.memory[0x2143])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x3412, 89);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(89, cpu.memory().memory[0x2143])
 }
 
 #[test]
 fn bcc_forward() {
     let script = create_branch_forward_test_scipt("BCC", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(33, cpu.memory().memory[0x55])
 }
 
 #[test]
 fn bcc_backward() {
     let script = create_branch_backward_test_scipt("BCC");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn bpl_forward() {
     let script = create_branch_forward_test_scipt("BPL", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(33, cpu.memory().memory[0x55])
 }
 
 #[test]
 fn bpl_backward() {
     let script = create_branch_backward_test_scipt("BPL");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn bvc_forward() {
     let script = create_branch_forward_test_scipt("BVC", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(33, cpu.memory().memory[0x55])
 }
 
 #[test]
 fn bvc_backward() {
     let script = create_branch_backward_test_scipt("BVC");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn bne_forward() {
     let script = create_branch_forward_test_scipt("BNE", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(33, cpu.memory().memory[0x55])
 }
 
 #[test]
 fn bne_backward() {
     let script = create_branch_backward_test_scipt("BNE");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn beq_forward() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#" LDA #0
     BEQ forward
     LDY #99
@@ -268,17 +248,15 @@ forward:
     LDY #42
     STY $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn beq_backward() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"BCC forward
 backward:
     LDX #42
@@ -293,44 +271,38 @@ forward:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn bmi_forward() {
     let script = create_branch_forward_test_scipt("BMI", 10u8.wrapping_neg());
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(10u8.wrapping_neg(), mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(10u8.wrapping_neg(), cpu.memory().memory[0x55])
 }
 
 #[test]
 fn adc_without_carry() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #30
     ADC #12
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn adc_signed_without_carry() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         format!(
             r#"LDA #30
     ADC #{}
@@ -339,17 +311,15 @@ fn adc_signed_without_carry() {
         )
         .as_ref(),
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(10u8.wrapping_neg(), mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(10u8.wrapping_neg(), cpu.memory().memory[0x42])
 }
 
 #[test]
 fn adc_with_carry() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #255
     ADC #2
     BCS end
@@ -358,17 +328,15 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn adc_with_overflow() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #80
     ADC #80
     BVS end
@@ -377,47 +345,41 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_and_1() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #%11110000
     AND #%00001111
     STA $00"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0000_0000, mock.memory[0x00])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0000_0000, cpu.memory().memory[0x00])
 }
 
 #[test]
 fn test_and_2() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #%01010101
     AND #%10011001
     STA $00"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0001_0001, mock.memory[0x00])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0001_0001, cpu.memory().memory[0x00])
 }
 
 #[test]
 fn test_sec() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     BCS good
     LDX #99
@@ -429,17 +391,15 @@ good:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x1ABC])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x1ABC])
 }
 
 #[test]
 fn test_clc() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     CLC
     BCC good
@@ -452,17 +412,15 @@ good:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x1ABC])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x1ABC])
 }
 
 #[test]
 fn test_clv() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #80
     ADC #80
     CLV
@@ -476,17 +434,15 @@ good:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x1ABC])
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x1ABC])
 }
 
 #[test]
 fn test_asl_a_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $05
     ASL A
     STA $10
@@ -497,19 +453,17 @@ end:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x05, 0b1010_1010);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0101_0100, mock.memory[0x10]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x05, 0b1010_1010);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0101_0100, cpu.memory().memory[0x10]);
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_asl_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"ASL $05
     LDA $05
     STA $10
@@ -520,19 +474,17 @@ end:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x05, 0b1010_1010);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0101_0100, mock.memory[0x10]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x05, 0b1010_1010);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0101_0100, cpu.memory().memory[0x10]);
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cmp_bmi() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #10
     CMP $00
     BMI inferior
@@ -542,18 +494,16 @@ inferior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cmp_bpl() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #20
     CMP $00
     BPL superior
@@ -563,18 +513,16 @@ superior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cmp_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #15
     CMP $00
     BEQ equal
@@ -584,18 +532,16 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cpx_bmi() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #10
     CPX $00
     BMI inferior
@@ -605,18 +551,16 @@ inferior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cpx_bpl() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #20
     CPX $00
     BPL superior
@@ -626,18 +570,16 @@ superior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cpx_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #15
     CPX $00
     BEQ equal
@@ -647,18 +589,16 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cpy_bmi() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #10
     CPY $00
     BMI inferior
@@ -668,18 +608,16 @@ inferior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cpy_bpl() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #20
     CPY $00
     BPL superior
@@ -689,18 +627,16 @@ superior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_cpy_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #15
     CPY $00
     BEQ equal
@@ -710,18 +646,16 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 15);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_dec_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"DEC $00
     LDA $00
     CMP #9
@@ -732,18 +666,16 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 10);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 10);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_dex_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX $00
     DEX
     CPX #9
@@ -754,18 +686,16 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 10);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 10);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_dey_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY $00
     DEY
     CPY #9
@@ -776,78 +706,68 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 10);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x00, 10);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_eor() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $12
     EOR #%10101010
     STA $12"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 0b11001100);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b01100110, mock.memory[0x12])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x12, 0b11001100);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b01100110, cpu.memory().memory[0x12])
 }
 
 #[test]
 fn test_inc() {
-    let mut mock = create_mock_from_script(r#"INC $12"#);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 9);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(10, mock.memory[0x12])
+    let mock = create_mock_from_script(r#"INC $12"#);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x12, 9);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(10, cpu.memory().memory[0x12])
 }
 
 #[test]
 fn test_inx() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX $12
     INX
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 9);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(10, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x12, 9);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(10, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_iny() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY $12
     INY
     STY $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 9);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(10, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0x12, 9);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(10, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_lsr_a_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $AB
     LSR A
     STA $AB
@@ -858,19 +778,17 @@ carryset:
     LDX #42
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1011);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1010_1011);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0101_0101, cpu.memory().memory[0xAB]);
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_lsr_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LSR $AB
     BCS carryset
     LDX #99
@@ -879,66 +797,58 @@ carryset:
     LDX #42
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1011);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1010_1011);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0101_0101, cpu.memory().memory[0xAB]);
+    assert_eq!(42, cpu.memory().memory[0x42])
 }
 
 #[test]
 fn test_ora() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $AB
     ORA #%00001111
     STA $BA"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1111_0000);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b1111_1111, mock.memory[0xBA]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1111_0000);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b1111_1111, cpu.memory().memory[0xBA]);
 }
 
 #[test]
 fn test_pha() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     PHA"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x01FF]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x01FD]);
 }
 
 #[test]
 fn test_pla() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     PHA
     LDA #0
     PLA
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_rol_a() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     LDA $AB
     ROL A
@@ -950,19 +860,17 @@ end:
     LDA #42
     STA $AB"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0x42]);
-    assert_eq!(42, mock.memory[0xAB]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1010_1010);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0101_0101, cpu.memory().memory[0x42]);
+    assert_eq!(42, cpu.memory().memory[0xAB]);
 }
 
 #[test]
 fn test_rol() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     ROL $AB
     BCS end
@@ -972,19 +880,17 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1010_1010);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b0101_0101, cpu.memory().memory[0xAB]);
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_ror_a() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     LDA $AB
     ROR A
@@ -997,19 +903,17 @@ end:
     LDA #42
     STA $AB"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b1101_0101, mock.memory[0x42]);
-    assert_eq!(42, mock.memory[0xAB]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1010_1010);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b1101_0101, cpu.memory().memory[0x42]);
+    assert_eq!(42, cpu.memory().memory[0xAB]);
 }
 
 #[test]
 fn test_ror() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     ROR $AB
     BCC end
@@ -1020,79 +924,69 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0b1101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory_mut().mem_write_u8(0xAB, 0b1010_1010);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(0b1101_0101, cpu.memory().memory[0xAB]);
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_tax() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     TAX
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_txa() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #42
     TXA
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_tay() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     TAY
     STY $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_tya() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #42
     TYA
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_txs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #1
     PHA
     LDA #2
@@ -1109,33 +1003,29 @@ fn test_txs() {
     PLA
     STA $43"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(30, mock.memory[0x42]);
-    assert_eq!(10, mock.memory[0x43]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(30, cpu.memory().memory[0x42]);
+    assert_eq!(10, cpu.memory().memory[0x43]);
 }
 
 #[test]
 fn test_tsx() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"PHA
     TSX
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
-    }
-    assert_eq!(0xFE, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(POWER_UP_STACK_POINTER - 1, cpu.memory().memory[0x42]);
 }
 
 #[test]
 fn test_jsr_jmp_rts() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"JSR func
     LDA #42
     STA $42
@@ -1149,11 +1039,575 @@ func:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        cpu.reset();
-        cpu.run_loop()
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(42, cpu.memory().memory[0x42]);
+    assert_eq!(42, cpu.memory().memory[0x4242]);
+}
+
+#[test]
+fn test_cycles_with_page_cross() {
+    let mock = create_mock_from_script(
+        r#"LDX #$FF
+    LDA $80FF,X
+    LDA $8000,X
+    STA $0200,X
+    BRK"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    let cycles: Vec<u32> = (0..5).map(|_| cpu.run()).collect();
+    assert_eq!(vec![2, 5, 4, 5, 7], cycles);
+    assert_eq!(7 + 23, cpu.cycles());
+}
+
+#[test]
+fn test_cycles_of_branches() {
+    let mock = create_mock_from_script(
+        r#"LDA #0
+    BEQ taken
+    NOP
+taken:
+    BNE taken
+    BRK"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    let cycles: Vec<u32> = (0..3).map(|_| cpu.run()).collect();
+    assert_eq!(vec![2, 3, 2], cycles);
+
+    // BEQ at $80FB jumps from page $80 to $810D
+    let mock = MemoryMock::new(&[0xA9, 0x00, 0xF0, 0x10], 0x80F9);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    let cycles: Vec<u32> = (0..2).map(|_| cpu.run()).collect();
+    assert_eq!(vec![2, 4], cycles);
+    assert_eq!(0x810D, cpu.counter);
+}
+
+#[test]
+fn test_jump_to_the_byte_after_the_opcode() {
+    // JMP $8001
+    let mut cpu = Cpu::new(MemoryMock::new(&[0x4C, 0x01, 0x80], 0x8000));
+    cpu.reset();
+    assert_eq!(3, cpu.run());
+    assert_eq!(0x8001, cpu.counter);
+
+    // LDA #0 ; BEQ landing on its own operand
+    let mut cpu = Cpu::new(MemoryMock::new(&[0xA9, 0x00, 0xF0, 0xFF], 0x8000));
+    cpu.reset();
+    let cycles: Vec<u32> = (0..2).map(|_| cpu.run()).collect();
+    assert_eq!(vec![2, 3], cycles);
+    assert_eq!(0x8003, cpu.counter);
+}
+
+fn create_mock_with_handler(program: &[u8], handler: &[u8], vector: u16) -> MemoryMock {
+    let mut mock = MemoryMock::new(program, 0x8000);
+    mock.load(handler, 0x9000);
+    mock.mem_write_u16(vector, 0x9000);
+    mock
+}
+
+#[test]
+fn test_brk_is_a_software_interrupt() {
+    // BRK, padding byte, INX ; handler: RTI
+    let mock = create_mock_with_handler(&[0x00, 0xFF, 0xE8], &[0x40], IRQ_VECTOR);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    assert_eq!(7, cpu.run());
+    assert_eq!(0x9000, cpu.counter);
+    let pushed_status = cpu.memory().memory[usize::from(cpu.get_stack_addr()) + 1];
+    assert_ne!(0, pushed_status & register::Status::BREAK.bits());
+    cpu.run();
+    assert_eq!(0x8002, cpu.counter);
+    cpu.run();
+    assert_eq!(1, cpu.x);
+}
+
+#[test]
+fn test_run_until_brk() {
+    // INX, BRK, padding byte ; handler: INY
+    let mock = create_mock_with_handler(&[0xE8, 0x00, 0xFF], &[0xC8], IRQ_VECTOR);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(1, cpu.x);
+    assert_eq!(0x9000, cpu.counter);
+    assert_eq!(0, cpu.y);
+
+    // INX, JAM
+    let mut cpu = Cpu::new(MemoryMock::new(&[0xE8, 0x02], 0x8000));
+    cpu.reset();
+    cpu.run_until_brk();
+    assert_eq!(1, cpu.x);
+    assert_eq!(0x8001, cpu.counter);
+}
+
+#[test]
+fn test_nmi_is_edge_triggered() {
+    // NOP, NOP ; handler: NOP, NOP
+    let mock = create_mock_with_handler(&[0xEA, 0xEA], &[0xEA, 0xEA], NMI_VECTOR);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.set_nmi(true);
+    assert_eq!(7, cpu.run());
+    assert_eq!(0x9000, cpu.counter);
+    cpu.set_nmi(true);
+    cpu.run();
+    assert_eq!(0x9001, cpu.counter);
+    cpu.set_nmi(false);
+    cpu.set_nmi(true);
+    cpu.run();
+    assert_eq!(0x9000, cpu.counter);
+    let pushed_status = cpu.memory().memory[usize::from(cpu.get_stack_addr()) + 1];
+    assert_eq!(0, pushed_status & register::Status::BREAK.bits());
+}
+
+#[test]
+fn test_irq_is_delayed_after_cli() {
+    // CLI, NOP, NOP ; handler: NOP
+    let mock = create_mock_with_handler(&[0x58, 0xEA, 0xEA], &[0xEA], IRQ_VECTOR);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.set_irq(true);
+    cpu.run();
+    cpu.run();
+    assert_eq!(0x8002, cpu.counter);
+    assert_eq!(7, cpu.run());
+    assert_eq!(0x9000, cpu.counter);
+    assert!(cpu.status.is_set(register::Status::INTERRUPT_DISABLE));
+    cpu.run();
+    assert_eq!(0x9001, cpu.counter);
+}
+
+#[test]
+fn test_reset_line() {
+    let mock = MemoryMock::new(&[0xEA, 0xEA], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run();
+    cpu.set_reset(true);
+    cpu.set_reset(false);
+    assert_eq!(7, cpu.run());
+    assert_eq!(0x8000, cpu.counter);
+    assert_eq!(POWER_UP_STACK_POINTER - 3, cpu.stack_pointer);
+}
+
+fn run_program(program: &[u8], instructions: usize) -> Cpu<MemoryMock> {
+    let mut mock = MemoryMock::new(program, 0x8000);
+    mock.memory[0x10] = 0x05;
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    for _ in 0..instructions {
+        cpu.run();
+    }
+    cpu
+}
+
+#[test]
+fn test_lax_and_sax() {
+    // LAX $10 ; LDX #$03 ; SAX $11
+    let cpu = run_program(&[0xA7, 0x10, 0xA2, 0x03, 0x87, 0x11], 3);
+    assert_eq!(0x05, cpu.a);
+    assert_eq!(0x01, cpu.memory().memory[0x11]);
+}
+
+#[test]
+fn test_dcp_and_isc() {
+    // LDA #4 ; DCP $10
+    let cpu = run_program(&[0xA9, 0x04, 0xC7, 0x10], 2);
+    assert_eq!(0x04, cpu.memory().memory[0x10]);
+    assert!(cpu.status.is_set(register::Status::ZERO));
+    assert!(cpu.status.is_set(register::Status::CARRY));
+
+    // LDA #10 ; SEC ; ISC $10
+    let cpu = run_program(&[0xA9, 0x0A, 0x38, 0xE7, 0x10], 3);
+    assert_eq!(0x06, cpu.memory().memory[0x10]);
+    assert_eq!(0x04, cpu.a);
+}
+
+#[test]
+fn test_shift_and_combine() {
+    // LDA #$10 ; SLO $10 ; RRA $10
+    let cpu = run_program(&[0xA9, 0x10, 0x07, 0x10, 0x67, 0x10], 2);
+    assert_eq!(0x0A, cpu.memory().memory[0x10]);
+    assert_eq!(0x1A, cpu.a);
+    let cpu = run_program(&[0xA9, 0x10, 0x07, 0x10, 0x67, 0x10], 3);
+    assert_eq!(0x05, cpu.memory().memory[0x10]);
+    assert_eq!(0x1F, cpu.a);
+}
+
+#[test]
+fn test_immediate_combinations() {
+    // LDA #$FF ; LDX #$0F ; AXS #$05
+    let cpu = run_program(&[0xA9, 0xFF, 0xA2, 0x0F, 0xCB, 0x05], 3);
+    assert_eq!(0x0A, cpu.x);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+
+    // LDA #$FF ; ANC #$80
+    let cpu = run_program(&[0xA9, 0xFF, 0x0B, 0x80], 2);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+
+    // LDA #$FF ; ALR #$03
+    let cpu = run_program(&[0xA9, 0xFF, 0x4B, 0x03], 2);
+    assert_eq!(0x01, cpu.a);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+
+    // LDA #$FF ; ARR #$C0
+    let cpu = run_program(&[0xA9, 0xFF, 0x6B, 0xC0], 2);
+    assert_eq!(0x60, cpu.a);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+    assert!(cpu.status.is_unset(register::Status::OVERFLOW));
+}
+
+#[test]
+fn test_unofficial_nop_cycles() {
+    let mock = MemoryMock::new(
+        &[0xA2, 0x01, 0x1C, 0xFF, 0x80, 0x04, 0x10, 0x80, 0x00],
+        0x8000,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    let cycles: Vec<u32> = (0..4).map(|_| cpu.run()).collect();
+    assert_eq!(vec![2, 5, 3, 2], cycles);
+    assert_eq!(0x8009, cpu.counter);
+}
+
+#[test]
+fn test_jam_halts_until_reset() {
+    let mock = MemoryMock::new(&[0x02], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run_until_brk();
+    assert!(cpu.is_jammed());
+    assert_eq!(0x8000, cpu.counter);
+    cpu.set_nmi(true);
+    assert_eq!(1, cpu.run());
+    assert_eq!(0x8000, cpu.counter);
+    cpu.set_reset(true);
+    cpu.run();
+    assert!(!cpu.is_jammed());
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+fn trace_program(mock: &mut MemoryMock, instructions: usize) -> Vec<String> {
+    let buffer = SharedBuffer::default();
+    let mut cpu = Cpu::new(mock);
+    cpu.set_tracer(Some(Tracer::new(buffer.clone())));
+    cpu.reset();
+    for _ in 0..instructions {
+        cpu.run();
+    }
+    buffer.lines()
+}
+
+#[test]
+fn test_trace_nestest_format() {
+    // LDA #$10 ; STA $0200 ; LDX #$01 ; LDA $0F,X ; *NOP $A9 ; JMP $9000
+    let mut mock = MemoryMock::new(
+        &[
+            0xA9, 0x10, 0x8D, 0x00, 0x02, 0xA2, 0x01, 0xB5, 0x0F, 0x04, 0xA9, 0x4C, 0x00, 0x90,
+        ],
+        0x8000,
+    );
+    mock.memory[0x10] = 0x05;
+    let lines = trace_program(&mut mock, 6);
+    assert_eq!(
+        vec![
+            "8000  A9 10     LDA #$10                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:7",
+            "8002  8D 00 02  STA $0200 = 00                  A:10 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:9",
+            "8005  A2 01     LDX #$01                        A:10 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:13",
+            "8007  B5 0F     LDA $0F,X @ 10 = 05             A:10 X:01 Y:00 P:24 SP:FD PPU:  0,  0 CYC:15",
+            "8009  04 A9    *NOP $A9 = 00                    A:05 X:01 Y:00 P:24 SP:FD PPU:  0,  0 CYC:19",
+            "800B  4C 00 90  JMP $9000                       A:05 X:01 Y:00 P:24 SP:FD PPU:  0,  0 CYC:22",
+        ],
+        lines
+    );
+}
+
+#[test]
+fn test_trace_indirect_operands() {
+    // LDY #$04 ; LDA ($20),Y ; JMP ($02FF)
+    let mut mock = MemoryMock::new(&[0xA0, 0x04, 0xB1, 0x20, 0x6C, 0xFF, 0x02], 0x8000);
+    mock.memory[0x20] = 0x00;
+    mock.memory[0x21] = 0x03;
+    mock.memory[0x0304] = 0x77;
+    mock.memory[0x02FF] = 0x00;
+    mock.memory[0x0200] = 0x90;
+    mock.memory[0x0300] = 0x55;
+    let lines = trace_program(&mut mock, 3);
+    assert!(lines[1].contains("LDA ($20),Y = 0300 @ 0304 = 77  "));
+    assert!(lines[2].contains("JMP ($02FF) = 9000  "));
+}
+
+#[test]
+fn test_trace_switched_at_runtime() {
+    let buffer = SharedBuffer::default();
+    let mock = MemoryMock::new(&[0xE8, 0xE8, 0xE8], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    cpu.set_tracer(Some(Tracer::new(buffer.clone())));
+    cpu.reset();
+    cpu.tracer_mut().unwrap().set_enabled(false);
+    cpu.run();
+    cpu.tracer_mut().unwrap().set_enabled(true);
+    cpu.tracer_mut().unwrap().set_ppu_position(241, 3);
+    cpu.run();
+    let lines = buffer.lines();
+    assert_eq!(1, lines.len());
+    assert!(lines[0].starts_with("8001  E8        INX"));
+    assert!(lines[0].ends_with("PPU:241,  3 CYC:9"));
+}
+
+#[test]
+fn test_bit_copies_negative_and_overflow() {
+    // LDA #$01 ; BIT $10 with $10 = $C0
+    let mut mock = MemoryMock::new(&[0xA9, 0x01, 0x24, 0x10], 0x8000);
+    mock.memory[0x10] = 0xC0;
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run();
+    cpu.run();
+    assert!(cpu.status.is_set(register::Status::NEGATIVE));
+    assert!(cpu.status.is_set(register::Status::OVERFLOW));
+    assert!(cpu.status.is_set(register::Status::ZERO));
+}
+
+#[test]
+fn test_compare_carry_is_unsigned() {
+    // LDY #$FE ; CPY #$5D ; LDA #$10 ; CMP #$90
+    let mock = MemoryMock::new(&[0xA0, 0xFE, 0xC0, 0x5D, 0xA9, 0x10, 0xC9, 0x90], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run();
+    cpu.run();
+    assert!(cpu.status.is_set(register::Status::CARRY));
+    assert!(cpu.status.is_set(register::Status::NEGATIVE));
+    cpu.run();
+    cpu.run();
+    assert!(cpu.status.is_unset(register::Status::CARRY));
+    assert!(cpu.status.is_set(register::Status::NEGATIVE));
+}
+
+#[test]
+fn test_jmp_indirect_does_not_cross_page() {
+    // JMP ($02FF)
+    let mut mock = MemoryMock::new(&[0x6C, 0xFF, 0x02], 0x8000);
+    mock.memory[0x02FF] = 0x34;
+    mock.memory[0x0200] = 0x12;
+    mock.memory[0x0300] = 0x56;
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.run();
+    assert_eq!(0x1234, cpu.counter);
+}
+
+#[test]
+fn test_indirect_pointers_wrap_in_zero_page() {
+    // LDX #$00 ; LDA ($FF,X) ; STA $10 ; LDY #$01 ; LDA ($FF),Y
+    let mut mock = MemoryMock::new(
+        &[0xA2, 0x00, 0xA1, 0xFF, 0x85, 0x10, 0xA0, 0x01, 0xB1, 0xFF],
+        0x8000,
+    );
+    mock.memory[0x00FF] = 0x00;
+    mock.memory[0x0000] = 0x03;
+    mock.memory[0x0100] = 0x04;
+    mock.memory[0x0300] = 0x42;
+    mock.memory[0x0301] = 0x43;
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    for _ in 0..5 {
+        cpu.run();
+    }
+    assert_eq!(0x42, cpu.memory().memory[0x10]);
+    assert_eq!(0x43, cpu.a);
+}
+
+#[test]
+fn test_plp_keeps_unused_flag_and_drops_break() {
+    // LDA #$10 ; PHA ; PLP
+    let cpu = run_program(&[0xA9, 0x10, 0x48, 0x28], 3);
+    assert_eq!(register::Status::UNUSED, cpu.status);
+}
+
+#[test]
+fn test_registers_round_trip() {
+    let mock = MemoryMock::new(&[0xE8], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    let registers = Registers {
+        program_counter: 0x8000,
+        stack_pointer: 0xF0,
+        a: 1,
+        x: 2,
+        y: 3,
+        status: 0xE5,
+    };
+    cpu.set_registers(&registers);
+    assert_eq!(registers, cpu.registers());
+    cpu.run();
+    assert_eq!(3, cpu.registers().x);
+}
+
+/// Runs the first instruction of `program` from $8000 and returns the bus
+/// accesses it made.
+fn bus_accesses(program: &[u8], x: u8, memory: &[(u16, u8)]) -> Vec<(u16, u8, BusAccess)> {
+    let mut mock = MemoryMock::new(program, 0x8000);
+    for &(addr, byte) in memory {
+        mock.memory[usize::from(addr)] = byte;
+    }
+    mock.accesses.clear();
+    let mut cpu = Cpu::new(mock);
+    cpu.set_registers(&Registers {
+        program_counter: 0x8000,
+        stack_pointer: 0xFD,
+        x,
+        ..Registers::default()
+    });
+    cpu.run();
+    cpu.into_memory().accesses
+}
+
+#[test]
+fn test_every_cycle_accesses_the_bus() {
+    for (&opcode, instruct) in INSTRUCTION_MAP.iter() {
+        if instruct.name == instruction::Name::Jam {
+            continue;
+        }
+        // the operands cross pages once indexed and the branches cross pages
+        let mut mock = MemoryMock::new(&[opcode, 0x10, 0x80], 0x80F0);
+        mock.accesses.clear();
+        let mut cpu = Cpu::new(mock);
+        cpu.set_registers(&Registers {
+            program_counter: 0x80F0,
+            stack_pointer: 0xFD,
+            x: 0xF0,
+            y: 0xF0,
+            ..Registers::default()
+        });
+        let cycles = cpu.run();
+        assert_eq!(
+            cycles as usize,
+            cpu.memory().accesses.len(),
+            "{:?} {:02X}: {:X?}",
+            instruct.name,
+            opcode,
+            cpu.memory().accesses
+        );
     }
-    assert_eq!(42, mock.memory[0x42]);
-    assert_eq!(42, mock.memory[0x4242]);
+}
+
+#[test]
+fn test_read_modify_write_writes_twice() {
+    // INC $10
+    let accesses = bus_accesses(&[0xE6, 0x10], 0, &[(0x10, 5)]);
+    assert_eq!(
+        vec![
+            (0x8000, 0xE6, BusAccess::Read),
+            (0x8001, 0x10, BusAccess::Read),
+            (0x0010, 5, BusAccess::Read),
+            (0x0010, 5, BusAccess::Write),
+            (0x0010, 6, BusAccess::Write),
+        ],
+        accesses
+    );
+}
+
+#[test]
+fn test_indexed_dummy_reads() {
+    // LDA $02FF,X reads the address before its high byte is fixed
+    let accesses = bus_accesses(&[0xBD, 0xFF, 0x02], 2, &[(0x0201, 1), (0x0301, 2)]);
+    assert_eq!(
+        vec![(0x0201, 1, BusAccess::Read), (0x0301, 2, BusAccess::Read)],
+        accesses[3..]
+    );
+    let accesses = bus_accesses(&[0xBD, 0x00, 0x02], 2, &[(0x0202, 1)]);
+    assert_eq!(vec![(0x0202, 1, BusAccess::Read)], accesses[3..]);
+
+    // STA $0200,X reads the target even without a page crossing, then
+    // stores without reading it again
+    let accesses = bus_accesses(&[0x9D, 0x00, 0x02], 2, &[(0x0202, 1)]);
+    assert_eq!(
+        vec![(0x0202, 1, BusAccess::Read), (0x0202, 0, BusAccess::Write)],
+        accesses[3..]
+    );
+
+    // LDA $F0,X reads the zero page base first
+    let accesses = bus_accesses(&[0xB5, 0xF0], 0x20, &[(0xF0, 1), (0x10, 2)]);
+    assert_eq!(
+        vec![(0x00F0, 1, BusAccess::Read), (0x0010, 2, BusAccess::Read)],
+        accesses[2..]
+    );
+}
+
+#[test]
+fn test_jsr_and_rts_bus_accesses() {
+    // JSR $8010 ; ... ; RTS
+    let mut mock = MemoryMock::new(&[0x20, 0x10, 0x80], 0x8000);
+    mock.memory[0x8010] = 0x60;
+    let mut cpu = Cpu::new(mock);
+    cpu.reset();
+    cpu.memory_mut().accesses.clear();
+    cpu.run();
+    cpu.run();
+    assert_eq!(
+        vec![
+            (0x8000, 0x20, BusAccess::Read),
+            (0x8001, 0x10, BusAccess::Read),
+            (0x01FD, 0x00, BusAccess::Read),
+            (0x01FD, 0x80, BusAccess::Write),
+            (0x01FC, 0x02, BusAccess::Write),
+            (0x8002, 0x80, BusAccess::Read),
+            // RTS
+            (0x8010, 0x60, BusAccess::Read),
+            (0x8011, 0x00, BusAccess::Read),
+            (0x01FB, 0x00, BusAccess::Read),
+            (0x01FC, 0x02, BusAccess::Read),
+            (0x01FD, 0x80, BusAccess::Read),
+            (0x8002, 0x80, BusAccess::Read),
+        ],
+        cpu.memory().accesses
+    );
+    assert_eq!(0x8003, cpu.program_counter());
+}
+
+#[test]
+fn test_taken_branch_reads_the_next_opcode() {
+    // BCC +$10 from $80F0 crosses into $8102
+    let mut mock = MemoryMock::new(&[0x90, 0x10], 0x80F0);
+    mock.accesses.clear();
+    let mut cpu = Cpu::new(mock);
+    cpu.set_program_counter(0x80F0);
+    assert_eq!(4, cpu.run());
+    assert_eq!(
+        vec![
+            (0x80F0, 0x90, BusAccess::Read),
+            (0x80F1, 0x10, BusAccess::Read),
+            (0x80F2, 0x00, BusAccess::Read),
+            (0x8002, 0x00, BusAccess::Read),
+        ],
+        cpu.memory().accesses
+    );
+    assert_eq!(0x8102, cpu.program_counter());
 }
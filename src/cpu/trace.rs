@@ -0,0 +1,184 @@
+use std::io::Write;
+
+use super::instruction::{Instruction, Mode, Name, INSTRUCTION_MAP};
+use super::Cpu;
+use crate::traits::Memory;
+
+/// nestest.log shows the I/O registers as $FF.
+const IO_REGISTERS: std::ops::Range<u16> = 0x2000..0x4020;
+const IO_REGISTER_VALUE: u8 = 0xFF;
+
+/// Writes one nestest.log line for each instruction executed by the `Cpu`.
+/// https://www.qmtpro.com/~nes/misc/nestest.log
+pub struct Tracer {
+    sink: Box<dyn Write>,
+    is_enabled: bool,
+    scanline: u16,
+    dot: u16,
+}
+
+impl Tracer {
+    pub fn new(sink: impl Write + 'static) -> Self {
+        Self {
+            sink: Box::new(sink),
+            is_enabled: true,
+            scanline: 0,
+            dot: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    /// Switches the tracing on or off at runtime.
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+    }
+
+    /// PPU position printed on the next line, the CPU alone does not know it.
+    pub fn set_ppu_position(&mut self, scanline: u16, dot: u16) {
+        self.scanline = scanline;
+        self.dot = dot;
+    }
+
+    /// A failing sink turns the tracing off instead of stopping the emulation.
+    fn write_line(&mut self, line: &str) {
+        if writeln!(self.sink, "{line}").is_err() {
+            self.is_enabled = false;
+        }
+    }
+}
+
+impl<M: Memory> Cpu<M> {
+    /// Traces the instruction at the program counter before it is executed.
+    pub(super) fn trace(&mut self) {
+        let Some((scanline, dot)) = self
+            .tracer
+            .as_ref()
+            .filter(|tracer| tracer.is_enabled)
+            .map(|tracer| (tracer.scanline, tracer.dot))
+        else {
+            return;
+        };
+        let instruct = INSTRUCTION_MAP.get(&self.trace_read(self.counter)).unwrap();
+        let bytes = (0..u16::from(instruct.len))
+            .map(|i| format!("{:02X}", self.trace_read(self.counter.wrapping_add(i))))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let prefix = if instruct.is_unofficial() { '*' } else { ' ' };
+        let disassembly = format!(
+            "{} {}",
+            mnemonic(&instruct.name),
+            self.trace_operand(instruct)
+        );
+        let line = format!(
+            "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            self.counter,
+            bytes,
+            prefix,
+            disassembly.trim_end(),
+            self.a,
+            self.x,
+            self.y,
+            self.status.bits(),
+            self.stack_pointer,
+            scanline,
+            dot,
+            self.cycles,
+        );
+        self.tracer.as_mut().unwrap().write_line(&line);
+    }
+
+    /// Operand with its effective address and the value it points to.
+    fn trace_operand(&self, instruct: &Instruction) -> String {
+        let operand = self.counter.wrapping_add(1);
+        let byte = self.trace_read(operand);
+        let word = self.trace_read_u16(operand);
+        match instruct.mode {
+            Mode::Implicit => String::new(),
+            Mode::Accumulator => "A".to_string(),
+            Mode::Immediate => format!("#${byte:02X}"),
+            Mode::ZeroPage => format!("${byte:02X} = {:02X}", self.trace_read(u16::from(byte))),
+            Mode::ZeroPageX | Mode::ZeroPageY => {
+                let (register, index) = match instruct.mode {
+                    Mode::ZeroPageX => ('X', self.x),
+                    _ => ('Y', self.y),
+                };
+                let addr = byte.wrapping_add(index);
+                format!(
+                    "${byte:02X},{register} @ {addr:02X} = {:02X}",
+                    self.trace_read(u16::from(addr))
+                )
+            }
+            Mode::Absolute if matches!(instruct.name, Name::Jmp | Name::Jsr) => {
+                format!("${word:04X}")
+            }
+            Mode::Absolute => format!("${word:04X} = {:02X}", self.trace_read(word)),
+            Mode::AbsoluteX | Mode::AbsoluteY => {
+                let (register, index) = match instruct.mode {
+                    Mode::AbsoluteX => ('X', self.x),
+                    _ => ('Y', self.y),
+                };
+                let addr = word.wrapping_add(u16::from(index));
+                format!(
+                    "${word:04X},{register} @ {addr:04X} = {:02X}",
+                    self.trace_read(addr)
+                )
+            }
+            Mode::Indirect => {
+                // the pointer high byte is fetched without carrying into the page
+                let hi = (word & 0xFF00) | u16::from((word as u8).wrapping_add(1));
+                let target = u16::from_le_bytes([self.trace_read(word), self.trace_read(hi)]);
+                format!("(${word:04X}) = {target:04X}")
+            }
+            Mode::IndirectX => {
+                let pointer = byte.wrapping_add(self.x);
+                let addr = self.trace_read_zero_page_u16(pointer);
+                format!(
+                    "(${byte:02X},X) @ {pointer:02X} = {addr:04X} = {:02X}",
+                    self.trace_read(addr)
+                )
+            }
+            Mode::IndirectY => {
+                let base = self.trace_read_zero_page_u16(byte);
+                let addr = base.wrapping_add(u16::from(self.y));
+                format!(
+                    "(${byte:02X}),Y = {base:04X} @ {addr:04X} = {:02X}",
+                    self.trace_read(addr)
+                )
+            }
+            Mode::Relative => {
+                let target = operand.wrapping_add(1).wrapping_add(byte as i8 as u16);
+                format!("${target:04X}")
+            }
+        }
+    }
+
+    fn trace_read(&self, addr: u16) -> u8 {
+        if IO_REGISTERS.contains(&addr) {
+            IO_REGISTER_VALUE
+        } else {
+            self.memory.peek_u8(addr)
+        }
+    }
+
+    fn trace_read_u16(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.trace_read(addr), self.trace_read(addr.wrapping_add(1))])
+    }
+
+    fn trace_read_zero_page_u16(&self, addr: u8) -> u16 {
+        u16::from_le_bytes([
+            self.trace_read(u16::from(addr)),
+            self.trace_read(u16::from(addr.wrapping_add(1))),
+        ])
+    }
+}
+
+/// nestest.log spells ISC as ISB.
+fn mnemonic(name: &Name) -> String {
+    match name {
+        Name::Isc => "ISB".to_string(),
+        _ => name.mnemonic(),
+    }
+}
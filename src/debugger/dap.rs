@@ -0,0 +1,621 @@
+//! Debug Adapter Protocol server, to debug assembled programs from an editor.
+//! https://microsoft.github.io/debug-adapter-protocol/specification
+//! The `launch` request takes the assembled binary in `program`, its load
+//! address in `origin`, $8000 by default, the assembly file in `source`,
+//! its `SourceMap` file in `sourceMap` and `stopOnEntry`.
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use super::source_map::SourceMap;
+use super::{Debugger, StopReason};
+use crate::cpu::Status;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const FLAGS_REFERENCE: u64 = 2;
+const DEFAULT_ORIGIN: u16 = 0x8000;
+/// Instructions run between two checks for a request from the editor.
+const CONTINUE_CHUNK: u64 = 10_000;
+const FLAGS: [(&str, Status); 8] = [
+    ("N", Status::NEGATIVE),
+    ("V", Status::OVERFLOW),
+    ("U", Status::UNUSED),
+    ("B", Status::BREAK),
+    ("D", Status::DECIMAL),
+    ("I", Status::INTERRUPT_DISABLE),
+    ("Z", Status::ZERO),
+    ("C", Status::CARRY),
+];
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Serves one editor session, the CPU is the only thread.
+pub struct DapServer {
+    debugger: Debugger,
+    source: Option<String>,
+    source_map: SourceMap,
+    is_stopped_on_entry: bool,
+    is_running: bool,
+    seq: u64,
+}
+
+impl DapServer {
+    pub fn new(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            source: None,
+            source_map: SourceMap::new(),
+            is_stopped_on_entry: false,
+            is_running: false,
+            seq: 0,
+        }
+    }
+
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Waits for an editor on `addr`, like the `debugServer` port of VS Code.
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        self.serve(BufReader::new(stream.try_clone()?), stream)
+    }
+
+    /// Answers the requests of `reader` until the editor disconnects.
+    /// The requests are read on their own thread so a `pause` reaches a running CPU.
+    pub fn serve(
+        &mut self,
+        reader: impl BufRead + Send + 'static,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let requests = spawn_reader(reader);
+        loop {
+            if self.is_running {
+                self.run_chunk(&mut writer)?;
+            }
+            let request = if self.is_running {
+                match requests.try_recv() {
+                    Ok(request) => request,
+                    Err(TryRecvError::Empty) => continue,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => return Ok(()),
+                }
+            };
+            if !self.handle(&request?, &mut writer)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn run_chunk(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        self.debugger.set_instruction_limit(CONTINUE_CHUNK);
+        let reason = match self.debugger.resume() {
+            StopReason::InstructionLimit => {
+                let counter = self.debugger.registers().program_counter;
+                // the next chunk would run over this breakpoint
+                if !self.debugger.has_breakpoint(counter) {
+                    return Ok(());
+                }
+                StopReason::Breakpoint(counter)
+            }
+            reason => reason,
+        };
+        self.is_running = false;
+        self.send_stopped(writer, stopped_body(reason))
+    }
+
+    /// Responds to `request` and sends the events following it,
+    /// returns whether the session goes on.
+    fn handle(&mut self, request: &Value, writer: &mut impl Write) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let mut stopped = None;
+        let body = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsReadMemoryRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "6502" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Flags", "variablesReference": FLAGS_REFERENCE, "expensive": false },
+            ]})),
+            "variables" => Ok(self.variables(args)),
+            "continue" => {
+                self.is_running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let reason = match command {
+                    "next" => self.debugger.step_over(),
+                    "stepIn" => self.debugger.step_into(),
+                    _ => self.debugger.step_out(),
+                };
+                stopped = Some(stopped_body(reason));
+                Ok(Value::Null)
+            }
+            "pause" => {
+                if self.is_running {
+                    self.is_running = false;
+                    stopped = Some(json!({ "reason": "pause" }));
+                }
+                Ok(Value::Null)
+            }
+            "readMemory" => self.read_memory(args),
+            "disconnect" => Ok(Value::Null),
+            _ => Err(format!("{command} is not supported")),
+        };
+        self.send_response(writer, request, body)?;
+        match command {
+            "initialize" => self.send_event(writer, "initialized", Value::Null)?,
+            "configurationDone" if self.is_stopped_on_entry => {
+                stopped = Some(json!({ "reason": "entry" }));
+            }
+            "configurationDone" => self.is_running = true,
+            "disconnect" => return Ok(false),
+            _ => {}
+        }
+        if let Some(body) = stopped {
+            self.send_stopped(writer, body)?;
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"].as_str().ok_or("missing program")?;
+        let data = std::fs::read(program).map_err(|error| format!("{program}: {error}"))?;
+        let origin = match &args["origin"] {
+            Value::Null => DEFAULT_ORIGIN,
+            origin => origin
+                .as_u64()
+                .and_then(|origin| u16::try_from(origin).ok())
+                .ok_or("origin is not an address")?,
+        };
+        if let Some(path) = args["sourceMap"].as_str() {
+            let text = std::fs::read_to_string(path).map_err(|error| format!("{path}: {error}"))?;
+            self.source_map =
+                SourceMap::parse(&text).map_err(|error| format!("{path}: {error}"))?;
+        }
+        self.source = args["source"].as_str().map(str::to_string);
+        self.is_stopped_on_entry = args["stopOnEntry"].as_bool().unwrap_or_default();
+        self.debugger.nes().load(&data, origin);
+        Ok(Value::Null)
+    }
+
+    /// Replaces the breakpoints, a line without code moves to the next one holding an instruction.
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        for addr in self.debugger.breakpoints().collect::<Vec<_>>() {
+            self.debugger.remove_breakpoint(addr);
+        }
+        let is_source = args["source"]["path"]
+            .as_str()
+            .zip(self.source.as_deref())
+            .is_some_and(|(path, source)| is_same_file(path, source));
+        let breakpoints: Vec<Value> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+                match self.source_map.resolve_line(line).filter(|_| is_source) {
+                    Some((line, addr)) => {
+                        self.debugger.add_breakpoint(addr);
+                        json!({ "verified": true, "line": line })
+                    }
+                    None => json!({ "verified": false, "line": line }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let counter = self.debugger.registers().program_counter;
+        let mut frame = json!({
+            "id": 0,
+            "name": self.debugger.disassemble(1)[0].to_string(),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{counter:04X}"),
+        });
+        if let (Some(line), Some(source)) = (self.source_map.line(counter), &self.source) {
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+            frame["source"] = json!({ "path": source });
+        }
+        json!({ "stackFrames": [frame], "totalFrames": 1 })
+    }
+
+    fn variables(&self, args: &Value) -> Value {
+        let registers = self.debugger.registers();
+        let variables: Vec<Value> = match args["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => [
+                ("A", registers.a),
+                ("X", registers.x),
+                ("Y", registers.y),
+                ("SP", registers.stack_pointer),
+                ("P", registers.status),
+            ]
+            .into_iter()
+            .map(|(name, value)| variable(name, format!("${value:02X}")))
+            .chain([json!({
+                "name": "PC",
+                "value": format!("${:04X}", registers.program_counter),
+                "variablesReference": 0,
+                "memoryReference": format!("0x{:04X}", registers.program_counter),
+            })])
+            .collect(),
+            Some(FLAGS_REFERENCE) => {
+                let status = Status::from_bits_truncate(registers.status);
+                FLAGS
+                    .into_iter()
+                    .map(|(name, flag)| variable(name, u8::from(status.is_set(flag)).to_string()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let reference = args["memoryReference"].as_str().unwrap_or_default();
+        let addr = parse_address(reference).ok_or(format!("{reference} is not an address"))?;
+        let addr = addr.wrapping_add(args["offset"].as_i64().unwrap_or_default() as u16);
+        let count = args["count"].as_u64().unwrap_or_default();
+        let data = self
+            .debugger
+            .read_memory(addr, u16::try_from(count).unwrap_or(u16::MAX));
+        Ok(json!({
+            "address": format!("0x{addr:04X}"),
+            "data": encode_base64(&data),
+        }))
+    }
+
+    fn send_response(
+        &mut self,
+        writer: &mut impl Write,
+        request: &Value,
+        body: Result<Value, String>,
+    ) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(writer, response)
+    }
+
+    fn send_event(&mut self, writer: &mut impl Write, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(writer, message)
+    }
+
+    fn send_stopped(&mut self, writer: &mut impl Write, mut body: Value) -> io::Result<()> {
+        body["threadId"] = json!(THREAD_ID);
+        body["allThreadsStopped"] = json!(true);
+        self.send_event(writer, "stopped", body)
+    }
+
+    fn send(&mut self, writer: &mut impl Write, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let content = message.to_string();
+        write!(writer, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+        writer.flush()
+    }
+}
+
+fn spawn_reader(mut reader: impl BufRead + Send + 'static) -> Receiver<io::Result<Value>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        while let Some(message) = read_message(&mut reader).transpose() {
+            let is_error = message.is_err();
+            if sender.send(message).is_err() || is_error {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Next `Content-Length` framed message, `None` at the end of the stream.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() && content_length.is_some() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+    let mut content = vec![0; content_length.unwrap_or_default()];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+}
+
+fn stopped_body(reason: StopReason) -> Value {
+    match reason {
+        StopReason::Breakpoint(_) => json!({ "reason": "breakpoint" }),
+        StopReason::Watchpoint(hit) => json!({
+            "reason": "data breakpoint",
+            "description": format!("{:?} of ${:02X} at ${:04X}", hit.access, hit.value, hit.addr),
+        }),
+        StopReason::Jammed(addr) => json!({
+            "reason": "exception",
+            "description": format!("CPU jammed at ${addr:04X}"),
+        }),
+        StopReason::Step | StopReason::Cursor(_) | StopReason::InstructionLimit => {
+            json!({ "reason": "step" })
+        }
+    }
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn is_same_file(path: &str, other: &str) -> bool {
+    let (path, other) = (Path::new(path), Path::new(other));
+    path == other
+        || path
+            .canonicalize()
+            .is_ok_and(|path| other.canonicalize().is_ok_and(|other| path == other))
+}
+
+/// `0x8000`, `$8000` or decimal address.
+fn parse_address(reference: &str) -> Option<u16> {
+    match reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix('$'))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => reference.parse().ok(),
+    }
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(char::from(BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize]));
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Nes;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    const SOURCE: &str = "start:
+  jsr sub
+  inx
+  sta $0200
+loop:
+  jmp loop
+sub:
+  ldy #$01
+  rts
+";
+    const SOURCE_MAP: &str = "8000 2\n8003 3\n8004 4\n8007 6\n800A 8\n800C 9\n";
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 13] = [
+        0x20, 0x0A, 0x80, // JSR $800A
+        0xE8,             // INX
+        0x8D, 0x00, 0x02, // STA $0200
+        0x4C, 0x07, 0x80, // JMP $8007
+        0xA0, 0x01,       // LDY #$01
+        0x60,             // RTS
+    ];
+
+    /// Writes the program, its source and its source map in a temporary directory.
+    fn write_files(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nes-emu-dap-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("snake.bin"), PROGRAM).unwrap();
+        std::fs::write(dir.join("snake.asm"), SOURCE).unwrap();
+        std::fs::write(dir.join("snake.map"), SOURCE_MAP).unwrap();
+        dir
+    }
+
+    fn launch_args(dir: &Path) -> Value {
+        json!({
+            "program": dir.join("snake.bin"),
+            "source": dir.join("snake.asm"),
+            "sourceMap": dir.join("snake.map"),
+            "stopOnEntry": true,
+        })
+    }
+
+    /// Serves the requests in order and returns every message sent back.
+    fn session(requests: &[(&str, Value)]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for (seq, (command, args)) in (1..).zip(requests) {
+            let content = json!({
+                "seq": seq,
+                "type": "request",
+                "command": command,
+                "arguments": args,
+            })
+            .to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{content}", content.len()).unwrap();
+        }
+        let mut output = Vec::new();
+        let mut server = DapServer::new(Debugger::new(Nes::new()));
+        server.serve(Cursor::new(input), &mut output).unwrap();
+        let mut output = Cursor::new(output);
+        std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+    }
+
+    fn response(messages: &[Value], request_seq: u64) -> &Value {
+        messages
+            .iter()
+            .find(|message| message["request_seq"] == request_seq)
+            .unwrap()
+    }
+
+    /// Reasons of the stopped events, in order.
+    fn stops(messages: &[Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .filter(|message| message["event"] == "stopped")
+            .map(|message| message["body"]["reason"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let dir = write_files("stepping");
+        let messages = session(&[
+            ("initialize", json!({ "adapterID": "nes-emu" })),
+            ("launch", launch_args(&dir)),
+            (
+                "setBreakpoints",
+                json!({
+                    "source": { "path": dir.join("snake.asm") },
+                    "breakpoints": [{ "line": 3 }, { "line": 5 }, { "line": 20 }],
+                }),
+            ),
+            ("configurationDone", Value::Null),
+            ("stepIn", json!({ "threadId": THREAD_ID })),
+            ("stackTrace", json!({ "threadId": THREAD_ID })),
+            ("stepOut", json!({ "threadId": THREAD_ID })),
+            ("next", json!({ "threadId": THREAD_ID })),
+            ("continue", json!({ "threadId": THREAD_ID })),
+            ("stackTrace", json!({ "threadId": THREAD_ID })),
+            (
+                "variables",
+                json!({ "variablesReference": REGISTERS_REFERENCE }),
+            ),
+            (
+                "variables",
+                json!({ "variablesReference": FLAGS_REFERENCE }),
+            ),
+            (
+                "readMemory",
+                json!({ "memoryReference": "0x8000", "offset": 1, "count": 2 }),
+            ),
+            ("disconnect", Value::Null),
+        ]);
+        assert_eq!("initialized", messages[1]["event"]);
+        assert_eq!(true, response(&messages, 2)["success"]);
+        assert_eq!(
+            json!([
+                { "verified": true, "line": 3 },
+                { "verified": true, "line": 6 },
+                { "verified": false, "line": 20 },
+            ]),
+            response(&messages, 3)["body"]["breakpoints"]
+        );
+        assert_eq!(
+            vec!["entry", "step", "step", "step", "breakpoint"],
+            stops(&messages)
+        );
+
+        let frame = &response(&messages, 6)["body"]["stackFrames"][0];
+        assert_eq!(8, frame["line"]);
+        assert_eq!("LDY #$01", frame["name"]);
+        assert_eq!("0x800A", frame["instructionPointerReference"]);
+        let frame = &response(&messages, 10)["body"]["stackFrames"][0];
+        assert_eq!(6, frame["line"]);
+
+        let registers = &response(&messages, 11)["body"]["variables"];
+        assert_eq!(
+            json!({ "name": "X", "value": "$01", "variablesReference": 0 }),
+            registers[1]
+        );
+        assert_eq!("$01", registers[2]["value"]);
+        assert_eq!("$8007", registers[5]["value"]);
+        let flags = &response(&messages, 12)["body"]["variables"];
+        assert_eq!(
+            json!({ "name": "I", "value": "1", "variablesReference": 0 }),
+            flags[5]
+        );
+        assert_eq!("0", flags[0]["value"]);
+
+        let memory = &response(&messages, 13)["body"];
+        assert_eq!("0x8001", memory["address"]);
+        assert_eq!(encode_base64(&[0x0A, 0x80]), memory["data"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pause_and_failures() {
+        let dir = write_files("pause");
+        let messages = session(&[
+            ("launch", json!({ "program": dir.join("missing.bin") })),
+            (
+                "launch",
+                json!({ "program": dir.join("snake.bin"), "origin": 0x8000 }),
+            ),
+            ("configurationDone", Value::Null),
+            ("pause", json!({ "threadId": THREAD_ID })),
+            ("stackTrace", json!({ "threadId": THREAD_ID })),
+            ("evaluate", json!({ "expression": "A" })),
+            ("disconnect", Value::Null),
+        ]);
+        assert_eq!(false, response(&messages, 1)["success"]);
+        assert_eq!(true, response(&messages, 2)["success"]);
+        assert_eq!(vec!["pause"], stops(&messages));
+        let frame = &response(&messages, 5)["body"]["stackFrames"][0];
+        assert_eq!("JMP $8007", frame["name"]);
+        assert!(frame.get("source").is_none());
+        assert_eq!(false, response(&messages, 6)["success"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!("", encode_base64(&[]));
+        assert_eq!("TQ==", encode_base64(b"M"));
+        assert_eq!("TWE=", encode_base64(b"Ma"));
+        assert_eq!("TWFu", encode_base64(b"Man"));
+    }
+}
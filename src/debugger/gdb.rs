@@ -0,0 +1,482 @@
+//! GDB Remote Serial Protocol server, for `target remote host:port`.
+//! https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+//! The registers are numbered A, X, Y, SP, PC and P, see `TARGET_XML`.
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Range;
+
+use super::{Access, Debugger, StopReason, Watchpoint};
+use crate::cpu::Registers;
+
+const INTERRUPT: u8 = 0x03;
+/// Instructions run between two checks for an interrupt from the client.
+const CONTINUE_CHUNK: u64 = 10_000;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+/// Byte ranges of A, X, Y, SP, PC and P in the `g` packet, PC is little endian.
+const REGISTERS: [Range<usize>; 6] = [0..1, 1..2, 2..3, 3..4, 4..6, 6..7];
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes-emu.mos6502">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="p" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// Serves one GDB client at a time over TCP.
+pub struct GdbStub {
+    debugger: Debugger,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> Self {
+        Self { debugger }
+    }
+
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Waits for a client on `addr` and serves it until it detaches.
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        self.serve(stream)
+    }
+
+    /// Answers the packets of `stream` until the client detaches, kills or leaves.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut connection = Connection::new(stream)?;
+        while let Some(packet) = connection.read_packet()? {
+            match packet.as_str() {
+                "k" => return Ok(()),
+                "D" => return connection.write_packet("OK"),
+                "QStartNoAckMode" => {
+                    connection.write_packet("OK")?;
+                    connection.is_acked = false;
+                }
+                _ if packet.starts_with('c') => {
+                    let reply = match self.jump(&packet[1..]) {
+                        Some(()) => self.resume(&mut connection)?,
+                        None => "E01".to_string(),
+                    };
+                    connection.write_packet(&reply)?;
+                }
+                _ => {
+                    let reply = self.reply(&packet).unwrap_or_else(|| "E01".to_string());
+                    connection.write_packet(&reply)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reply to the packets answered without running, `None` when malformed.
+    fn reply(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = (packet.get(..1)?, &packet[1..]);
+        let reply = match command {
+            "?" => stop_reply(StopReason::Step),
+            "g" => encode_hex(&register_bytes(&self.debugger.registers())),
+            "G" => {
+                let registers = registers_from_bytes(&decode_hex(args)?)?;
+                self.debugger.set_registers(&registers);
+                "OK".to_string()
+            }
+            "p" => {
+                let bytes = register_bytes(&self.debugger.registers());
+                let range = REGISTERS.get(usize::from(parse_hex(args)?))?;
+                encode_hex(&bytes[range.clone()])
+            }
+            "P" => {
+                let (index, value) = args.split_once('=')?;
+                let range = REGISTERS.get(usize::from(parse_hex(index)?))?;
+                let mut bytes = register_bytes(&self.debugger.registers());
+                let value = decode_hex(value).filter(|value| value.len() == range.len())?;
+                bytes[range.clone()].copy_from_slice(&value);
+                self.debugger.set_registers(&registers_from_bytes(&bytes)?);
+                "OK".to_string()
+            }
+            "m" => {
+                let (addr, len) = args.split_once(',')?;
+                encode_hex(&self.debugger.read_memory(parse_hex(addr)?, parse_hex(len)?))
+            }
+            "M" => {
+                let (addr, data) = args.split_once(',')?;
+                let (_, data) = data.split_once(':')?;
+                self.debugger
+                    .write_memory(parse_hex(addr)?, &decode_hex(data)?);
+                "OK".to_string()
+            }
+            "s" => {
+                self.jump(args)?;
+                stop_reply(self.debugger.step_into())
+            }
+            "Z" => self.set_point(args, true)?,
+            "z" => self.set_point(args, false)?,
+            "H" => "OK".to_string(),
+            _ => match packet {
+                "qAttached" => "1".to_string(),
+                _ if packet.starts_with("qSupported") => {
+                    "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string()
+                }
+                _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                    read_target_xml(&packet["qXfer:features:read:target.xml:".len()..])?
+                }
+                _ => String::new(),
+            },
+        };
+        Some(reply)
+    }
+
+    /// Moves the program counter to the optional address of `c` and `s`.
+    fn jump(&mut self, addr: &str) -> Option<()> {
+        if !addr.is_empty() {
+            let registers = Registers {
+                program_counter: parse_hex(addr)?,
+                ..self.debugger.registers()
+            };
+            self.debugger.set_registers(&registers);
+        }
+        Some(())
+    }
+
+    /// Runs by chunks, checking between them whether the client interrupts.
+    fn resume(&mut self, connection: &mut Connection) -> io::Result<String> {
+        self.debugger.set_instruction_limit(CONTINUE_CHUNK);
+        loop {
+            let reason = self.debugger.resume();
+            let counter = self.debugger.registers().program_counter;
+            match reason {
+                // the next chunk would run over this breakpoint
+                StopReason::InstructionLimit if self.debugger.has_breakpoint(counter) => {
+                    return Ok(stop_reply(StopReason::Breakpoint(counter)))
+                }
+                StopReason::InstructionLimit => {
+                    if connection.is_interrupted()? {
+                        return Ok(format!("S{SIGINT:02x}"));
+                    }
+                }
+                reason => return Ok(stop_reply(reason)),
+            }
+        }
+    }
+
+    /// `Z` and `z` packets: `type,addr,kind`, the kind is the length of a watchpoint.
+    fn set_point(&mut self, args: &str, is_inserted: bool) -> Option<String> {
+        let mut fields = args.split(';').next()?.split(',');
+        let (point, addr, len) = (fields.next()?, fields.next()?, fields.next()?);
+        let addr = parse_hex(addr)?;
+        let range = addr..=addr.saturating_add(parse_hex(len)?.max(1) - 1);
+        let watchpoint = match point {
+            "0" | "1" if is_inserted => {
+                self.debugger.add_breakpoint(addr);
+                return Some("OK".to_string());
+            }
+            "0" | "1" => {
+                self.debugger.remove_breakpoint(addr);
+                return Some("OK".to_string());
+            }
+            "2" => Watchpoint::write(range),
+            "3" => Watchpoint::read(range),
+            "4" => Watchpoint::access(range),
+            _ => return Some(String::new()),
+        };
+        if is_inserted {
+            self.debugger.add_watchpoint(watchpoint);
+        } else {
+            self.debugger.remove_watchpoint(&watchpoint);
+        }
+        Some("OK".to_string())
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    is_acked: bool,
+    last_packet: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            is_acked: true,
+            last_packet: Vec::new(),
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Next packet data with a valid checksum, `None` once the client left.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => self.writer.write_all(&self.last_packet)?,
+                // acks and interrupts while already stopped
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            self.reader.read_until(b'#', &mut data)?;
+            if data.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let is_valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                == Some(checksum_of(&data));
+            if self.is_acked {
+                self.writer.write_all(if is_valid { b"+" } else { b"-" })?;
+            }
+            if is_valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        self.last_packet = format!("${data}#{:02x}", checksum_of(data.as_bytes())).into_bytes();
+        self.writer.write_all(&self.last_packet)
+    }
+
+    /// Whether the client sent an interrupt or left, without blocking.
+    fn is_interrupted(&mut self) -> io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let byte = self.read_byte();
+        self.reader.get_ref().set_nonblocking(false)?;
+        match byte {
+            Ok(None) => Ok(true),
+            Ok(Some(byte)) => Ok(byte == INTERRUPT),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn stop_reply(reason: StopReason) -> String {
+    match reason {
+        StopReason::Watchpoint(hit) => {
+            let kind = match hit.access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+            };
+            format!("T{SIGTRAP:02x}{kind}:{:04x};", hit.addr)
+        }
+        StopReason::Jammed(_) => format!("S{SIGILL:02x}"),
+        _ => format!("S{SIGTRAP:02x}"),
+    }
+}
+
+/// `offset,length` window of the target description, `l` marks the last one.
+fn read_target_xml(args: &str) -> Option<String> {
+    let (offset, length) = args.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+    let start = offset.min(TARGET_XML.len());
+    let end = start.saturating_add(length).min(TARGET_XML.len());
+    let prefix = if end == TARGET_XML.len() { 'l' } else { 'm' };
+    Some(format!("{prefix}{}", &TARGET_XML[start..end]))
+}
+
+fn register_bytes(registers: &Registers) -> [u8; 7] {
+    let [counter_lo, counter_hi] = registers.program_counter.to_le_bytes();
+    [
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.stack_pointer,
+        counter_lo,
+        counter_hi,
+        registers.status,
+    ]
+}
+
+fn registers_from_bytes(bytes: &[u8]) -> Option<Registers> {
+    let &[a, x, y, stack_pointer, counter_lo, counter_hi, status] = bytes else {
+        return None;
+    };
+    Some(Registers {
+        program_counter: u16::from_le_bytes([counter_lo, counter_hi]),
+        stack_pointer,
+        a,
+        x,
+        y,
+        status,
+    })
+}
+
+fn parse_hex(hex: &str) -> Option<u16> {
+    u16::from_str_radix(hex, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Nes;
+    use std::net::SocketAddr;
+    use std::thread;
+    use std::time::Duration;
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 10] = [
+        0xA9, 0x07,       // LDA #$07
+        0xE8,             // INX
+        0x8D, 0x00, 0x02, // STA $0200
+        0x4C, 0x06, 0x80, // JMP $8006
+        0x02,             // JAM
+    ];
+
+    /// Scripted client, answers the acks and returns the reply data.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: SocketAddr) -> Self {
+            Self {
+                stream: TcpStream::connect(addr).unwrap(),
+            }
+        }
+
+        fn read_reply(&mut self) -> String {
+            let mut reply = Vec::new();
+            let mut byte = [0];
+            while byte[0] != b'$' {
+                self.stream.read_exact(&mut byte).unwrap();
+            }
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                reply.push(byte[0]);
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            assert_eq!(format!("{:02x}", checksum_of(&reply)).as_bytes(), checksum);
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(reply).unwrap()
+        }
+
+        fn write_packet(&mut self, data: &str) {
+            write!(self.stream, "${data}#{:02x}", checksum_of(data.as_bytes())).unwrap();
+        }
+
+        fn send(&mut self, data: &str) {
+            self.write_packet(data);
+            let mut ack = [0];
+            self.stream.read_exact(&mut ack).unwrap();
+            assert_eq!(b'+', ack[0]);
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.read_reply()
+        }
+    }
+
+    /// Serves a stub on the program while `script` runs on a client thread.
+    fn with_client(script: impl FnOnce(Client) + Send + 'static) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || script(Client::connect(addr)));
+        let mut nes = Nes::new();
+        nes.load(&PROGRAM, 0x8000);
+        let mut stub = GdbStub::new(Debugger::new(nes));
+        stub.serve(listener.accept().unwrap().0).unwrap();
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        with_client(|mut client| {
+            assert_eq!("S05", client.request("?"));
+            assert!(client.request("g").starts_with("000000fd0080"));
+            assert_eq!("OK", client.request("P0=42"));
+            assert_eq!("42", client.request("p0"));
+            assert_eq!("0080", client.request("p4"));
+            assert_eq!("E01", client.request("p9"));
+            assert_eq!("a907e8", client.request("m8000,3"));
+            assert_eq!("OK", client.request("M0300,2:abcd"));
+            assert_eq!("abcd", client.request("m0300,2"));
+            assert_eq!("", client.request("vMustReplyEmpty"));
+            assert!(client
+                .request("qXfer:features:read:target.xml:0,1000")
+                .starts_with("l<?xml"));
+            assert!(client
+                .request("qXfer:features:read:target.xml:0,10")
+                .starts_with('m'));
+            assert_eq!("OK", client.request("D"));
+        });
+    }
+
+    #[test]
+    fn test_breakpoints_watchpoints_and_step() {
+        with_client(|mut client| {
+            assert_eq!("S05", client.request("s"));
+            assert_eq!("07", client.request("p0"));
+            assert_eq!("OK", client.request("Z0,8006,1"));
+            assert_eq!("S05", client.request("c"));
+            assert_eq!("0680", client.request("p4"));
+            assert_eq!("OK", client.request("z0,8006,1"));
+            assert_eq!("OK", client.request("Z2,0200,1"));
+            assert_eq!("T05watch:0200;", client.request("c8003"));
+            assert_eq!("OK", client.request("z2,0200,1"));
+            assert_eq!("S04", client.request("c8009"));
+            client.send("k");
+        });
+    }
+
+    #[test]
+    fn test_interrupt_and_no_ack_mode() {
+        with_client(|mut client| {
+            assert_eq!("OK", client.request("QStartNoAckMode"));
+            client.write_packet("c");
+            thread::sleep(Duration::from_millis(50));
+            client.stream.write_all(&[INTERRUPT]).unwrap();
+            assert_eq!("S02", client.read_reply());
+            client.write_packet("p4");
+            assert_eq!("0680", client.read_reply());
+        });
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+use crate::cpu::disasm::{self, Line};
+use crate::cpu::Registers;
+use crate::traits::Memory;
+use crate::Nes;
+
+pub mod dap;
+pub mod gdb;
+pub mod source_map;
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+const JSR_LEN: u16 = 3;
+/// Bounds every run so an endless loop gives the control back.
+const DEFAULT_INSTRUCTION_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Stops on the accesses to `range`, optionally only when `value` is read or written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub on_read: bool,
+    pub on_write: bool,
+    pub value: Option<u8>,
+}
+
+impl Watchpoint {
+    pub fn read(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            on_read: true,
+            on_write: false,
+            value: None,
+        }
+    }
+
+    pub fn write(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            on_read: false,
+            on_write: true,
+            value: None,
+        }
+    }
+
+    pub fn access(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            on_read: true,
+            on_write: true,
+            value: None,
+        }
+    }
+
+    pub fn with_value(self, value: u8) -> Self {
+        Self {
+            value: Some(value),
+            ..self
+        }
+    }
+
+    pub fn matches(&self, addr: u16, value: u8, access: Access) -> bool {
+        let is_watched_access = match access {
+            Access::Read => self.on_read,
+            Access::Write => self.on_write,
+        };
+        is_watched_access
+            && self.range.contains(&addr)
+            && self.value.is_none_or(|expected| expected == value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    pub access: Access,
+}
+
+/// Why the execution gave the control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step, step over or step out is done.
+    Step,
+    /// The next instruction is on a breakpoint.
+    Breakpoint(u16),
+    /// The last instruction made a watched access.
+    Watchpoint(WatchHit),
+    /// The run to cursor reached its address.
+    Cursor(u16),
+    /// A JAM opcode froze the CPU at this address.
+    Jammed(u16),
+    /// The instruction limit was reached, see `Debugger::set_instruction_limit`.
+    InstructionLimit,
+}
+
+/// Breakpoints, watchpoints and stepping over a `Nes`.
+pub struct Debugger {
+    nes: Nes,
+    breakpoints: BTreeSet<u16>,
+    instruction_limit: u64,
+}
+
+impl Debugger {
+    pub fn new(nes: Nes) -> Self {
+        Self {
+            nes,
+            breakpoints: BTreeSet::new(),
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+        }
+    }
+
+    pub fn nes(&mut self) -> &mut Nes {
+        &mut self.nes
+    }
+
+    pub fn into_nes(self) -> Nes {
+        self.nes
+    }
+
+    pub fn set_instruction_limit(&mut self, limit: u64) {
+        self.instruction_limit = limit;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.nes.bus_mut().watchpoints_mut().push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let watchpoints = self.nes.bus_mut().watchpoints_mut();
+        let count = watchpoints.len();
+        watchpoints.retain(|other| other != watchpoint);
+        watchpoints.len() != count
+    }
+
+    pub fn registers(&self) -> Registers {
+        self.nes.cpu.registers()
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.nes.cpu.set_registers(registers)
+    }
+
+    /// Reads `len` bytes from `addr` without side effects, see `Memory::peek_u8`.
+    pub fn read_memory(&self, addr: u16, len: u16) -> Vec<u8> {
+        (0..len)
+            .map(|offset| self.nes.peek_u8(addr.wrapping_add(offset)))
+            .collect()
+    }
+
+    /// Writes through the bus, the mapper and device registers react like on a CPU write.
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) {
+        let bus = self.nes.bus_mut();
+        for (offset, &byte) in (0..).zip(data) {
+            bus.mem_write_u8(addr.wrapping_add(offset), byte);
+        }
+    }
+
+    /// The next `count` instructions from the program counter.
+    pub fn disassemble(&self, count: usize) -> Vec<Line> {
+        disasm::disassemble_memory(
+            self.nes.bus(),
+            self.nes.cpu.registers().program_counter,
+            count,
+        )
+    }
+
+    /// Runs until a breakpoint or a watchpoint stops the execution.
+    pub fn resume(&mut self) -> StopReason {
+        self.run_until(|_, _| false)
+    }
+
+    /// Executes one instruction, entering the subroutines and interrupt handlers.
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(|_, _| true)
+    }
+
+    /// Like `step_into` but a JSR runs until its subroutine returns.
+    pub fn step_over(&mut self) -> StopReason {
+        let registers = self.registers();
+        if self.nes.peek_u8(registers.program_counter) != JSR {
+            return self.step_into();
+        }
+        let return_addr = registers.program_counter.wrapping_add(JSR_LEN);
+        self.run_until(|_, after| {
+            after.program_counter == return_addr && after.stack_pointer >= registers.stack_pointer
+        })
+    }
+
+    /// Runs until the RTS or RTI leaving the current subroutine or handler.
+    pub fn step_out(&mut self) -> StopReason {
+        let stack_pointer = self.registers().stack_pointer;
+        self.run_until(|opcode, after| {
+            matches!(opcode, RTS | RTI) && after.stack_pointer > stack_pointer
+        })
+    }
+
+    /// Runs until the program counter reaches `addr`.
+    pub fn run_to_cursor(&mut self, addr: u16) -> StopReason {
+        match self.run_until(|_, after| after.program_counter == addr) {
+            StopReason::Step => StopReason::Cursor(addr),
+            reason => reason,
+        }
+    }
+
+    /// Executes instructions until `is_done`, given the executed opcode and
+    /// the registers after it, or a breakpoint or a watchpoint stop.
+    /// The first instruction runs even when it is on a breakpoint.
+    fn run_until(&mut self, mut is_done: impl FnMut(u8, &Registers) -> bool) -> StopReason {
+        self.nes.bus_mut().take_watch_hit();
+        for executed in 0..self.instruction_limit {
+            let counter = self.registers().program_counter;
+            if executed > 0 && self.has_breakpoint(counter) {
+                return StopReason::Breakpoint(counter);
+            }
+            if self.nes.cpu.is_jammed() {
+                return StopReason::Jammed(counter);
+            }
+            let opcode = self.nes.peek_u8(counter);
+            self.nes.run();
+            if let Some(hit) = self.nes.bus_mut().take_watch_hit() {
+                return StopReason::Watchpoint(hit);
+            }
+            if is_done(opcode, &self.registers()) {
+                return StopReason::Step;
+            }
+        }
+        StopReason::InstructionLimit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 0x13] = [
+        0x20, 0x10, 0x80, // JSR $8010
+        0xE8,             // INX
+        0x8D, 0x00, 0x02, // STA $0200
+        0x4C, 0x07, 0x80, // JMP $8007
+        0, 0, 0, 0, 0, 0,
+        0xC8,             // $8010 INY
+        0xC8,             // INY
+        0x60,             // RTS
+    ];
+
+    fn debugger() -> Debugger {
+        let mut nes = Nes::new();
+        nes.load(&PROGRAM, 0x8000);
+        Debugger::new(nes)
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(0x8004);
+        assert_eq!(StopReason::Breakpoint(0x8004), debugger.resume());
+        assert_eq!(1, debugger.registers().x);
+        assert_eq!(2, debugger.registers().y);
+        debugger.set_instruction_limit(10);
+        assert_eq!(StopReason::InstructionLimit, debugger.resume());
+    }
+
+    #[test]
+    fn test_step_into_and_step_out() {
+        let mut debugger = debugger();
+        assert_eq!(StopReason::Step, debugger.step_into());
+        assert_eq!(0x8010, debugger.registers().program_counter);
+        assert_eq!(StopReason::Step, debugger.step_into());
+        assert_eq!(StopReason::Step, debugger.step_out());
+        assert_eq!(0x8003, debugger.registers().program_counter);
+        assert_eq!(2, debugger.registers().y);
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut debugger = debugger();
+        assert_eq!(StopReason::Step, debugger.step_over());
+        assert_eq!(0x8003, debugger.registers().program_counter);
+        assert_eq!(StopReason::Step, debugger.step_over());
+        assert_eq!(0x8004, debugger.registers().program_counter);
+    }
+
+    #[test]
+    fn test_breakpoint_inside_step_over() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(0x8011);
+        assert_eq!(StopReason::Breakpoint(0x8011), debugger.step_over());
+    }
+
+    #[test]
+    fn test_run_to_cursor() {
+        let mut debugger = debugger();
+        assert_eq!(StopReason::Cursor(0x8007), debugger.run_to_cursor(0x8007));
+        assert_eq!("JMP $8007", debugger.disassemble(1)[0].to_string());
+    }
+
+    #[test]
+    fn test_write_watchpoint_value_condition() {
+        let mut debugger = debugger();
+        debugger.set_instruction_limit(20);
+        debugger.add_watchpoint(Watchpoint::write(0x0200..=0x0200).with_value(1));
+        assert_eq!(StopReason::InstructionLimit, debugger.resume());
+
+        let mut debugger = self::debugger();
+        debugger.add_watchpoint(Watchpoint::write(0x0200..=0x02FF).with_value(0));
+        assert_eq!(
+            StopReason::Watchpoint(WatchHit {
+                addr: 0x0200,
+                value: 0,
+                access: Access::Write
+            }),
+            debugger.resume()
+        );
+        assert_eq!(0x8007, debugger.registers().program_counter);
+    }
+
+    #[test]
+    fn test_stack_watchpoints() {
+        let mut debugger = debugger();
+        let watchpoint = Watchpoint::write(0x0100..=0x01FF);
+        debugger.add_watchpoint(watchpoint.clone());
+        // the JSR pushes its return address minus one, high byte first
+        assert_eq!(
+            StopReason::Watchpoint(WatchHit {
+                addr: 0x01FD,
+                value: 0x80,
+                access: Access::Write
+            }),
+            debugger.resume()
+        );
+        assert!(debugger.remove_watchpoint(&watchpoint));
+        debugger.add_watchpoint(Watchpoint::read(0x01FC..=0x01FD));
+        assert_eq!(
+            StopReason::Watchpoint(WatchHit {
+                addr: 0x01FC,
+                value: 0x02,
+                access: Access::Read
+            }),
+            debugger.resume()
+        );
+        assert_eq!(0x8003, debugger.registers().program_counter);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceMapError {
+    /// The 1-based line of the map which is not an `address line` pair.
+    InvalidEntry(usize),
+}
+
+impl fmt::Display for SourceMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceMapError::InvalidEntry(line) => {
+                write!(
+                    f,
+                    "line {line} of the source map is not an `address line` pair"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SourceMapError {}
+
+/// Source lines of the assembled instructions.
+/// The text format holds one `address line` pair per line, the address in
+/// hexadecimal with an optional `$` and the 1-based source line in decimal,
+/// like `$8000 12`. Empty lines and `;` comments are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    lines: BTreeMap<u16, usize>,
+    /// Lowest address of each line.
+    addrs: BTreeMap<usize, u16>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SourceMapError> {
+        let mut map = Self::new();
+        for (index, entry) in text.lines().enumerate() {
+            let entry = entry.split(';').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let invalid = SourceMapError::InvalidEntry(index + 1);
+            let (addr, line) = entry
+                .split_once(char::is_whitespace)
+                .ok_or(invalid.clone())?;
+            let addr = u16::from_str_radix(addr.trim_start_matches('$'), 16)
+                .map_err(|_| invalid.clone())?;
+            let line = line.trim().parse().map_err(|_| invalid)?;
+            map.insert(addr, line);
+        }
+        Ok(map)
+    }
+
+    pub fn insert(&mut self, addr: u16, line: usize) {
+        self.lines.insert(addr, line);
+        let lowest = self.addrs.entry(line).or_insert(addr);
+        *lowest = (*lowest).min(addr);
+    }
+
+    pub fn line(&self, addr: u16) -> Option<usize> {
+        self.lines.get(&addr).copied()
+    }
+
+    /// First line from `line` holding an instruction, with its address.
+    pub fn resolve_line(&self, line: usize) -> Option<(usize, u16)> {
+        self.addrs
+            .range(line..)
+            .next()
+            .map(|(&line, &addr)| (line, addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let map = SourceMap::parse("; snake\n$8000 2\n8003 3 ; inx\n\n8005 3\n").unwrap();
+        assert_eq!(Some(2), map.line(0x8000));
+        assert_eq!(Some(3), map.line(0x8005));
+        assert_eq!(None, map.line(0x8001));
+        assert_eq!(Some((3, 0x8003)), map.resolve_line(3));
+        assert_eq!(Some((2, 0x8000)), map.resolve_line(1));
+        assert_eq!(None, map.resolve_line(4));
+    }
+
+    #[test]
+    fn test_invalid_entry() {
+        assert_eq!(
+            Err(SourceMapError::InvalidEntry(2)),
+            SourceMap::parse("8000 1\n8002\n")
+        );
+        assert_eq!(
+            Err(SourceMapError::InvalidEntry(1)),
+            SourceMap::parse("zz 1")
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use bitflags::bitflags;
 
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
 use crate::traits::Device;
 
 bitflags! {
@@ -15,12 +16,15 @@ bitflags! {
     }
 }
 
+/// Both controller ports latch the buttons on the writes to $4016.
+/// https://www.nesdev.org/wiki/Standard_controller#Input_($4016_write)
+pub const STROBE: u16 = 0x4016;
+
 pub struct Joypad {
     address: u16,
     is_strobe_on: bool,
     current_button_mask: Button,
     button_status: Button,
-    memory: *mut u8,
 }
 
 impl Joypad {
@@ -30,7 +34,6 @@ impl Joypad {
             is_strobe_on: false,
             current_button_mask: Button::A,
             button_status: Button::from_bits_truncate(0),
-            memory: std::ptr::null_mut(),
         }
     }
 
@@ -48,31 +51,55 @@ impl Device for Joypad {
         usize::from(self.address)..usize::from(self.address + 1)
     }
 
-    fn map(&mut self, memory: &mut [u8]) {
-        self.memory = &mut memory[0]
+    /// Bit of the next button, the following read gets the next one
+    /// unless the strobe is on.
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.peek(addr);
+        if !self.is_strobe_on {
+            self.current_button_mask.bits <<= 1;
+        }
+        data
     }
 
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_read(&mut self) {
-        self.is_strobe_on = *self.memory & 1 == 1;
+    /// The writes to $4017 are the APU frame counter, not a strobe.
+    fn write(&mut self, addr: u16, data: u8) {
+        if addr != STROBE {
+            return;
+        }
+        self.is_strobe_on = data & 1 == 1;
         if self.is_strobe_on {
             self.current_button_mask = Button::A
         } else {
         }
     }
 
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_write(&mut self) {
+    /// Bit of the next button, 1 once the eight buttons were read.
+    fn peek(&self, _addr: u16) -> u8 {
         if self.current_button_mask.is_empty() {
-            *self.memory = 1;
-            return;
-        }
-        *self.memory = u8::from(self.button_status.contains(self.current_button_mask));
-        if !self.is_strobe_on {
-            self.current_button_mask.bits <<= 1;
+            return 1;
         }
+        u8::from(self.button_status.contains(self.current_button_mask))
+    }
+
+    /// Only D0 carries the buttons, D1-D4 are the unused expansion lines.
+    /// https://www.nesdev.org/wiki/Standard_controller#Output_($4016/$4017_read)
+    fn open_bus_mask(&self, _addr: u16) -> u8 {
+        0b1110_0000
+    }
+}
+
+impl Snapshot for Joypad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_strobe_on);
+        writer.write_u8(self.current_button_mask.bits());
+        writer.write_u8(self.button_status.bits());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.is_strobe_on = reader.read_bool()?;
+        self.current_button_mask = Button::from_bits_truncate(reader.read_u8()?);
+        self.button_status = Button::from_bits_truncate(reader.read_u8()?);
+        Ok(())
     }
 }
 
@@ -81,87 +108,90 @@ mod tests {
 
     #[test]
     fn test_press_button_a() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
-        unsafe {
-            joypad.mem_write();
-        }
-        assert_eq!(joypad_byte[0], 1);
+        assert_eq!(1, joypad.read(0x4016));
     }
 
     #[test]
     fn test_release_button_a() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
         joypad.release(Button::A);
-        unsafe {
-            joypad.mem_write();
-        }
-        assert_eq!(joypad_byte[0], 0);
+        assert_eq!(0, joypad.read(0x4016));
     }
 
     #[test]
     fn test_button_index_reset() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
-        unsafe {
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 1);
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 0);
-
-            std::ptr::write_volatile(&mut joypad_byte[0], 1);
-            joypad.mem_read();
-            std::ptr::write_volatile(&mut joypad_byte[0], 0);
-            joypad.mem_read();
-
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 1);
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 0);
-        }
+        assert_eq!(1, joypad.read(0x4016));
+        assert_eq!(0, joypad.read(0x4016));
+
+        joypad.write(0x4016, 1);
+        joypad.write(0x4016, 0);
+
+        assert_eq!(1, joypad.read(0x4016));
+        assert_eq!(0, joypad.read(0x4016));
+    }
+
+    #[test]
+    fn test_second_port_ignores_frame_counter_writes() {
+        let mut joypad = Joypad::new(0x4017);
+        joypad.press(Button::A);
+        joypad.write(0x4016, 1);
+        joypad.write(0x4016, 0);
+        joypad.write(0x4017, 1);
+        assert_eq!(1, joypad.read(0x4017));
+        assert_eq!(0, joypad.read(0x4017));
+    }
+
+    #[test]
+    fn test_peek_does_not_shift() {
+        let mut joypad = Joypad::new(0x4016);
+        joypad.press(Button::A);
+        assert_eq!(1, joypad.peek(0x4016));
+        assert_eq!(1, joypad.peek(0x4016));
+        joypad.read(0x4016);
+        assert_eq!(0, joypad.peek(0x4016));
     }
 
     #[test]
     fn test_reading_when_strobe_off() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
         joypad.press(Button::SELECT);
         joypad.press(Button::UP);
 
         let expected_results = [1, 0, 1, 0, 1, 0, 0, 0, 1, 1, 1];
         for result in expected_results {
-            unsafe {
-                joypad.mem_write();
-            }
-            assert_eq!(joypad_byte[0], result);
+            assert_eq!(result, joypad.read(0x4016));
         }
     }
 
     #[test]
     fn test_reading_when_strobe_on() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
-        unsafe {
-            std::ptr::write_volatile(&mut joypad_byte[0], 1);
-            joypad.mem_read();
-        }
+        joypad.write(0x4016, 1);
         joypad.press(Button::A);
 
         for _ in 0..3 {
-            unsafe {
-                joypad.mem_write();
-            }
-            assert_eq!(joypad_byte[0], 1);
+            assert_eq!(1, joypad.read(0x4016));
         }
     }
+
+    #[test]
+    fn test_state_restores_the_shift() {
+        let mut joypad = Joypad::new(0x4016);
+        joypad.press(Button::B);
+        let mut writer = StateWriter::new();
+        joypad.save_state(&mut writer);
+        let state = writer.into_inner();
+        joypad.read(0x4016);
+        assert_eq!(1, joypad.read(0x4016));
+        joypad.release(Button::B);
+        joypad.load_state(&mut StateReader::new(&state)).unwrap();
+        assert_eq!(0, joypad.read(0x4016));
+        assert_eq!(1, joypad.read(0x4016));
+    }
 }
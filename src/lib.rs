@@ -1,113 +1,234 @@
+pub mod apu;
 mod bus;
+pub mod bus_log;
+pub mod cartridge;
 pub mod cpu;
+pub mod debugger;
 pub mod joypad;
+pub mod ppu;
 mod random_gen;
+pub mod rewind;
 pub mod screen;
+pub mod state;
 pub mod traits;
 use bus::Bus;
-use cpu::{Cpu, PROGRAM_POINTER};
+use bus_log::BusLog;
+use cartridge::{Cartridge, CartridgeError};
+use cpu::{Cpu, Tracer, PROGRAM_POINTER};
 use joypad::{Button, Joypad};
-use random_gen::RandomGenerator;
-use screen::Screen;
-use std::{marker::PhantomPinned, pin::Pin, ptr};
-use traits::Memory;
+use state::{Snapshot, State, StateError, StateWriter, Tag};
+use traits::{Device, Memory};
+
+const TRAINER_ADDR: u16 = 0x7000;
+const CARTRIDGE_SECTION: Tag = *b"CART";
+const CPU_SECTION: Tag = *b"CPU ";
+const RAM_SECTION: Tag = *b"RAM ";
+const BUS_SECTION: Tag = *b"BUS ";
+const JOYPAD_1_SECTION: Tag = *b"PAD1";
+const JOYPAD_2_SECTION: Tag = *b"PAD2";
+const RANDOM_SECTION: Tag = *b"RNG ";
+const PPU_SECTION: Tag = *b"PPU ";
+const APU_SECTION: Tag = *b"APU ";
 
 pub enum Player {
     One,
     Two,
 }
 
+/// The console, its CPU owns the bus and the bus owns every other chip.
 pub struct Nes {
-    memory: [u8; 0xFFFF],
-    joypad_1: Joypad,
-    joypad_2: Joypad,
-    color_generator: RandomGenerator,
-    screen: Screen,
-    bus: Bus,
-    cpu: Cpu,
-    _pin: PhantomPinned,
+    cpu: Cpu<Bus>,
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Nes {
-    pub fn new() -> Pin<Box<Self>> {
-        let nes = Self {
-            memory: [0; 0xFFFF],
-            joypad_1: Joypad::new(0x4016),
-            joypad_2: Joypad::new(0x4017),
-            color_generator: RandomGenerator::new(0x4018, 1..16),
-            screen: Screen::default(),
-            bus: Bus::new(),
-            cpu: Cpu::new(ptr::null_mut::<Bus>()),
-            _pin: PhantomPinned,
-        };
-
-        let mut pinned_boxed_nes = Box::pin(nes);
-        unsafe {
-            pinned_boxed_nes.map_devices();
+    pub fn new() -> Self {
+        Self {
+            cpu: Cpu::new(Bus::new()),
         }
-        pinned_boxed_nes
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn press(self: &mut Pin<Box<Self>>, player: Player, button: joypad::Button) {
-        self.get_mut_from_pin().player_joypad(player).press(button)
+    pub fn press(&mut self, player: Player, button: joypad::Button) {
+        self.player_joypad(player).press(button)
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn release(self: &mut Pin<Box<Self>>, player: Player, button: joypad::Button) {
-        self.get_mut_from_pin()
-            .player_joypad(player)
-            .release(button)
+    pub fn release(&mut self, player: Player, button: joypad::Button) {
+        self.player_joypad(player).release(button)
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn run(self: &mut Pin<Box<Self>>) {
-        self.get_mut_from_pin().cpu.run();
+    /// Runs one CPU instruction and steps the PPU and APU in lockstep,
+    /// returns the CPU cycles spent.
+    pub fn run(&mut self) -> u32 {
+        let bus = self.cpu.memory();
+        let (irq, nmi) = (bus.irq(), bus.ppu.nmi());
+        let (scanline, dot) = (bus.ppu.scanline(), bus.ppu.dot());
+        self.cpu.set_irq(irq);
+        self.cpu.set_nmi(nmi);
+        if let Some(tracer) = self.cpu.tracer_mut() {
+            tracer.set_ppu_position(scanline, dot);
+        }
+        let cycles = self.cpu.run() + self.bus_mut().take_stall_cycles();
+        self.bus_mut().tick(cycles);
+        cycles
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn load(self: &mut Pin<Box<Self>>, data: &[u8], dest: u16) {
-        let nes = self.get_mut_from_pin();
-        nes.bus.load(data, dest);
-        nes.bus.mem_write_u16(PROGRAM_POINTER, dest);
-        nes.cpu.reset();
+    /// Runs until the PPU starts a new frame.
+    pub fn run_frame(&mut self) {
+        let frame = self.bus().ppu.frame();
+        while self.bus().ppu.frame() == frame {
+            self.run();
+        }
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn get_screen_data(self: &Pin<Box<Self>>) -> Vec<Vec<u8>> {
-        self.get_from_pin().screen.get_screen_data()
+    /// Pulses the RESET line, like the console reset button.
+    pub fn reset(&mut self) {
+        self.cpu.set_reset(true);
+        self.cpu.set_reset(false);
     }
 
-    pub fn player_joypad(&mut self, player: Player) -> &mut Joypad {
-        match player {
-            Player::One => &mut self.joypad_1,
-            Player::Two => &mut self.joypad_2,
+    pub fn load(&mut self, data: &[u8], dest: u16) {
+        let bus = self.bus_mut();
+        bus.set_cartridge(None);
+        bus.load(data, dest);
+        bus.mem_write_u16(PROGRAM_POINTER, dest);
+        self.cpu.reset();
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), CartridgeError> {
+        let cartridge = Cartridge::new(rom)?;
+        let trainer = cartridge.trainer.clone();
+        let bus = self.bus_mut();
+        bus.set_cartridge(Some(cartridge));
+        for (addr, byte) in (TRAINER_ADDR..).zip(trainer.unwrap_or_default()) {
+            bus.mem_write_u8(addr, byte);
+        }
+        self.cpu.reset();
+        Ok(())
+    }
+
+    /// Maps an extra device on the bus, the console devices keep their addresses.
+    pub fn attach_device(&mut self, device: Box<dyn Device>) {
+        self.bus_mut().attach(device)
+    }
+
+    /// Moves the CPU execution, the nestest automation mode starts at $C000.
+    pub fn set_program_counter(&mut self, addr: u16) {
+        self.cpu.set_program_counter(addr)
+    }
+
+    /// Reads the CPU address space without side effects, see `Memory::peek_u8`.
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        self.bus().peek_u8(addr)
+    }
+
+    pub fn peek_range(&self, range: std::ops::Range<u16>) -> Vec<u8> {
+        self.bus().peek_range(range)
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.bus().cartridge()
+    }
+
+    /// Traces the CPU in the nestest.log format, see `Tracer`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.set_tracer(tracer)
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.cpu.tracer_mut()
+    }
+
+    /// Records the CPU bus accesses, see `BusLog`.
+    pub fn set_bus_log(&mut self, log: Option<BusLog>) {
+        self.bus_mut().set_log(log)
+    }
+
+    pub fn bus_log(&self) -> Option<&BusLog> {
+        self.bus().log()
+    }
+
+    pub fn bus_log_mut(&mut self) -> Option<&mut BusLog> {
+        self.bus_mut().log_mut()
+    }
+
+    pub fn get_screen_data(&self) -> Vec<u8> {
+        self.bus().ppu.frame_buffer().to_vec()
+    }
+
+    /// Audio samples produced since the last call, see `Apu::take_samples`.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.bus_mut().apu.take_samples()
+    }
+
+    /// Snapshot of the whole machine, see `state`.
+    pub fn save_state(&self) -> Vec<u8> {
+        let bus = self.bus();
+        let mut writer = StateWriter::with_header();
+        if let Some(cartridge) = bus.cartridge() {
+            writer.write_section(CARTRIDGE_SECTION, |writer| cartridge.save_state(writer));
+        }
+        writer.write_section(CPU_SECTION, |writer| self.cpu.save_state(writer));
+        writer.write_section(RAM_SECTION, |writer| writer.write_bytes(bus.ram()));
+        writer.write_section(BUS_SECTION, |writer| bus.save_state(writer));
+        writer.write_section(JOYPAD_1_SECTION, |writer| bus.joypad_1.save_state(writer));
+        writer.write_section(JOYPAD_2_SECTION, |writer| bus.joypad_2.save_state(writer));
+        writer.write_section(RANDOM_SECTION, |writer| {
+            bus.color_generator.save_state(writer)
+        });
+        writer.write_section(PPU_SECTION, |writer| bus.ppu.save_state(writer));
+        writer.write_section(APU_SECTION, |writer| bus.apu.save_state(writer));
+        writer.into_inner()
+    }
+
+    /// Restores a `save_state` of the same cartridge, the machine is left
+    /// untouched when it fails.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let state = State::parse(data)?;
+        let backup = self.save_state();
+        let result = self.apply_state(&state);
+        if result.is_err() {
+            self.apply_state(&State::parse(&backup)?)?;
         }
+        result
     }
 
-    unsafe fn map_devices(self: &mut Pin<Box<Self>>) {
-        let nes_ref = self.get_mut_from_pin();
-        nes_ref.bus.map(
-            &mut nes_ref.memory,
-            &[
-                &mut nes_ref.joypad_1,
-                &mut nes_ref.joypad_2,
-                &mut nes_ref.color_generator,
-                &mut nes_ref.screen,
-            ],
-        );
-        nes_ref.cpu.set_mem(&mut nes_ref.bus)
+    fn apply_state(&mut self, state: &State) -> Result<(), StateError> {
+        match self.bus_mut().cartridge_mut() {
+            Some(cartridge) => state.load(CARTRIDGE_SECTION, cartridge)?,
+            None if state.has_section(CARTRIDGE_SECTION) => {
+                return Err(StateError::CartridgeMismatch)
+            }
+            None => {}
+        }
+        state.load(CPU_SECTION, &mut self.cpu)?;
+        let bus = self.bus_mut();
+        state.section(RAM_SECTION)?.read_bytes_into(bus.ram_mut())?;
+        state.load(BUS_SECTION, bus)?;
+        state.load(JOYPAD_1_SECTION, &mut bus.joypad_1)?;
+        state.load(JOYPAD_2_SECTION, &mut bus.joypad_2)?;
+        state.load(RANDOM_SECTION, &mut bus.color_generator)?;
+        state.load(PPU_SECTION, &mut bus.ppu)?;
+        state.load(APU_SECTION, &mut bus.apu)
+    }
+
+    fn player_joypad(&mut self, player: Player) -> &mut Joypad {
+        let bus = self.bus_mut();
+        match player {
+            Player::One => &mut bus.joypad_1,
+            Player::Two => &mut bus.joypad_2,
+        }
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn get_mut_from_pin<'a>(self: &'a mut Pin<Box<Self>>) -> &'a mut Self {
-        let pinned_nes_ref: Pin<&mut Self> = Pin::as_mut(self);
-        Pin::get_unchecked_mut(pinned_nes_ref)
+    fn bus(&self) -> &Bus {
+        self.cpu.memory()
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn get_from_pin<'a>(self: &'a Pin<Box<Self>>) -> &'a Self {
-        let pinned_nes_ref: Pin<&Self> = Pin::as_ref(self);
-        Pin::get_ref(pinned_nes_ref)
+    fn bus_mut(&mut self) -> &mut Bus {
+        self.cpu.memory_mut()
     }
 }
@@ -1,16 +1,33 @@
 use gloo_render::AnimationFrame;
 use gloo_timers::callback::Timeout;
-use nes_emu::{joypad::Button, screen::Color, Nes, Player};
+use js_sys::Uint8Array;
+use nes_emu::{
+    joypad::Button,
+    rewind::Rewind,
+    screen::{self, SCREEN_HEIGHT, SCREEN_WIDTH},
+    Nes, Player,
+};
 use reqwasm::http::Request;
-use std::{cell::RefCell, pin::Pin, rc::Rc};
-use wasm_bindgen::{closure::Closure, JsCast, JsValue};
-use web_sys::{window, CanvasRenderingContext2d, HtmlCanvasElement, HtmlSpanElement};
-use yew::{events::KeyboardEvent, html, html::Scope, Component, Context, Html, NodeRef};
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::{closure::Closure, Clamped, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    window, CanvasRenderingContext2d, Event, File, HtmlCanvasElement, HtmlInputElement,
+    HtmlSpanElement, ImageData,
+};
+use yew::{
+    events::KeyboardEvent, html, html::Scope, Component, Context, Html, NodeRef, TargetCast,
+};
+
+/// The NTSC NES draws a frame every 16.64 ms.
+const FRAME_DURATION_MS: u32 = 16;
 
 pub enum Msg {
     Render { timestamp: f64 },
     KeyDown { key: KeyboardEvent },
     KeyUp { key: KeyboardEvent },
+    LoadRom { file: File },
+    RomLoaded { is_demo: bool },
     Run,
 }
 
@@ -21,8 +38,13 @@ pub struct App {
     _animation_frame: Option<AnimationFrame>,
     on_key_down: Option<Closure<dyn Fn(KeyboardEvent)>>,
     on_key_up: Option<Closure<dyn Fn(KeyboardEvent)>>,
-    nes: Rc<RefCell<Pin<Box<Nes>>>>,
+    nes: Rc<RefCell<Nes>>,
+    rewind: Rewind,
     scale: u8,
+    is_running: bool,
+    is_rewinding: bool,
+    /// An assembled demo program runs instead of a cartridge.
+    is_demo: bool,
 }
 
 impl Component for App {
@@ -38,7 +60,11 @@ impl Component for App {
             on_key_down: None,
             on_key_up: None,
             nes: Rc::new(RefCell::new(Nes::new())),
-            scale: 15,
+            rewind: Rewind::default(),
+            scale: 3,
+            is_running: false,
+            is_rewinding: false,
+            is_demo: false,
         }
     }
 
@@ -51,29 +77,12 @@ impl Component for App {
             }
             Msg::KeyDown { key } => {
                 let key_pressed = self.key_pressed.cast::<HtmlSpanElement>().unwrap();
-                unsafe {
-                    match key.key().as_ref() {
-                        "z" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .press(Player::One, Button::UP),
-                        "q" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .press(Player::One, Button::LEFT),
-                        "s" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .press(Player::One, Button::DOWN),
-                        "d" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .press(Player::One, Button::RIGHT),
-                        _ => {}
+                match key.key().as_ref() {
+                    "Backspace" => self.is_rewinding = true,
+                    key => {
+                        if let Some(button) = button(key) {
+                            self.nes.as_ref().borrow_mut().press(Player::One, button)
+                        }
                     }
                 }
                 let str = format!("pressed [{}]", key.key());
@@ -82,44 +91,44 @@ impl Component for App {
             }
             Msg::KeyUp { key } => {
                 let key_pressed = self.key_pressed.cast::<HtmlSpanElement>().unwrap();
-                unsafe {
-                    match key.key().as_ref() {
-                        "z" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .release(Player::One, Button::UP),
-                        "q" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .release(Player::One, Button::LEFT),
-                        "s" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .release(Player::One, Button::DOWN),
-                        "d" => self
-                            .nes
-                            .as_ref()
-                            .borrow_mut()
-                            .release(Player::One, Button::RIGHT),
-                        _ => {}
+                match key.key().as_ref() {
+                    "Backspace" => self.is_rewinding = false,
+                    key => {
+                        if let Some(button) = button(key) {
+                            self.nes.as_ref().borrow_mut().release(Player::One, button)
+                        }
                     }
                 }
                 let str = format!("released [{}]", key.key());
                 key_pressed.set_text_content(Some(&str));
                 false
             }
+            Msg::LoadRom { file } => {
+                self.load_rom(file, ctx.link().clone());
+                false
+            }
+            Msg::RomLoaded { is_demo } => {
+                self.rewind.clear();
+                self.is_demo = is_demo;
+                if !self.is_running {
+                    self.is_running = true;
+                    ctx.link().send_message(Msg::Run);
+                }
+                true
+            }
             Msg::Run => {
-                //We need to find another solution for that code....
-                for _ in 0..8 {
-                    unsafe {
-                        self.nes.as_ref().borrow_mut().run();
+                let mut nes = self.nes.as_ref().borrow_mut();
+                if self.is_rewinding {
+                    if let Err(err) = self.rewind.step_back(&mut nes) {
+                        log::error!("unable to rewind: {}", err);
                     }
+                } else {
+                    nes.run_frame();
+                    self.rewind.record(&nes);
                 }
+                drop(nes);
                 let link = ctx.link().clone();
-                let timeout = Timeout::new(1, move || {
+                let timeout = Timeout::new(FRAME_DURATION_MS, move || {
                     link.send_message(Msg::Run);
                 });
                 timeout.forget();
@@ -129,12 +138,31 @@ impl Component for App {
         }
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let on_rom_selected = ctx.link().batch_callback(|event: Event| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            input
+                .files()
+                .and_then(|files| files.get(0))
+                .map(|file| Msg::LoadRom { file })
+        });
+        let (width, height) = if self.is_demo {
+            (SCREEN_HEIGHT, SCREEN_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        };
+        let canvas_style = format!(
+            "width: {}px; height: {}px; image-rendering: pixelated;",
+            width * usize::from(self.scale),
+            height * usize::from(self.scale)
+        );
         html! {
             <div>
                 <h1>{ "NES Emulator" }</h1>
+                <input type="file" accept=".nes" onchange={ on_rom_selected } />
                 <canvas
                     ref={ self.canvas_ref.clone() }
+                    style={ canvas_style }
                 />
                 <h1>{"key pressed : "}<span ref={self.key_pressed.clone()}>{"None"}</span></h1>
             </div>
@@ -154,8 +182,6 @@ impl Component for App {
         self.rendering_context = Some(rendering_context);
 
         if first_render {
-            canvas.set_width(32 * self.scale as u32);
-            canvas.set_height(32 * self.scale as u32);
             self.listen_keyboard_events(ctx.link().clone());
             self.request_animation_frame(ctx.link().clone());
             self.load_program(ctx.link().clone());
@@ -165,22 +191,29 @@ impl Component for App {
 
 impl App {
     fn render_frame(&mut self, _timestamp: f64) {
+        let canvas = self.canvas_ref.cast::<HtmlCanvasElement>().unwrap();
         let rendering_context = self.rendering_context.as_ref().unwrap();
-        unsafe {
-            let screen_data = self.nes.as_ref().borrow().get_screen_data();
-            for (c, line) in screen_data.iter().enumerate() {
-                for (l, pixel_color) in line.iter().enumerate() {
-                    let fill_color = JsValue::from_str(&String::from(Color::from(*pixel_color)));
-                    rendering_context.set_fill_style(&fill_color);
-                    rendering_context.fill_rect(
-                        l as f64 * self.scale as f64,
-                        c as f64 * self.scale as f64,
-                        self.scale as f64,
-                        self.scale as f64,
-                    );
-                }
-            }
+        let nes = self.nes.as_ref().borrow();
+        let (pixels, width, height) = if self.is_demo {
+            let ram = nes.peek_range(screen::DEMO_SCREEN);
+            let size = screen::DEMO_SCREEN_SIZE;
+            (screen::demo_to_rgba(&ram), size, size)
+        } else {
+            let screen_data = nes.get_screen_data();
+            (screen::to_rgba(&screen_data), SCREEN_WIDTH, SCREEN_HEIGHT)
+        };
+        // resizing clears the canvas, only do it when switching programs
+        if canvas.width() != width as u32 || canvas.height() != height as u32 {
+            canvas.set_width(width as u32);
+            canvas.set_height(height as u32);
         }
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&pixels[..]),
+            width as u32,
+            height as u32,
+        )
+        .unwrap();
+        rendering_context.put_image_data(&image, 0.0, 0.0).unwrap();
     }
 
     fn request_animation_frame(&mut self, link: Scope<Self>) {
@@ -215,6 +248,20 @@ impl App {
             .expect("Unable to set callback on window");
     }
 
+    fn load_rom(&mut self, file: File, link: Scope<Self>) {
+        let nes = self.nes.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let buffer = JsFuture::from(file.array_buffer()).await.unwrap();
+            let rom = Uint8Array::new(&buffer).to_vec();
+            let result = nes.as_ref().borrow_mut().load_rom(&rom);
+            match result {
+                Ok(()) => link.send_message(Msg::RomLoaded { is_demo: false }),
+                Err(err) => log::error!("unable to load {}: {}", file.name(), err),
+            }
+        })
+    }
+
+    /// Runs the snake demo until a ROM is picked.
     fn load_program(&mut self, link: Scope<Self>) {
         let nes = self.nes.clone();
         wasm_bindgen_futures::spawn_local(async move {
@@ -229,13 +276,27 @@ impl App {
                     .unwrap(),
                 0x8000,
             );
-            unsafe {
-                nes.as_ref().borrow_mut().load(&program[..], 0x8000);
-            }
-            link.send_message(Msg::Run);
+            nes.as_ref().borrow_mut().load(&program[..], 0x8000);
+            link.send_message(Msg::RomLoaded { is_demo: true });
         })
     }
 }
+
+/// Keyboard layout of the controller, ZQSD moves on AZERTY keyboards.
+fn button(key: &str) -> Option<Button> {
+    match key {
+        "z" => Some(Button::UP),
+        "q" => Some(Button::LEFT),
+        "s" => Some(Button::DOWN),
+        "d" => Some(Button::RIGHT),
+        "k" => Some(Button::A),
+        "j" => Some(Button::B),
+        "Enter" => Some(Button::START),
+        "Shift" => Some(Button::SELECT),
+        _ => None,
+    }
+}
+
 fn main() {
     wasm_logger::init(wasm_logger::Config::default());
     yew::start_app::<App>();
@@ -0,0 +1,630 @@
+mod register;
+mod render;
+
+pub use render::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::cartridge::{mapper::SharedMapper, Mirroring};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::traits::Device;
+use register::{Control, Mask, Status};
+use render::{Background, SpriteLine};
+
+const MEMORY_RANGE: std::ops::Range<usize> = 0x2000..0x2008;
+
+const PPUCTRL: u16 = 0x2000;
+const PPUMASK: u16 = 0x2001;
+const PPUSTATUS: u16 = 0x2002;
+const OAMADDR: u16 = 0x2003;
+const OAMDATA: u16 = 0x2004;
+const PPUSCROLL: u16 = 0x2005;
+const PPUADDR: u16 = 0x2006;
+const PPUDATA: u16 = 0x2007;
+
+const NAMETABLES_START: u16 = 0x2000;
+const NAMETABLE_SIZE: u16 = 0x0400;
+const PALETTES_START: u16 = 0x3F00;
+const VRAM_ADDR_MASK: u16 = 0x3FFF;
+const STATUS_OPEN_BUS_MASK: u8 = 0b0001_1111;
+
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+const VBLANK_SCANLINE: u16 = 241;
+const PRE_RENDER_SCANLINE: u16 = 261;
+
+/// Ricoh 2C02, the Picture Processing Unit.
+/// https://www.nesdev.org/wiki/PPU_registers
+pub struct Ppu {
+    mapper: Option<SharedMapper>,
+    control: Control,
+    mask: Mask,
+    status: Status,
+    oam_addr: u8,
+    oam: [u8; 256],
+    vram: [u8; 4 * NAMETABLE_SIZE as usize],
+    palettes: [u8; 32],
+    /// Current VRAM address, the loopy `v` register.
+    v: u16,
+    /// Temporary VRAM address, the loopy `t` register.
+    t: u16,
+    /// Fine X scroll.
+    x: u8,
+    /// First or second write toggle shared by PPUSCROLL and PPUADDR.
+    w: bool,
+    read_buffer: u8,
+    /// Last value written on the PPU registers, read back from the
+    /// write-only registers.
+    io_latch: u8,
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    background: Background,
+    sprite_line: SpriteLine,
+    frame_buffer: Vec<u8>,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            mapper: None,
+            control: Control::empty(),
+            mask: Mask::empty(),
+            status: Status::empty(),
+            oam_addr: 0,
+            oam: [0; 256],
+            vram: [0; 4 * NAMETABLE_SIZE as usize],
+            palettes: [0; 32],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            background: Background::default(),
+            sprite_line: SpriteLine::default(),
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn set_mapper(&mut self, mapper: Option<SharedMapper>) {
+        self.mapper = mapper;
+    }
+
+    /// Level of the NMI output, asserted during vblank when enabled in PPUCTRL.
+    pub fn nmi(&self) -> bool {
+        self.status.contains(Status::VBLANK) && self.control.contains(Control::GENERATE_NMI)
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Advances the PPU by `dots` PPU clock cycles.
+    pub fn tick(&mut self, dots: u32) {
+        for _ in 0..dots {
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        self.render_dot();
+        match (self.scanline, self.dot) {
+            (VBLANK_SCANLINE, 1) => self.status.insert(Status::VBLANK),
+            (PRE_RENDER_SCANLINE, 1) => self
+                .status
+                .remove(Status::VBLANK | Status::SPRITE_ZERO_HIT | Status::SPRITE_OVERFLOW),
+            _ => {}
+        }
+        self.dot += 1;
+        let is_odd_frame = self.frame % 2 == 1;
+        if self.scanline == PRE_RENDER_SCANLINE
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && is_odd_frame
+            && self.is_rendering_enabled()
+        {
+            // the last dot of the pre-render line is skipped on odd frames
+            self.dot += 1;
+        }
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mapper
+            .as_ref()
+            .map_or(Mirroring::Horizontal, |mapper| mapper.borrow().mirroring())
+    }
+
+    fn nametable_offset(&self, addr: u16) -> usize {
+        let addr = (addr - NAMETABLES_START) % (4 * NAMETABLE_SIZE);
+        let table = addr / NAMETABLE_SIZE;
+        let physical_table = match self.mirroring() {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        usize::from(physical_table * NAMETABLE_SIZE + addr % NAMETABLE_SIZE)
+    }
+
+    fn palette_offset(addr: u16) -> usize {
+        let offset = usize::from(addr % 32);
+        // $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries $3F00/$3F04/$3F08/$3F0C
+        if offset % 4 == 0 {
+            offset & 0x0F
+        } else {
+            offset
+        }
+    }
+
+    fn vram_read(&mut self, addr: u16) -> u8 {
+        let addr = addr & VRAM_ADDR_MASK;
+        if addr >= NAMETABLES_START {
+            if let Some(mapper) = &self.mapper {
+                mapper.borrow_mut().ppu_address(addr)
+            }
+        }
+        match addr {
+            0x0000..=0x1FFF => self
+                .mapper
+                .as_ref()
+                .map_or(0, |mapper| mapper.borrow_mut().ppu_read(addr)),
+            0x2000..=0x3EFF => self.vram[self.nametable_offset(addr)],
+            _ => self.palettes[Self::palette_offset(addr)],
+        }
+    }
+
+    fn vram_write(&mut self, addr: u16, data: u8) {
+        let addr = addr & VRAM_ADDR_MASK;
+        match addr {
+            0x0000..=0x1FFF => {
+                if let Some(mapper) = &self.mapper {
+                    mapper.borrow_mut().ppu_write(addr, data)
+                }
+            }
+            0x2000..=0x3EFF => self.vram[self.nametable_offset(addr)] = data,
+            _ => self.palettes[Self::palette_offset(addr)] = data,
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let increment = if self.control.contains(Control::VRAM_INCREMENT_32) {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(increment) & 0x7FFF;
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow_mut().ppu_address(self.v & VRAM_ADDR_MASK)
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        self.io_latch = data;
+        match addr {
+            PPUCTRL => {
+                self.control = Control::from_bits_truncate(data);
+                self.t = (self.t & !0x0C00) | (u16::from(data & 0b11) << 10);
+            }
+            PPUMASK => self.mask = Mask::from_bits_truncate(data),
+            OAMADDR => self.oam_addr = data,
+            OAMDATA => {
+                self.oam[usize::from(self.oam_addr)] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            PPUSCROLL => {
+                if self.w {
+                    self.t = (self.t & !0x73E0)
+                        | (u16::from(data & 0b111) << 12)
+                        | (u16::from(data >> 3) << 5);
+                } else {
+                    self.t = (self.t & !0x001F) | u16::from(data >> 3);
+                    self.x = data & 0b111;
+                }
+                self.w = !self.w;
+            }
+            PPUADDR => {
+                if self.w {
+                    self.t = (self.t & 0xFF00) | u16::from(data);
+                    self.v = self.t;
+                    if let Some(mapper) = &self.mapper {
+                        mapper.borrow_mut().ppu_address(self.v & VRAM_ADDR_MASK)
+                    }
+                } else {
+                    self.t = (self.t & 0x00FF) | (u16::from(data & 0x3F) << 8);
+                }
+                self.w = !self.w;
+            }
+            PPUDATA => {
+                self.vram_write(self.v, data);
+                self.increment_vram_addr();
+            }
+            _ => {}
+        }
+    }
+
+    /// `read_register` without its side effects.
+    fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            PPUSTATUS => self.status.bits() | (self.io_latch & STATUS_OPEN_BUS_MASK),
+            OAMDATA => self.oam[usize::from(self.oam_addr)],
+            PPUDATA => {
+                let addr = self.v & VRAM_ADDR_MASK;
+                if addr >= PALETTES_START {
+                    self.palettes[Self::palette_offset(addr)]
+                } else {
+                    self.read_buffer
+                }
+            }
+            _ => self.io_latch,
+        }
+    }
+
+    fn read_register(&mut self, addr: u16) -> u8 {
+        match addr {
+            PPUSTATUS => {
+                let data = self.peek_register(addr);
+                self.status.remove(Status::VBLANK);
+                self.w = false;
+                self.io_latch = data;
+                data
+            }
+            OAMDATA => {
+                self.io_latch = self.peek_register(addr);
+                self.io_latch
+            }
+            PPUDATA => {
+                let addr = self.v & VRAM_ADDR_MASK;
+                let data = self.vram_read(addr);
+                self.io_latch = if addr >= PALETTES_START {
+                    // palettes are not buffered, the buffer gets the nametable
+                    // byte "under" the palette instead
+                    self.read_buffer = self.vram[self.nametable_offset(addr - 0x1000)];
+                    data
+                } else {
+                    std::mem::replace(&mut self.read_buffer, data)
+                };
+                self.increment_vram_addr();
+                self.io_latch
+            }
+            _ => self.io_latch,
+        }
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Ppu {
+    fn mapping_def(&self) -> std::ops::Range<usize> {
+        MEMORY_RANGE
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.read_register(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.write_register(addr, data)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.peek_register(addr)
+    }
+}
+
+impl Snapshot for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_array(&[
+            self.control.bits(),
+            self.mask.bits(),
+            self.status.bits(),
+            self.oam_addr,
+        ]);
+        writer.write_array(&self.oam);
+        writer.write_array(&self.vram);
+        writer.write_array(&self.palettes);
+        writer.write_u16(self.v);
+        writer.write_u16(self.t);
+        writer.write_u8(self.x);
+        writer.write_bool(self.w);
+        writer.write_u8(self.read_buffer);
+        writer.write_u8(self.io_latch);
+        writer.write_u16(self.scanline);
+        writer.write_u16(self.dot);
+        writer.write_u64(self.frame);
+        self.background.save_state(writer);
+        self.sprite_line.save_state(writer);
+        writer.write_bytes(&self.frame_buffer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let [control, mask, status, oam_addr] = reader.read_array()?;
+        self.control = Control::from_bits_truncate(control);
+        self.mask = Mask::from_bits_truncate(mask);
+        self.status = Status::from_bits_truncate(status);
+        self.oam_addr = oam_addr;
+        self.oam = reader.read_array()?;
+        self.vram = reader.read_array()?;
+        self.palettes = reader.read_array()?;
+        self.v = reader.read_u16()?;
+        self.t = reader.read_u16()?;
+        self.x = reader.read_u8()?;
+        self.w = reader.read_bool()?;
+        self.read_buffer = reader.read_u8()?;
+        self.io_latch = reader.read_u8()?;
+        self.scanline = reader.read_u16()?;
+        self.dot = reader.read_u16()?;
+        if self.scanline >= SCANLINES_PER_FRAME || self.dot >= DOTS_PER_SCANLINE {
+            return Err(StateError::InvalidValue("PPU position"));
+        }
+        self.frame = reader.read_u64()?;
+        self.background.load_state(reader)?;
+        self.sprite_line.load_state(reader)?;
+        reader.read_bytes_into(&mut self.frame_buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mapper;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const SPRITE_FLIP_FLAGS: u8 = 0b1100_0000;
+
+    struct PpuMock {
+        ppu: Ppu,
+    }
+
+    impl PpuMock {
+        fn new() -> Self {
+            Self { ppu: Ppu::new() }
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.ppu.write(addr, data)
+        }
+
+        fn read(&mut self, addr: u16) -> u8 {
+            self.ppu.read(addr)
+        }
+
+        fn set_vram_addr(&mut self, addr: u16) {
+            let [lo, hi] = addr.to_le_bytes();
+            self.write(PPUADDR, hi);
+            self.write(PPUADDR, lo);
+        }
+    }
+
+    #[test]
+    fn test_ppudata_read_is_buffered() {
+        let mut mock = PpuMock::new();
+        mock.set_vram_addr(0x2400);
+        mock.write(PPUDATA, 42);
+        mock.write(PPUDATA, 43);
+        mock.set_vram_addr(0x2400);
+        mock.read(PPUDATA);
+        assert_eq!(42, mock.read(PPUDATA));
+        assert_eq!(43, mock.read(PPUDATA));
+    }
+
+    #[test]
+    fn test_ppudata_increment_32() {
+        let mut mock = PpuMock::new();
+        mock.write(PPUCTRL, Control::VRAM_INCREMENT_32.bits());
+        mock.set_vram_addr(0x2000);
+        mock.write(PPUDATA, 1);
+        mock.write(PPUDATA, 2);
+        assert_eq!(2, mock.ppu.vram[32]);
+    }
+
+    #[test]
+    fn test_palette_mirrors_and_unbuffered_read() {
+        let mut mock = PpuMock::new();
+        mock.set_vram_addr(0x3F10);
+        mock.write(PPUDATA, 0x2A);
+        mock.set_vram_addr(0x3F00);
+        assert_eq!(0x2A, mock.read(PPUDATA));
+        mock.set_vram_addr(0x3FE0);
+        assert_eq!(0x2A, mock.read(PPUDATA));
+    }
+
+    #[test]
+    fn test_nametable_mirroring_from_cartridge() {
+        let mut header = mapper::test_header(0, 0);
+        header.mirroring = Mirroring::Vertical;
+        let cartridge_mapper = new_shared_mapper(&header);
+        let mut mock = PpuMock::new();
+        mock.ppu.set_mapper(Some(Rc::clone(&cartridge_mapper)));
+        mock.set_vram_addr(0x2801);
+        mock.write(PPUDATA, 99);
+        mock.set_vram_addr(0x2001);
+        mock.read(PPUDATA);
+        assert_eq!(99, mock.read(PPUDATA));
+
+        mock.set_vram_addr(0x0010);
+        mock.write(PPUDATA, 7);
+        assert_eq!(7, cartridge_mapper.borrow_mut().ppu_read(0x0010));
+    }
+
+    #[test]
+    fn test_status_read_clears_vblank_and_latch() {
+        let mut mock = PpuMock::new();
+        mock.ppu
+            .tick(u32::from(DOTS_PER_SCANLINE) * u32::from(VBLANK_SCANLINE) + 2);
+        mock.write(PPUADDR, 0x21);
+        let status = mock.read(PPUSTATUS);
+        assert_eq!(Status::VBLANK.bits() | 0x01, status);
+        assert_eq!(0x01, mock.read(PPUSTATUS));
+
+        mock.write(PPUADDR, 0x23);
+        mock.write(PPUADDR, 0x45);
+        assert_eq!(0x2345, mock.ppu.v);
+    }
+
+    #[test]
+    fn test_peek_keeps_vblank_and_read_buffer() {
+        let mut mock = PpuMock::new();
+        mock.ppu
+            .tick(u32::from(DOTS_PER_SCANLINE) * u32::from(VBLANK_SCANLINE) + 2);
+        assert_eq!(Status::VBLANK.bits(), mock.ppu.peek(PPUSTATUS));
+        assert!(mock.ppu.status.contains(Status::VBLANK));
+
+        mock.set_vram_addr(0x2000);
+        mock.write(PPUDATA, 0x42);
+        mock.set_vram_addr(0x2000);
+        mock.read(PPUDATA);
+        assert_eq!(0x42, mock.ppu.peek(PPUDATA));
+        assert_eq!(0x2001, mock.ppu.v);
+    }
+
+    #[test]
+    fn test_nmi_at_vblank() {
+        let mut mock = PpuMock::new();
+        mock.write(PPUCTRL, Control::GENERATE_NMI.bits());
+        mock.ppu
+            .tick(u32::from(DOTS_PER_SCANLINE) * u32::from(VBLANK_SCANLINE) + 1);
+        assert!(!mock.ppu.nmi());
+        mock.ppu.tick(1);
+        assert!(mock.ppu.nmi());
+        mock.write(PPUCTRL, 0);
+        assert!(!mock.ppu.nmi());
+        mock.write(PPUCTRL, Control::GENERATE_NMI.bits());
+        assert!(mock.ppu.nmi());
+        mock.ppu.tick(u32::from(
+            DOTS_PER_SCANLINE * (PRE_RENDER_SCANLINE - VBLANK_SCANLINE),
+        ));
+        assert!(!mock.ppu.nmi());
+    }
+
+    fn new_shared_mapper(header: &crate::cartridge::Header) -> SharedMapper {
+        Rc::new(RefCell::new(
+            mapper::new(header, vec![0; 0x4000], vec![]).unwrap(),
+        ))
+    }
+
+    fn new_mock_with_chr_ram() -> PpuMock {
+        let mut mock = PpuMock::new();
+        mock.ppu
+            .set_mapper(Some(new_shared_mapper(&mapper::test_header(0, 0))));
+        // tile 1 is a plain square of color 1
+        mock.set_vram_addr(0x0010);
+        for _ in 0..8 {
+            mock.write(PPUDATA, 0xFF);
+        }
+        mock.set_vram_addr(0x2000);
+        mock.write(PPUDATA, 1);
+        mock.set_vram_addr(0x3F00);
+        mock.write(PPUDATA, 0x0F);
+        mock.write(PPUDATA, 0x16);
+        mock.set_vram_addr(0x3F11);
+        mock.write(PPUDATA, 0x2A);
+        mock.write(PPUCTRL, 0);
+        mock.write(PPUSCROLL, 0);
+        mock.write(PPUSCROLL, 0);
+        mock
+    }
+
+    fn frame_dots() -> u32 {
+        u32::from(DOTS_PER_SCANLINE) * u32::from(SCANLINES_PER_FRAME)
+    }
+
+    #[test]
+    fn test_render_background() {
+        let mut mock = new_mock_with_chr_ram();
+        mock.write(
+            PPUMASK,
+            (Mask::SHOW_BACKGROUND | Mask::SHOW_LEFT_BACKGROUND).bits(),
+        );
+        mock.ppu.tick(2 * frame_dots());
+        let frame = mock.ppu.frame_buffer();
+        assert_eq!([0x16; 8], frame[0..8]);
+        assert_eq!(0x0F, frame[8]);
+        assert_eq!(0x16, frame[7 * SCREEN_WIDTH + 7]);
+        assert_eq!(0x0F, frame[8 * SCREEN_WIDTH]);
+    }
+
+    #[test]
+    fn test_render_background_with_fine_scroll() {
+        let mut mock = new_mock_with_chr_ram();
+        mock.write(PPUSCROLL, 3);
+        mock.write(PPUSCROLL, 2);
+        mock.write(
+            PPUMASK,
+            (Mask::SHOW_BACKGROUND | Mask::SHOW_LEFT_BACKGROUND).bits(),
+        );
+        mock.ppu.tick(2 * frame_dots());
+        let frame = mock.ppu.frame_buffer();
+        assert_eq!([0x16; 5], frame[0..5]);
+        assert_eq!(0x0F, frame[5]);
+        assert_eq!(0x16, frame[5 * SCREEN_WIDTH]);
+        assert_eq!(0x0F, frame[6 * SCREEN_WIDTH]);
+    }
+
+    #[test]
+    fn test_render_sprite_and_sprite_zero_hit() {
+        let mut mock = new_mock_with_chr_ram();
+        mock.write(OAMADDR, 0);
+        for byte in [0, 1, SPRITE_FLIP_FLAGS, 4] {
+            mock.write(OAMDATA, byte);
+        }
+        mock.write(PPUMASK, Mask::all().bits() & !Mask::GREYSCALE.bits());
+        mock.ppu.tick(2 * frame_dots());
+        assert!(!mock.ppu.status.contains(Status::SPRITE_ZERO_HIT));
+        mock.ppu.tick(3 * u32::from(DOTS_PER_SCANLINE));
+        assert!(mock.ppu.status.contains(Status::SPRITE_ZERO_HIT));
+        let frame = mock.ppu.frame_buffer();
+        assert_eq!(0x16, frame[SCREEN_WIDTH + 3]);
+        assert_eq!([0x2A; 8], frame[SCREEN_WIDTH + 4..SCREEN_WIDTH + 12]);
+        assert_eq!(0x0F, frame[SCREEN_WIDTH + 12]);
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let mut mock = new_mock_with_chr_ram();
+        mock.write(OAMADDR, 0);
+        for sprite in 0..9 {
+            for byte in [10, 1, 0, sprite * 8] {
+                mock.write(OAMDATA, byte);
+            }
+        }
+        mock.write(PPUMASK, Mask::SHOW_SPRITES.bits());
+        mock.ppu
+            .tick(frame_dots() + 12 * u32::from(DOTS_PER_SCANLINE));
+        assert!(mock.ppu.status.contains(Status::SPRITE_OVERFLOW));
+        assert_eq!(0x0F, mock.ppu.frame_buffer()[11 * SCREEN_WIDTH + 64]);
+        assert_eq!(0x2A, mock.ppu.frame_buffer()[11 * SCREEN_WIDTH + 56]);
+    }
+
+    #[test]
+    fn test_scroll_registers() {
+        let mut mock = PpuMock::new();
+        mock.write(PPUCTRL, 0b10);
+        mock.write(PPUSCROLL, 0b0111_1101);
+        mock.write(PPUSCROLL, 0b0101_1110);
+        assert_eq!(0b101, mock.ppu.x);
+        assert_eq!(0b110_1001_0110_1111, mock.ppu.t);
+    }
+}
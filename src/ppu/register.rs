@@ -0,0 +1,35 @@
+use bitflags::bitflags;
+
+bitflags! {
+    pub struct Control: u8 {
+        const GENERATE_NMI =            0b1000_0000;
+        const MASTER_SLAVE =            0b0100_0000;
+        const SPRITE_SIZE_16 =          0b0010_0000;
+        const BACKGROUND_TABLE_1000 =   0b0001_0000;
+        const SPRITE_TABLE_1000 =       0b0000_1000;
+        const VRAM_INCREMENT_32 =       0b0000_0100;
+        const NAMETABLE_HI =            0b0000_0010;
+        const NAMETABLE_LO =            0b0000_0001;
+    }
+}
+
+bitflags! {
+    pub struct Mask: u8 {
+        const EMPHASIZE_BLUE =          0b1000_0000;
+        const EMPHASIZE_GREEN =         0b0100_0000;
+        const EMPHASIZE_RED =           0b0010_0000;
+        const SHOW_SPRITES =            0b0001_0000;
+        const SHOW_BACKGROUND =         0b0000_1000;
+        const SHOW_LEFT_SPRITES =       0b0000_0100;
+        const SHOW_LEFT_BACKGROUND =    0b0000_0010;
+        const GREYSCALE =               0b0000_0001;
+    }
+}
+
+bitflags! {
+    pub struct Status: u8 {
+        const VBLANK =                  0b1000_0000;
+        const SPRITE_ZERO_HIT =         0b0100_0000;
+        const SPRITE_OVERFLOW =         0b0010_0000;
+    }
+}
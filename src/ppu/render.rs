@@ -0,0 +1,416 @@
+use super::register::{Control, Mask, Status};
+use super::{Ppu, NAMETABLES_START, PALETTES_START, PRE_RENDER_SCANLINE};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+const MAX_SPRITES_PER_LINE: usize = 8;
+const SPRITE_PALETTES_START: u16 = 0x3F10;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03C0;
+const EMPTY_SPRITE_TILE: u8 = 0xFF;
+
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
+const SPRITE_PALETTE: u8 = 0b0000_0011;
+const SPRITE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTALLY: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICALLY: u8 = 0b1000_0000;
+
+/// Latches and shift registers of the background pipeline.
+/// https://www.nesdev.org/wiki/PPU_rendering
+#[derive(Default)]
+pub(super) struct Background {
+    tile_id: u8,
+    attribute: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    shift_pattern_lo: u16,
+    shift_pattern_hi: u16,
+    shift_attribute_lo: u16,
+    shift_attribute_hi: u16,
+}
+
+impl Background {
+    fn load_shifters(&mut self) {
+        self.shift_pattern_lo = (self.shift_pattern_lo & 0xFF00) | u16::from(self.pattern_lo);
+        self.shift_pattern_hi = (self.shift_pattern_hi & 0xFF00) | u16::from(self.pattern_hi);
+        let attribute_lo = if self.attribute & 0b01 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        let attribute_hi = if self.attribute & 0b10 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        self.shift_attribute_lo = (self.shift_attribute_lo & 0xFF00) | attribute_lo;
+        self.shift_attribute_hi = (self.shift_attribute_hi & 0xFF00) | attribute_hi;
+    }
+
+    fn shift(&mut self) {
+        self.shift_pattern_lo <<= 1;
+        self.shift_pattern_hi <<= 1;
+        self.shift_attribute_lo <<= 1;
+        self.shift_attribute_hi <<= 1;
+    }
+
+    /// (palette, pixel) at fine X scroll `fine_x`.
+    fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let bit = 15 - u16::from(fine_x);
+        let pixel =
+            ((self.shift_pattern_hi >> bit) & 1) << 1 | ((self.shift_pattern_lo >> bit) & 1);
+        let palette =
+            ((self.shift_attribute_hi >> bit) & 1) << 1 | ((self.shift_attribute_lo >> bit) & 1);
+        (palette as u8, pixel as u8)
+    }
+}
+
+/// A sprite selected for the line being drawn, its pattern is already flipped.
+#[derive(Clone, Copy, Default)]
+pub(super) struct Sprite {
+    x: u8,
+    attributes: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    is_sprite_zero: bool,
+}
+
+impl Sprite {
+    fn pixel(&self, x: usize) -> Option<u8> {
+        let offset = x
+            .checked_sub(usize::from(self.x))
+            .filter(|offset| *offset < 8)?;
+        let bit = 7 - offset;
+        let pixel = ((self.pattern_hi >> bit) & 1) << 1 | ((self.pattern_lo >> bit) & 1);
+        (pixel != 0).then_some(pixel)
+    }
+}
+
+#[derive(Default)]
+pub(super) struct SpriteLine {
+    sprites: [Sprite; MAX_SPRITES_PER_LINE],
+    count: usize,
+    /// OAM index of the sprites selected for the next line.
+    selected: [u8; MAX_SPRITES_PER_LINE],
+    selected_count: usize,
+}
+
+impl Snapshot for Background {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_array(&[
+            self.tile_id,
+            self.attribute,
+            self.pattern_lo,
+            self.pattern_hi,
+        ]);
+        for shifter in [
+            self.shift_pattern_lo,
+            self.shift_pattern_hi,
+            self.shift_attribute_lo,
+            self.shift_attribute_hi,
+        ] {
+            writer.write_u16(shifter);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        [
+            self.tile_id,
+            self.attribute,
+            self.pattern_lo,
+            self.pattern_hi,
+        ] = reader.read_array()?;
+        for shifter in [
+            &mut self.shift_pattern_lo,
+            &mut self.shift_pattern_hi,
+            &mut self.shift_attribute_lo,
+            &mut self.shift_attribute_hi,
+        ] {
+            *shifter = reader.read_u16()?;
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for SpriteLine {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for sprite in &self.sprites {
+            writer.write_array(&[
+                sprite.x,
+                sprite.attributes,
+                sprite.pattern_lo,
+                sprite.pattern_hi,
+            ]);
+            writer.write_bool(sprite.is_sprite_zero);
+        }
+        writer.write_u8(self.count as u8);
+        writer.write_array(&self.selected);
+        writer.write_u8(self.selected_count as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        for sprite in &mut self.sprites {
+            [
+                sprite.x,
+                sprite.attributes,
+                sprite.pattern_lo,
+                sprite.pattern_hi,
+            ] = reader.read_array()?;
+            sprite.is_sprite_zero = reader.read_bool()?;
+        }
+        self.count = usize::from(reader.read_u8()?).min(MAX_SPRITES_PER_LINE);
+        self.selected = reader.read_array()?;
+        self.selected_count = usize::from(reader.read_u8()?).min(MAX_SPRITES_PER_LINE);
+        Ok(())
+    }
+}
+
+impl Ppu {
+    pub(super) fn is_rendering_enabled(&self) -> bool {
+        self.mask
+            .intersects(Mask::SHOW_BACKGROUND | Mask::SHOW_SPRITES)
+    }
+
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+
+    /// Work done by the rendering pipeline for the current dot.
+    pub(super) fn render_dot(&mut self) {
+        let is_visible_line = usize::from(self.scanline) < SCREEN_HEIGHT;
+        let is_pre_render_line = self.scanline == PRE_RENDER_SCANLINE;
+        if !self.is_rendering_enabled() || !(is_visible_line || is_pre_render_line) {
+            return;
+        }
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.background.shift();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.background.load_shifters();
+                    self.background.tile_id = self.vram_read(NAMETABLES_START | (self.v & 0x0FFF));
+                }
+                2 => self.fetch_attribute(),
+                4 => self.background.pattern_lo = self.vram_read(self.background_pattern_addr()),
+                6 => {
+                    self.background.pattern_hi = self.vram_read(self.background_pattern_addr() + 8)
+                }
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.background.load_shifters();
+                self.v = (self.v & !(COARSE_X | NAMETABLE_X)) | (self.t & (COARSE_X | NAMETABLE_X));
+                self.evaluate_sprites(is_visible_line);
+            }
+            280..=304 if is_pre_render_line => {
+                self.v = (self.v & !(COARSE_Y | NAMETABLE_Y | FINE_Y))
+                    | (self.t & (COARSE_Y | NAMETABLE_Y | FINE_Y));
+            }
+            338 | 340 => {
+                self.vram_read(NAMETABLES_START | (self.v & 0x0FFF));
+            }
+            _ => {}
+        }
+        if (257..321).contains(&dot) && dot % 8 == 1 {
+            self.fetch_sprite(usize::from((dot - 257) / 8));
+        }
+        if is_visible_line && (1..=256).contains(&dot) {
+            self.draw_pixel(usize::from(dot - 1));
+        }
+    }
+
+    fn fetch_attribute(&mut self) {
+        let v = self.v;
+        let addr = NAMETABLES_START
+            | ATTRIBUTE_TABLE_OFFSET
+            | (v & (NAMETABLE_X | NAMETABLE_Y))
+            | ((v >> 4) & 0x38)
+            | ((v >> 2) & 0x07);
+        let mut attribute = self.vram_read(addr);
+        if v & 0x40 != 0 {
+            attribute >>= 4;
+        }
+        if v & 0x02 != 0 {
+            attribute >>= 2;
+        }
+        self.background.attribute = attribute & 0b11;
+    }
+
+    fn background_pattern_addr(&self) -> u16 {
+        let table = if self.control.contains(Control::BACKGROUND_TABLE_1000) {
+            0x1000
+        } else {
+            0x0000
+        };
+        table + u16::from(self.background.tile_id) * 16 + ((self.v & FINE_Y) >> 12)
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == COARSE_X {
+            self.v &= !COARSE_X;
+            self.v ^= NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !FINE_Y;
+        let coarse_y = (self.v & COARSE_Y) >> 5;
+        let coarse_y = match coarse_y {
+            29 => {
+                self.v ^= NAMETABLE_Y;
+                0
+            }
+            31 => 0,
+            _ => coarse_y + 1,
+        };
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.control.contains(Control::SPRITE_SIZE_16) {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Selects the sprites of the next line, the real PPU does it during dots 65-256.
+    fn evaluate_sprites(&mut self, is_visible_line: bool) {
+        self.sprite_line.selected_count = 0;
+        if !is_visible_line {
+            return;
+        }
+        let height = self.sprite_height();
+        for index in 0..64u8 {
+            let y = u16::from(self.oam[usize::from(index) * 4]);
+            if self.scanline < y || self.scanline - y >= height {
+                continue;
+            }
+            if self.sprite_line.selected_count == MAX_SPRITES_PER_LINE {
+                self.status.insert(Status::SPRITE_OVERFLOW);
+                break;
+            }
+            self.sprite_line.selected[self.sprite_line.selected_count] = index;
+            self.sprite_line.selected_count += 1;
+        }
+    }
+
+    /// Fetches the pattern of the sprite in `slot` for the next line,
+    /// empty slots fetch the tile $FF like the real PPU does.
+    fn fetch_sprite(&mut self, slot: usize) {
+        let height = self.sprite_height();
+        let (entry, row) = if slot < self.sprite_line.selected_count {
+            let index = usize::from(self.sprite_line.selected[slot]) * 4;
+            let entry = [
+                self.oam[index],
+                self.oam[index + 1],
+                self.oam[index + 2],
+                self.oam[index + 3],
+            ];
+            let row = self.scanline - u16::from(entry[0]);
+            (entry, row)
+        } else {
+            ([0xFF, EMPTY_SPRITE_TILE, 0xFF, 0xFF], 0)
+        };
+        let [_, tile, attributes, x] = entry;
+        let row = if attributes & SPRITE_FLIP_VERTICALLY != 0 {
+            height - 1 - row
+        } else {
+            row
+        };
+        let (table, tile) = if height == 16 {
+            (
+                0x1000 * u16::from(tile & 1),
+                (tile & 0xFE) + u8::from(row >= 8),
+            )
+        } else if self.control.contains(Control::SPRITE_TABLE_1000) {
+            (0x1000, tile)
+        } else {
+            (0x0000, tile)
+        };
+        let addr = table + u16::from(tile) * 16 + row % 8;
+
+        // the two garbage nametable fetches
+        self.vram_read(NAMETABLES_START | (self.v & 0x0FFF));
+        self.vram_read(NAMETABLES_START | (self.v & 0x0FFF));
+        let mut pattern_lo = self.vram_read(addr);
+        let mut pattern_hi = self.vram_read(addr + 8);
+        if attributes & SPRITE_FLIP_HORIZONTALLY != 0 {
+            pattern_lo = pattern_lo.reverse_bits();
+            pattern_hi = pattern_hi.reverse_bits();
+        }
+
+        if slot < self.sprite_line.selected_count {
+            self.sprite_line.sprites[slot] = Sprite {
+                x,
+                attributes,
+                pattern_lo,
+                pattern_hi,
+                is_sprite_zero: self.sprite_line.selected[slot] == 0,
+            };
+        }
+        if slot == MAX_SPRITES_PER_LINE - 1 {
+            self.sprite_line.count = self.sprite_line.selected_count;
+        }
+    }
+
+    fn draw_pixel(&mut self, x: usize) {
+        let is_left_column = x < 8;
+        let (background_palette, background_pixel) = if self.mask.contains(Mask::SHOW_BACKGROUND)
+            && (!is_left_column || self.mask.contains(Mask::SHOW_LEFT_BACKGROUND))
+        {
+            self.background.pixel(self.x)
+        } else {
+            (0, 0)
+        };
+        let sprite = if self.mask.contains(Mask::SHOW_SPRITES)
+            && (!is_left_column || self.mask.contains(Mask::SHOW_LEFT_SPRITES))
+        {
+            self.sprite_line.sprites[..self.sprite_line.count]
+                .iter()
+                .find_map(|sprite| sprite.pixel(x).map(|pixel| (sprite, pixel)))
+        } else {
+            None
+        };
+
+        let palette_addr = match sprite {
+            Some((sprite, sprite_pixel)) => {
+                if sprite.is_sprite_zero && background_pixel != 0 && x != SCREEN_WIDTH - 1 {
+                    self.status.insert(Status::SPRITE_ZERO_HIT);
+                }
+                if background_pixel != 0 && sprite.attributes & SPRITE_BEHIND_BACKGROUND != 0 {
+                    PALETTES_START + u16::from(background_palette << 2 | background_pixel)
+                } else {
+                    SPRITE_PALETTES_START
+                        + u16::from((sprite.attributes & SPRITE_PALETTE) << 2 | sprite_pixel)
+                }
+            }
+            None if background_pixel != 0 => {
+                PALETTES_START + u16::from(background_palette << 2 | background_pixel)
+            }
+            None => PALETTES_START,
+        };
+        let mut color = self.palettes[Self::palette_offset(palette_addr)];
+        color &= if self.mask.contains(Mask::GREYSCALE) {
+            0x30
+        } else {
+            0x3F
+        };
+        self.frame_buffer[usize::from(self.scanline) * SCREEN_WIDTH + x] = color;
+    }
+}
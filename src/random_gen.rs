@@ -1,10 +1,14 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
 use crate::traits::Device;
 use rand::Rng;
 
 pub struct RandomGenerator {
     address: u16,
     range: std::ops::Range<u8>,
-    memory: *mut u8,
+    /// Xorshift state, randomly seeded and kept in the save states so a
+    /// restored game draws the same numbers.
+    /// https://en.wikipedia.org/wiki/Xorshift
+    state: u32,
 }
 
 impl RandomGenerator {
@@ -12,12 +16,21 @@ impl RandomGenerator {
         Self {
             address,
             range,
-            memory: std::ptr::null_mut(),
+            state: rand::thread_rng().gen_range(1..=u32::MAX),
         }
     }
 
-    fn generate(&self) -> u8 {
-        rand::thread_rng().gen_range(self.range.clone())
+    fn next_state(&self) -> u32 {
+        let mut state = self.state;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    }
+
+    fn number(&self, state: u32) -> u8 {
+        let len = u32::from(self.range.end - self.range.start);
+        self.range.start + (state % len) as u8
     }
 }
 
@@ -26,21 +39,46 @@ impl Device for RandomGenerator {
         usize::from(self.address)..usize::from(self.address + 1)
     }
 
-    fn map(&mut self, memory: &mut [u8]) {
-        self.memory = &mut memory[0]
+    /// Draws a new number on every read.
+    fn read(&mut self, _addr: u16) -> u8 {
+        self.state = self.next_state();
+        self.number(self.state)
     }
 
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_read(&mut self) {}
+    fn write(&mut self, _addr: u16, _data: u8) {}
 
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_write(&mut self) {
-        *self.memory = self.generate();
+    /// The number the next read draws.
+    fn peek(&self, _addr: u16) -> u8 {
+        self.number(self.next_state())
+    }
+}
+
+impl Snapshot for RandomGenerator {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.state);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        match reader.read_u32()? {
+            0 => Err(StateError::InvalidValue("random generator state")),
+            state => {
+                self.state = state;
+                Ok(())
+            }
+        }
     }
 }
 
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_peek_draws_the_next_number() {
+        let mut generator = RandomGenerator::new(0x4018, 1..16);
+        for _ in 0..100 {
+            let number = generator.peek(0x4018);
+            assert!((1..16).contains(&number));
+            assert_eq!(number, generator.read(0x4018));
+        }
+    }
 }
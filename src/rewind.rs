@@ -0,0 +1,331 @@
+//! Rewind buffer of periodic save states. The snapshots are grouped, the
+//! first one of a group is kept whole, the keyframe, and the next ones as
+//! run-length encoded XOR deltas against it. Dropping the oldest group
+//! bounds the memory to `capacity` snapshots plus a group of at most a
+//! quarter of it.
+
+use std::collections::VecDeque;
+
+use crate::state::StateError;
+use crate::Nes;
+
+/// 10 seconds at 60 frames per second.
+pub const DEFAULT_CAPACITY: usize = 600;
+pub const DEFAULT_INTERVAL: u32 = 1;
+/// Most snapshots sharing a keyframe.
+const GROUP_LEN: usize = 60;
+/// Unchanged bytes ending a run of changed bytes in a delta.
+const MIN_UNCHANGED_RUN: usize = 4;
+
+struct Group {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Group {
+    fn len(&self) -> usize {
+        1 + self.deltas.len()
+    }
+
+    fn size(&self) -> usize {
+        self.keyframe.len() + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+pub struct Rewind {
+    capacity: usize,
+    interval: u32,
+    frames_since_snapshot: u32,
+    /// The latest snapshot was taken on the current frame, restoring it
+    /// would not go back.
+    is_latest_current: bool,
+    groups: VecDeque<Group>,
+    len: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, DEFAULT_INTERVAL)
+    }
+}
+
+impl Rewind {
+    /// Keeps at least the last `capacity` snapshots, taken every `interval`
+    /// frames.
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+            frames_since_snapshot: 0,
+            is_latest_current: false,
+            groups: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: u32) {
+        self.interval = interval.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.len = 0;
+        self.frames_since_snapshot = 0;
+        self.is_latest_current = false;
+    }
+
+    /// Bytes held by the keyframes and the deltas.
+    pub fn memory_size(&self) -> usize {
+        self.groups.iter().map(Group::size).sum()
+    }
+
+    /// To call after each frame, takes a snapshot every `interval` frames.
+    pub fn record(&mut self, nes: &Nes) {
+        self.frames_since_snapshot += 1;
+        self.is_latest_current = self.frames_since_snapshot >= self.interval;
+        if self.is_latest_current {
+            self.frames_since_snapshot = 0;
+            self.push(nes.save_state());
+        }
+    }
+
+    /// Restores the latest snapshot older than the current frame and drops
+    /// it, going `interval` frames back, an interval of 1 rewinds frame by
+    /// frame. Returns `false` once there is nothing left to rewind.
+    pub fn step_back(&mut self, nes: &mut Nes) -> Result<bool, StateError> {
+        self.frames_since_snapshot = 0;
+        if std::mem::take(&mut self.is_latest_current) {
+            self.pop();
+        }
+        match self.pop() {
+            Some(state) => nes.load_state(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        let group_len = (self.capacity / 4).clamp(1, GROUP_LEN);
+        match self.groups.back_mut() {
+            Some(group) if group.len() < group_len && group.keyframe.len() == state.len() => {
+                group.deltas.push(encode_delta(&state, &group.keyframe))
+            }
+            _ => self.groups.push_back(Group {
+                keyframe: state,
+                deltas: Vec::new(),
+            }),
+        }
+        self.len += 1;
+        self.evict();
+    }
+
+    /// Removes the latest snapshot.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let group = self.groups.back_mut()?;
+        let state = match group.deltas.pop() {
+            Some(delta) => decode_delta(&delta, &group.keyframe),
+            None => self.groups.pop_back()?.keyframe,
+        };
+        self.len -= 1;
+        Some(state)
+    }
+
+    /// Drops the oldest groups while the other ones hold enough snapshots.
+    fn evict(&mut self) {
+        while let Some(oldest) = self.groups.front() {
+            if self.len - oldest.len() < self.capacity {
+                break;
+            }
+            self.len -= oldest.len();
+            self.groups.pop_front();
+        }
+    }
+}
+
+/// XOR of `state` and `keyframe` as runs of unchanged bytes followed by
+/// runs of changed bytes: `unchanged length, changed length, XOR bytes`,
+/// the lengths in LEB128.
+fn encode_delta(state: &[u8], keyframe: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = state.iter().zip(keyframe).map(|(a, b)| a ^ b).collect();
+    let is_unchanged_run = |start: usize| {
+        xor[start..]
+            .iter()
+            .take(MIN_UNCHANGED_RUN)
+            .all(|&byte| byte == 0)
+    };
+    let mut delta = Vec::new();
+    let mut start = 0;
+    while start < xor.len() {
+        let unchanged = xor[start..].iter().take_while(|&&byte| byte == 0).count();
+        let changed_start = start + unchanged;
+        let mut end = changed_start;
+        while end < xor.len() && !is_unchanged_run(end) {
+            end += 1;
+        }
+        write_leb128(&mut delta, unchanged);
+        write_leb128(&mut delta, end - changed_start);
+        delta.extend_from_slice(&xor[changed_start..end]);
+        start = end;
+    }
+    delta
+}
+
+fn decode_delta(delta: &[u8], keyframe: &[u8]) -> Vec<u8> {
+    let mut state = keyframe.to_vec();
+    let mut offset = 0;
+    let mut index = 0;
+    while index < delta.len() {
+        offset += read_leb128(delta, &mut index);
+        let len = read_leb128(delta, &mut index);
+        for (byte, xor) in state[offset..offset + len]
+            .iter_mut()
+            .zip(&delta[index..index + len])
+        {
+            *byte ^= xor;
+        }
+        offset += len;
+        index += len;
+    }
+    state
+}
+
+fn write_leb128(data: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+fn read_leb128(data: &[u8], index: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*index];
+        *index += 1;
+        value |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_round_trip() {
+        let keyframe: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut state = keyframe.clone();
+        state[0] ^= 1;
+        state[2] = 0xAA;
+        state[300..310].fill(0);
+        state[999] = 0;
+        let delta = encode_delta(&state, &keyframe);
+        assert!(delta.len() < 30, "{} bytes delta", delta.len());
+        assert_eq!(state, decode_delta(&delta, &keyframe));
+        assert_eq!(
+            keyframe,
+            decode_delta(&encode_delta(&keyframe, &keyframe), &keyframe)
+        );
+    }
+
+    #[test]
+    fn test_leb128() {
+        let mut data = Vec::new();
+        for value in [0, 0x7F, 0x80, 0x3FFF, 0x12345] {
+            write_leb128(&mut data, value);
+        }
+        let mut index = 0;
+        for value in [0, 0x7F, 0x80, 0x3FFF, 0x12345] {
+            assert_eq!(value, read_leb128(&data, &mut index));
+        }
+        assert_eq!(data.len(), index);
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut rewind = Rewind::new(3, 1);
+        for i in 0..8u8 {
+            rewind.push(vec![i; 16]);
+        }
+        assert_eq!(3, rewind.len());
+        // a shorter state starts a new group
+        rewind.push(vec![8; 4]);
+        assert_eq!(vec![8; 4], rewind.pop().unwrap());
+        assert_eq!(vec![7; 16], rewind.pop().unwrap());
+        assert_eq!(vec![6; 16], rewind.pop().unwrap());
+        assert_eq!(None, rewind.pop());
+        assert!(rewind.is_empty());
+    }
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 7] = [
+        0xE6, 0x10,       // INC $10
+        0x4C, 0x00, 0x80, // JMP $8000
+        0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_step_back_frame_by_frame() {
+        let mut nes = Nes::new();
+        let mut rewind = Rewind::new(20, 1);
+        let mut states = Vec::new();
+        nes.load(&PROGRAM, 0x8000);
+        for _ in 0..30 {
+            nes.run_frame();
+            rewind.record(&nes);
+            states.push(nes.save_state());
+        }
+        assert_eq!(20, rewind.len());
+        // 4 keyframes and 16 deltas
+        assert!(rewind.memory_size() < 5 * states[0].len());
+        for expected in states.iter().rev().skip(1).take(19) {
+            assert!(rewind.step_back(&mut nes).unwrap());
+            assert_eq!(*expected, nes.save_state());
+        }
+        assert!(!rewind.step_back(&mut nes).unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_interval() {
+        let mut nes = Nes::new();
+        let mut rewind = Rewind::default();
+        rewind.set_interval(4);
+        nes.load(&PROGRAM, 0x8000);
+        for _ in 0..10 {
+            nes.run_frame();
+            rewind.record(&nes);
+        }
+        assert_eq!(2, rewind.len());
+        let frame = nes.bus().ppu.frame();
+        rewind.step_back(&mut nes).unwrap();
+        assert_eq!(frame - 2, nes.bus().ppu.frame());
+    }
+}
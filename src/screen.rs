@@ -1,101 +1,139 @@
-use js_sys::Intl::Collator;
+pub use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
 
-use crate::traits::Device;
+pub const BYTES_PER_PIXEL: usize = 4;
 
-const MEMORY_RANGE: std::ops::Range<usize> = 0x0200..0x0600;
-const NUMBER_OF_PIXELS: usize = 0x0600 - 0x0200;
-const NUMBER_OF_PIXELS_PER_LINE: usize = 32;
+/// RGB values of the 64 colors the 2C02 can output, indexed by the
+/// palette values found in the PPU frame buffer.
+/// https://www.nesdev.org/wiki/PPU_palettes
+pub const SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
+    (0x54, 0x54, 0x54),
+    (0x00, 0x1E, 0x74),
+    (0x08, 0x10, 0x90),
+    (0x30, 0x00, 0x88),
+    (0x44, 0x00, 0x64),
+    (0x5C, 0x00, 0x30),
+    (0x54, 0x04, 0x00),
+    (0x3C, 0x18, 0x00),
+    (0x20, 0x2A, 0x00),
+    (0x08, 0x3A, 0x00),
+    (0x00, 0x40, 0x00),
+    (0x00, 0x3C, 0x00),
+    (0x00, 0x32, 0x3C),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x98, 0x96, 0x98),
+    (0x08, 0x4C, 0xC4),
+    (0x30, 0x32, 0xEC),
+    (0x5C, 0x1E, 0xE4),
+    (0x88, 0x14, 0xB0),
+    (0xA0, 0x14, 0x64),
+    (0x98, 0x22, 0x20),
+    (0x78, 0x3C, 0x00),
+    (0x54, 0x5A, 0x00),
+    (0x28, 0x72, 0x00),
+    (0x08, 0x7C, 0x00),
+    (0x00, 0x76, 0x28),
+    (0x00, 0x66, 0x78),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xEC, 0xEE, 0xEC),
+    (0x4C, 0x9A, 0xEC),
+    (0x78, 0x7C, 0xEC),
+    (0xB0, 0x62, 0xEC),
+    (0xE4, 0x54, 0xEC),
+    (0xEC, 0x58, 0xB4),
+    (0xEC, 0x6A, 0x64),
+    (0xD4, 0x88, 0x20),
+    (0xA0, 0xAA, 0x00),
+    (0x74, 0xC4, 0x00),
+    (0x4C, 0xD0, 0x20),
+    (0x38, 0xCC, 0x6C),
+    (0x38, 0xB4, 0xCC),
+    (0x3C, 0x3C, 0x3C),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xEC, 0xEE, 0xEC),
+    (0xA8, 0xCC, 0xEC),
+    (0xBC, 0xBC, 0xEC),
+    (0xD4, 0xB2, 0xEC),
+    (0xEC, 0xAE, 0xEC),
+    (0xEC, 0xAE, 0xD4),
+    (0xEC, 0xB4, 0xB0),
+    (0xE4, 0xC4, 0x90),
+    (0xCC, 0xD2, 0x78),
+    (0xB4, 0xDE, 0x78),
+    (0xA8, 0xE2, 0x90),
+    (0x98, 0xE2, 0xB4),
+    (0xA0, 0xD6, 0xE4),
+    (0xA0, 0xA2, 0xA0),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+];
 
-pub enum Color {
-    Black,
-    White,
-    Grey,
-    Red,
-    Green,
-    Blue,
-    Magenta,
-    Yellow,
-    Cyan,
-}
-
-impl std::convert::From<u8> for Color {
-    fn from(byte: u8) -> Self {
-        match byte {
-            0 => Color::Black,
-            1 => Color::White,
-            2 | 9 => Color::Grey,
-            3 | 10 => Color::Red,
-            4 | 11 => Color::Green,
-            5 | 12 => Color::Blue,
-            6 | 13 => Color::Magenta,
-            7 | 14 => Color::Yellow,
-            _ => Color::Cyan,
-        }
-    }
-}
-
-impl std::convert::From<Color> for String {
-    fn from(color: Color) -> Self {
-        match color {
-            Color::Black => "#000000".to_owned(),
-            Color::White => "#FFFFFF".to_owned(),
-            Color::Grey => "#7F7F7F".to_owned(),
-            Color::Red => "#FF0000".to_owned(),
-            Color::Green => "#00FF00".to_owned(),
-            Color::Blue => "#0000FF".to_owned(),
-            Color::Magenta => "#FF00FF".to_owned(),
-            Color::Yellow => "#FFFF00".to_owned(),
-            Color::Cyan => "#00FFFF".to_owned(),
-        }
+/// Converts a frame buffer of palette indexes into RGBA pixels.
+pub fn to_rgba(frame_buffer: &[u8]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(frame_buffer.len() * BYTES_PER_PIXEL);
+    for &index in frame_buffer {
+        let (r, g, b) = SYSTEM_PALETTE[usize::from(index & 0x3F)];
+        pixels.extend_from_slice(&[r, g, b, 0xFF]);
     }
+    pixels
 }
 
-pub struct Screen {
-    memory: [*mut u8; NUMBER_OF_PIXELS],
-}
+/// RAM drawn by the assembled demo programs, one byte per cell of a grid
+/// of `DEMO_SCREEN_SIZE` x `DEMO_SCREEN_SIZE` cells.
+pub const DEMO_SCREEN: std::ops::Range<u16> = 0x0200..0x0600;
+pub const DEMO_SCREEN_SIZE: usize = 32;
 
-impl Screen {
-    pub fn default() -> Self {
-        Self {
-            memory: [std::ptr::null_mut(); NUMBER_OF_PIXELS],
-        }
-    }
+/// RGB values of the demo cells, values above 15 are drawn in cyan.
+const DEMO_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xFF, 0xFF, 0xFF),
+    (0x7F, 0x7F, 0x7F),
+    (0xFF, 0x00, 0x00),
+    (0x00, 0xFF, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0xFF, 0x00, 0xFF),
+    (0xFF, 0xFF, 0x00),
+    (0x00, 0xFF, 0xFF),
+    (0x7F, 0x7F, 0x7F),
+    (0xFF, 0x00, 0x00),
+    (0x00, 0xFF, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0xFF, 0x00, 0xFF),
+    (0xFF, 0xFF, 0x00),
+    (0x00, 0xFF, 0xFF),
+];
 
-    pub fn get_screen_data(&self) -> Vec<Vec<u8>> {
-        let mut data = Vec::new();
-        let mut line = Vec::new();
-        let mut col_count = 0;
-        for byte in &self.memory {
-            unsafe {
-                line.push(**byte);
-            }
-            col_count += 1;
-            if col_count == NUMBER_OF_PIXELS_PER_LINE {
-                col_count = 0;
-                data.push(line);
-                line = Vec::new();
-            }
-        }
-        data
+/// Converts the demo screen RAM into RGBA pixels, one pixel per cell.
+pub fn demo_to_rgba(ram: &[u8]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(ram.len() * BYTES_PER_PIXEL);
+    for &value in ram {
+        let (r, g, b) = DEMO_PALETTE[usize::from(value.min(0x0F))];
+        pixels.extend_from_slice(&[r, g, b, 0xFF]);
     }
+    pixels
 }
 
-impl Device for Screen {
-    fn mapping_def(&self) -> std::ops::Range<usize> {
-        MEMORY_RANGE
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn map(&mut self, memory: &mut [u8]) {
-        for (count, pointer) in self.memory.iter_mut().enumerate() {
-            *pointer = &mut memory[count];
-        }
+    #[test]
+    fn test_to_rgba() {
+        assert_eq!(
+            vec![0x54, 0x54, 0x54, 0xFF, 0xEC, 0xEE, 0xEC, 0xFF],
+            to_rgba(&[0x00, 0x70])
+        );
     }
 
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_read(&mut self) {}
-
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_write(&mut self) {}
+    #[test]
+    fn test_demo_to_rgba() {
+        assert_eq!(
+            vec![0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0xFF, 0xFF],
+            demo_to_rgba(&[0x00, 0x0A, 0xC8])
+        );
+    }
 }
@@ -0,0 +1,395 @@
+//! Save states: the `MAGIC` number and the format `VERSION`, followed by one
+//! section per component. A section is a 4 bytes tag, the length of its
+//! content and the content written by the component `Snapshot`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+pub const MAGIC: [u8; 4] = *b"NESS";
+/// Bumped when a section layout changes, the older versions are migrated
+/// in `State::parse` or rejected.
+pub const VERSION: u16 = 3;
+
+pub type Tag = [u8; 4];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    MissingSection(Tag),
+    SizeMismatch { expected: usize, actual: usize },
+    InvalidValue(&'static str),
+    CartridgeMismatch,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "missing save state magic number"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "save state version {version} is not supported")
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::MissingSection(tag) => write!(
+                f,
+                "save state has no {} section",
+                String::from_utf8_lossy(tag).trim_end()
+            ),
+            StateError::SizeMismatch { expected, actual } => write!(
+                f,
+                "save state holds {actual} bytes where {expected} are expected"
+            ),
+            StateError::InvalidValue(name) => write!(f, "save state has an invalid {name}"),
+            StateError::CartridgeMismatch => {
+                write!(f, "save state was made with another cartridge")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Component whose state goes in a save state.
+pub trait Snapshot {
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writer starting with the magic number and the version.
+    pub fn with_header() -> Self {
+        let mut writer = Self::new();
+        writer.write_array(&MAGIC);
+        writer.write_u16(VERSION);
+        writer
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Writes the section `tag` filled by `content`.
+    pub fn write_section(&mut self, tag: Tag, content: impl FnOnce(&mut StateWriter)) {
+        let mut section = Self::new();
+        content(&mut section);
+        self.write_array(&tag);
+        self.write_bytes(&section.data);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(u8::from(value));
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_array(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_array(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_array(&value.to_le_bytes());
+    }
+
+    /// Writes `data` as is, its length is known by the reader.
+    pub fn write_array(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    /// Writes `data` after its length.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        self.write_u32(data.len() as u32);
+        self.write_array(data);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue("boolean")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        self.read_array().map(u16::from_le_bytes)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        self.read_array().map(u64::from_le_bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Reads bytes written by `StateWriter::write_bytes`.
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads bytes written by `StateWriter::write_bytes` in `buffer`,
+    /// which must have the same length.
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), StateError> {
+        let data = self.read_bytes()?;
+        if data.len() != buffer.len() {
+            return Err(StateError::SizeMismatch {
+                expected: buffer.len(),
+                actual: data.len(),
+            });
+        }
+        buffer.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+/// Sections of a save state whose header was checked.
+pub struct State<'a> {
+    sections: HashMap<Tag, &'a [u8]>,
+}
+
+impl<'a> State<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, StateError> {
+        let mut reader = StateReader::new(data);
+        if reader.read_array::<4>().ok() != Some(MAGIC) {
+            return Err(StateError::InvalidMagic);
+        }
+        match reader.read_u16()? {
+            VERSION => {}
+            version => return Err(StateError::UnsupportedVersion(version)),
+        }
+        let mut sections = HashMap::new();
+        while !reader.data.is_empty() {
+            let tag = reader.read_array()?;
+            sections.insert(tag, reader.read_bytes()?);
+        }
+        Ok(Self { sections })
+    }
+
+    pub fn has_section(&self, tag: Tag) -> bool {
+        self.sections.contains_key(&tag)
+    }
+
+    pub fn section(&self, tag: Tag) -> Result<StateReader<'a>, StateError> {
+        self.sections
+            .get(&tag)
+            .map(|data| StateReader::new(data))
+            .ok_or(StateError::MissingSection(tag))
+    }
+
+    /// Loads `component` from the section `tag`, which it must read entirely.
+    pub fn load(&self, tag: Tag, component: &mut dyn Snapshot) -> Result<(), StateError> {
+        let mut reader = self.section(tag)?;
+        component.load_state(&mut reader)?;
+        match reader.data.len() {
+            0 => Ok(()),
+            _ => Err(StateError::InvalidValue("section length")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Nes;
+
+    struct Counter {
+        value: u16,
+        history: Vec<u8>,
+    }
+
+    impl Snapshot for Counter {
+        fn save_state(&self, writer: &mut StateWriter) {
+            writer.write_u16(self.value);
+            writer.write_bytes(&self.history);
+        }
+
+        fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+            self.value = reader.read_u16()?;
+            self.history = reader.read_bytes()?.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sections_round_trip() {
+        let counter = Counter {
+            value: 0x1234,
+            history: vec![1, 2, 3],
+        };
+        let mut writer = StateWriter::with_header();
+        writer.write_section(*b"CNT ", |writer| counter.save_state(writer));
+        let data = writer.into_inner();
+
+        let state = State::parse(&data).unwrap();
+        let mut loaded = Counter {
+            value: 0,
+            history: Vec::new(),
+        };
+        state.load(*b"CNT ", &mut loaded).unwrap();
+        assert_eq!(0x1234, loaded.value);
+        assert_eq!(vec![1, 2, 3], loaded.history);
+        assert_eq!(
+            Some(StateError::MissingSection(*b"PPU ")),
+            state.load(*b"PPU ", &mut loaded).err()
+        );
+    }
+
+    #[test]
+    fn test_invalid_states_are_rejected() {
+        assert_eq!(
+            Some(StateError::InvalidMagic),
+            State::parse(b"NES\x1A").err()
+        );
+        let mut future = MAGIC.to_vec();
+        future.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Some(StateError::UnsupportedVersion(VERSION + 1)),
+            State::parse(&future).err()
+        );
+        let mut writer = StateWriter::with_header();
+        writer.write_section(*b"CNT ", |writer| writer.write_u16(7));
+        let data = writer.into_inner();
+        assert_eq!(
+            Some(StateError::Truncated),
+            State::parse(&data[..data.len() - 1]).err()
+        );
+        let mut buffer = [0; 3];
+        assert_eq!(
+            Err(StateError::SizeMismatch {
+                expected: 3,
+                actual: 2
+            }),
+            StateReader::new(&[2, 0, 0, 0, 1, 2]).read_bytes_into(&mut buffer)
+        );
+    }
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 15] = [
+        0xE8,             // INX
+        0x8A,             // TXA
+        0x65, 0x10,       // ADC $10
+        0x85, 0x10,       // STA $10
+        0xAD, 0x18, 0x40, // LDA $4018, random number
+        0x85, 0x11,       // STA $11
+        0x4C, 0x00, 0x80, // JMP $8000
+        0x00,
+    ];
+
+    #[test]
+    fn test_nes_state_round_trip() {
+        let mut nes = Nes::new();
+        nes.load(&PROGRAM, 0x8000);
+        for _ in 0..1000 {
+            nes.run();
+        }
+        let state = nes.save_state();
+        for _ in 0..1000 {
+            nes.run();
+        }
+        let expected = nes.save_state();
+        let expected_memory = nes.peek_range(0x0010..0x0012);
+
+        nes.load_state(&state).unwrap();
+        assert_eq!(state, nes.save_state());
+        for _ in 0..1000 {
+            nes.run();
+        }
+        assert_eq!(expected_memory, nes.peek_range(0x0010..0x0012));
+        assert_eq!(expected, nes.save_state());
+    }
+
+    #[test]
+    fn test_failed_load_keeps_the_state() {
+        let mut nes = Nes::new();
+        nes.load(&PROGRAM, 0x8000);
+        nes.run();
+        let state = nes.save_state();
+        assert_eq!(
+            Err(StateError::Truncated),
+            nes.load_state(&state[..state.len() - 1])
+        );
+        // the APU section fails once the sections before it were loaded
+        let mut other = Nes::new();
+        other.load(&[0xEA], 0x9000);
+        let data = other.save_state();
+        let mut writer = StateWriter::with_header();
+        for (&tag, &content) in &State::parse(&data).unwrap().sections {
+            if tag != *b"APU " {
+                writer.write_section(tag, |writer| writer.write_array(content));
+            } else {
+                writer.write_section(tag, |_| {});
+            }
+        }
+        let broken = writer.into_inner();
+        assert!(nes.load_state(&broken).is_err());
+        assert_eq!(state, nes.save_state());
+    }
+
+    fn nrom(prg_banks: u8) -> Vec<u8> {
+        let mut rom = b"NES\x1A".to_vec();
+        rom.extend_from_slice(&[prg_banks, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        rom.resize(rom.len() + usize::from(prg_banks) * 0x4000, 0xEA);
+        rom
+    }
+
+    #[test]
+    fn test_state_of_another_cartridge_is_rejected() {
+        let mut nes = Nes::new();
+        let mut other = Nes::new();
+        nes.load_rom(&nrom(1)).unwrap();
+        nes.run();
+        let state = nes.save_state();
+        assert_eq!(Err(StateError::CartridgeMismatch), other.load_state(&state));
+        other.load_rom(&nrom(2)).unwrap();
+        assert_eq!(Err(StateError::CartridgeMismatch), other.load_state(&state));
+        other.load_rom(&nrom(1)).unwrap();
+        assert_eq!(Ok(()), other.load_state(&state));
+        assert_eq!(state, other.save_state());
+    }
+}
@@ -1,35 +1,79 @@
+use crate::bus_log::AccessKind;
+
+/// Chip answering the CPU bus accesses within `mapping_def`. The bus hands
+/// it the address and the data, it never shares its memory.
 pub trait Device {
     fn mapping_def(&self) -> std::ops::Range<usize>;
 
-    fn map(&mut self, memory: &mut [u8]);
+    /// Byte the device drives on the data bus for a read of `addr`.
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, data: u8);
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read(&mut self);
+    /// Value a read of `addr` would return, without its side effects.
+    fn peek(&self, addr: u16) -> u8;
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write(&mut self);
+    /// Bits the device leaves floating on a read of `addr`, they keep the
+    /// last value of the data bus.
+    /// https://www.nesdev.org/wiki/Open_bus_behavior
+    fn open_bus_mask(&self, _addr: u16) -> u8 {
+        0
+    }
 }
 
 pub trait Memory {
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn load(&mut self, data: &[u8], address: u16);
+    fn load(&mut self, data: &[u8], address: u16);
+
+    fn mem_read_u8(&mut self, address: u16) -> u8;
+
+    fn mem_write_u8(&mut self, address: u16, byte: u8);
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read_u8(&mut self, address: u16) -> u8;
+    /// Reads `address` without triggering any device behavior, for the
+    /// tracers, debuggers and memory viewers.
+    fn peek_u8(&self, address: u16) -> u8;
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write_u8(&mut self, address: u16, byte: u8);
+    /// Kind of the next accesses and the CPU cycle they happen on, for the
+    /// bus log.
+    fn set_access_context(&mut self, _kind: AccessKind, _cycle: u64) {}
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read_u16(&mut self, address: u16) -> u16 {
-        let bytes = [self.mem_read_u8(address), self.mem_read_u8(address + 1)];
+    fn peek_range(&self, range: std::ops::Range<u16>) -> Vec<u8> {
+        range.map(|address| self.peek_u8(address)).collect()
+    }
+
+    fn mem_read_u16(&mut self, address: u16) -> u16 {
+        let bytes = [
+            self.mem_read_u8(address),
+            self.mem_read_u8(address.wrapping_add(1)),
+        ];
         u16::from_le_bytes(bytes)
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write_u16(&mut self, address: u16, word: u16) {
+    fn mem_write_u16(&mut self, address: u16, word: u16) {
         let [lo, hi] = word.to_le_bytes();
         self.mem_write_u8(address, lo);
-        self.mem_write_u8(address + 1, hi)
+        self.mem_write_u8(address.wrapping_add(1), hi)
+    }
+}
+
+/// Lets a `Cpu` borrow its memory instead of owning it.
+impl<M: Memory + ?Sized> Memory for &mut M {
+    fn load(&mut self, data: &[u8], address: u16) {
+        (**self).load(data, address)
+    }
+
+    fn mem_read_u8(&mut self, address: u16) -> u8 {
+        (**self).mem_read_u8(address)
+    }
+
+    fn mem_write_u8(&mut self, address: u16, byte: u8) {
+        (**self).mem_write_u8(address, byte)
+    }
+
+    fn peek_u8(&self, address: u16) -> u8 {
+        (**self).peek_u8(address)
+    }
+
+    fn set_access_context(&mut self, kind: AccessKind, cycle: u64) {
+        (**self).set_access_context(kind, cycle)
     }
 }
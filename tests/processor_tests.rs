@@ -0,0 +1,247 @@
+//! Runs the `Cpu` against the ProcessorTests JSON files, one file per opcode.
+//! https://github.com/SingleStepTests/ProcessorTests/tree/main/nes6502
+//! The files are looked up in `tests/processor_tests` or in the directory
+//! given by `NES_EMU_PROCESSOR_TESTS`, a missing file of an opcode the CPU
+//! implements fails, see `tests/processor_tests/README.md`.
+
+use nes_emu::cpu::{disasm, Cpu, Name, Registers};
+use nes_emu::traits::Memory;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+const TESTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/processor_tests");
+const TESTS_DIR_VAR: &str = "NES_EMU_PROCESSOR_TESTS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+impl State {
+    fn registers(&self) -> Registers {
+        Registers {
+            program_counter: self.pc,
+            stack_pointer: self.s,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.p,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, Access)>,
+}
+
+/// Flat 64K RAM recording every bus access in order.
+struct RecordingMemory {
+    memory: Box<[u8; 0x10000]>,
+    accesses: Vec<(u16, u8, Access)>,
+}
+
+impl Memory for RecordingMemory {
+    fn load(&mut self, data: &[u8], address: u16) {
+        let start = usize::from(address);
+        self.memory[start..start + data.len()].copy_from_slice(data);
+    }
+
+    fn mem_read_u8(&mut self, address: u16) -> u8 {
+        let byte = self.memory[usize::from(address)];
+        self.accesses.push((address, byte, Access::Read));
+        byte
+    }
+
+    fn mem_write_u8(&mut self, address: u16, byte: u8) {
+        self.accesses.push((address, byte, Access::Write));
+        self.memory[usize::from(address)] = byte
+    }
+
+    fn peek_u8(&self, address: u16) -> u8 {
+        self.memory[usize::from(address)]
+    }
+}
+
+/// Mismatches found for one opcode, with the first failing case of each kind.
+#[derive(Default)]
+struct Report {
+    cases: usize,
+    registers: Vec<String>,
+    memory: Vec<String>,
+    cycles: Vec<String>,
+    bus: Vec<String>,
+}
+
+impl Report {
+    fn is_passing(&self) -> bool {
+        self.registers.is_empty()
+            && self.memory.is_empty()
+            && self.cycles.is_empty()
+            && self.bus.is_empty()
+    }
+
+    fn check(&mut self, case: &Case) {
+        self.cases += 1;
+        let mut memory = RecordingMemory {
+            memory: Box::new([0; 0x10000]),
+            accesses: Vec::new(),
+        };
+        for &(addr, byte) in &case.initial.ram {
+            memory.memory[usize::from(addr)] = byte;
+        }
+        let mut cpu = Cpu::new(&mut memory);
+        cpu.set_registers(&case.initial.registers());
+        let cycles = cpu.run();
+
+        let registers = cpu.registers();
+        if registers != case.expected.registers() {
+            self.registers.push(format!(
+                "{}: expected {:X?}, got {:X?}",
+                case.name,
+                case.expected.registers(),
+                registers
+            ));
+        }
+        let wrong_bytes: Vec<String> = case
+            .expected
+            .ram
+            .iter()
+            .filter(|&&(addr, byte)| memory.memory[usize::from(addr)] != byte)
+            .map(|&(addr, byte)| {
+                format!(
+                    "${addr:04X} expected {byte:02X}, got {:02X}",
+                    memory.memory[usize::from(addr)]
+                )
+            })
+            .collect();
+        if !wrong_bytes.is_empty() {
+            self.memory
+                .push(format!("{}: {}", case.name, wrong_bytes.join(", ")));
+        }
+        if cycles as usize != case.cycles.len() {
+            self.cycles.push(format!(
+                "{}: expected {} cycles, got {cycles}",
+                case.name,
+                case.cycles.len()
+            ));
+        }
+        if memory.accesses != case.cycles {
+            self.bus.push(format!(
+                "{}: expected {:X?}, got {:X?}",
+                case.name, case.cycles, memory.accesses
+            ));
+        }
+    }
+
+    fn summary(&self, opcode: u8) -> String {
+        let mut summary = format!(
+            "{opcode:02x}: {} cases, {} registers, {} memory, {} cycles, {} bus mismatches",
+            self.cases,
+            self.registers.len(),
+            self.memory.len(),
+            self.cycles.len(),
+            self.bus.len()
+        );
+        for first in [&self.registers, &self.memory, &self.cycles, &self.bus]
+            .into_iter()
+            .filter_map(|mismatches| mismatches.first())
+        {
+            summary.push_str("\n    ");
+            summary.push_str(first);
+        }
+        summary
+    }
+}
+
+fn run_cases(json: &str) -> Report {
+    let cases: Vec<Case> = serde_json::from_str(json).expect("invalid ProcessorTests JSON");
+    let mut report = Report::default();
+    for case in &cases {
+        report.check(case);
+    }
+    report
+}
+
+/// The JAM opcodes halt the CPU, the upstream cases do not model it.
+fn is_jam(opcode: u8) -> bool {
+    disasm::decode(|addr| if addr == 0 { opcode } else { 0 }, 0).name == Name::Jam
+}
+
+#[test]
+fn test_processor_tests() {
+    let dir = std::env::var(TESTS_DIR_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(TESTS_DIR));
+    assert!(
+        dir.is_dir(),
+        "{} not found, run tests/processor_tests/fetch.sh",
+        dir.display()
+    );
+    let mut failures = Vec::new();
+    for opcode in (0..=u8::MAX).filter(|&opcode| !is_jam(opcode)) {
+        let path = dir.join(format!("{opcode:02x}.json"));
+        let Ok(json) = std::fs::read_to_string(&path) else {
+            failures.push(format!("{opcode:02x}: {} not found", path.display()));
+            continue;
+        };
+        let report = run_cases(&json);
+        println!("{}", report.summary(opcode));
+        if !report.is_passing() {
+            failures.push(report.summary(opcode));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+const LDA_IMMEDIATE_CASE: &str = r#"[{
+    "name": "a9 42 00",
+    "initial": {"pc": 1000, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                "ram": [[1000, 169], [1001, 66]]},
+    "final": {"pc": 1002, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+              "ram": [[1000, 169], [1001, 66]]},
+    "cycles": [[1000, 169, "read"], [1001, 66, "read"]]
+}]"#;
+
+#[test]
+fn test_harness_on_a_passing_case() {
+    let report = run_cases(LDA_IMMEDIATE_CASE);
+    assert_eq!(1, report.cases);
+    assert!(report.is_passing(), "{}", report.summary(0xA9));
+}
+
+#[test]
+fn test_harness_reports_each_mismatch_kind() {
+    let json = LDA_IMMEDIATE_CASE
+        .replace(r#""a": 66"#, r#""a": 67"#)
+        .replace(
+            "[[1000, 169], [1001, 66]]},\n    \"cycles\"",
+            "[[1001, 67]]},\n    \"cycles\"",
+        )
+        .replace(
+            r#"[1001, 66, "read"]]"#,
+            r#"[1001, 66, "read"], [1002, 0, "read"]]"#,
+        );
+    let report = run_cases(&json);
+    assert_eq!(1, report.registers.len());
+    assert_eq!(1, report.memory.len());
+    assert_eq!(1, report.cycles.len());
+    assert_eq!(1, report.bus.len());
+}
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Downloads the nes6502/v1 ProcessorTests in the directory given as argument,
+# next to this script by default, keeping the first CASES cases of each opcode.
+set -e
+DIR=${1:-$(dirname "$0")}
+mkdir -p "$DIR"
+cd "$DIR"
+
+PROCESSOR_TESTS=https://raw.githubusercontent.com/SingleStepTests/ProcessorTests/main/nes6502/v1
+CASES=${CASES:-20}
+# The JAM opcodes halt the CPU, their cases are not run.
+JAM="02 12 22 32 42 52 62 72 92 b2 d2 f2"
+
+for opcode in $(seq 0 255); do
+    file=$(printf "%02x.json" "$opcode")
+    case " $JAM " in *" ${file%.json} "*) continue ;; esac
+    curl -sSfL "$PROCESSOR_TESTS/$file" | python3 -c "
+import json, sys
+json.dump(json.load(sys.stdin)[:$CASES], sys.stdout, separators=(',', ':'))
+print()" > "$file"
+done
@@ -0,0 +1,38 @@
+#!/bin/sh
+# Downloads the test ROMs used by tests/test_roms.rs next to this script and
+# assembles the 6502 functional test without its decimal mode tests, which
+# needs as65 (https://www.kingswood-consulting.co.uk/assemblers/) in the PATH.
+set -e
+cd "$(dirname "$0")"
+
+NES_TEST_ROMS=https://raw.githubusercontent.com/christopherpow/nes-test-roms/master
+FUNCTIONAL_TESTS=https://raw.githubusercontent.com/Klaus2m5/6502_65C02_functional_tests/master
+
+fetch() {
+    mkdir -p "$(dirname "$2")"
+    curl -sSfL "$1" -o "$2"
+}
+
+fetch "$NES_TEST_ROMS/other/nestest.nes" nestest.nes
+fetch "$NES_TEST_ROMS/other/nestest.log" nestest.log
+
+for rom in 01-basics 02-implied 03-immediate 04-zero_page 05-zp_xy 06-absolute \
+    07-abs_xy 08-ind_x 09-ind_y 10-branches 11-stack 12-jmp_jsr 13-rts 14-rti \
+    15-brk 16-special; do
+    fetch "$NES_TEST_ROMS/instr_test-v5/rom_singles/$rom.nes" "instr_test-v5/rom_singles/$rom.nes"
+done
+
+for rom in 1-instr_timing 2-branch_timing; do
+    fetch "$NES_TEST_ROMS/instr_timing/rom_singles/$rom.nes" "instr_timing/rom_singles/$rom.nes"
+done
+
+# The NES CPU has no decimal mode.
+fetch "$FUNCTIONAL_TESTS/6502_functional_test.a65" 6502_functional_test.a65
+sed -i 's/^disable_decimal *= *0/disable_decimal = 1/' 6502_functional_test.a65
+grep -q '^disable_decimal = 1' 6502_functional_test.a65
+as65 -l -m -w -h0 6502_functional_test.a65
+# Address of the `jmp *` of the success macro, read by the test.
+awk '/test passed, no errors/ { print $1; exit }' 6502_functional_test.lst \
+    > 6502_functional_test.success
+test -s 6502_functional_test.success
+rm 6502_functional_test.a65 6502_functional_test.lst
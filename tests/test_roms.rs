@@ -0,0 +1,346 @@
+//! Runs the standard CPU test ROMs, see `tests/roms/README.md` to get them.
+//! A missing ROM fails, the harness itself is checked with small ROMs
+//! generated by the tests.
+
+use nes_emu::cpu::{Cpu, Tracer};
+use nes_emu::traits::Memory;
+use nes_emu::Nes;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+const ROMS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/roms");
+
+/// https://www.qmtpro.com/~nes/misc/nestest.txt
+const NESTEST_START: u16 = 0xC000;
+const NESTEST_OFFICIAL_RESULT: u16 = 0x02;
+const NESTEST_UNOFFICIAL_RESULT: u16 = 0x03;
+/// Bounds the run when the CPU jams or loops without tracing.
+const NESTEST_MAX_RUNS_PER_LINE: usize = 4;
+
+/// https://github.com/Klaus2m5/6502_65C02_functional_tests
+const FUNCTIONAL_TEST_START: u16 = 0x0400;
+/// Address of the success trap, written by `tests/roms/fetch.sh`.
+const FUNCTIONAL_TEST_SUCCESS: &str = "6502_functional_test.success";
+const FUNCTIONAL_TEST_MAX_CYCLES: u64 = 200_000_000;
+
+/// https://github.com/christopherpow/nes-test-roms/blob/master/instr_test-v5/readme.txt
+const BLARGG_STATUS: u16 = 0x6000;
+const BLARGG_SIGNATURE: u16 = 0x6001;
+const BLARGG_SIGNATURE_BYTES: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_TEXT: u16 = 0x6004;
+const BLARGG_TEXT_END: u16 = 0x8000;
+const BLARGG_RUNNING: u8 = 0x80;
+const BLARGG_RESET_REQUESTED: u8 = 0x81;
+/// The reset has to be pressed at least 100ms after the request.
+const BLARGG_RESET_DELAY_FRAMES: u32 = 7;
+const BLARGG_MAX_FRAMES: u32 = 60 * 60;
+
+const INSTR_TEST_ROMS: [&str; 16] = [
+    "instr_test-v5/rom_singles/01-basics.nes",
+    "instr_test-v5/rom_singles/02-implied.nes",
+    "instr_test-v5/rom_singles/03-immediate.nes",
+    "instr_test-v5/rom_singles/04-zero_page.nes",
+    "instr_test-v5/rom_singles/05-zp_xy.nes",
+    "instr_test-v5/rom_singles/06-absolute.nes",
+    "instr_test-v5/rom_singles/07-abs_xy.nes",
+    "instr_test-v5/rom_singles/08-ind_x.nes",
+    "instr_test-v5/rom_singles/09-ind_y.nes",
+    "instr_test-v5/rom_singles/10-branches.nes",
+    "instr_test-v5/rom_singles/11-stack.nes",
+    "instr_test-v5/rom_singles/12-jmp_jsr.nes",
+    "instr_test-v5/rom_singles/13-rts.nes",
+    "instr_test-v5/rom_singles/14-rti.nes",
+    "instr_test-v5/rom_singles/15-brk.nes",
+    "instr_test-v5/rom_singles/16-special.nes",
+];
+
+const CPU_TIMING_ROMS: [&str; 2] = [
+    "instr_timing/rom_singles/1-instr_timing.nes",
+    "instr_timing/rom_singles/2-branch_timing.nes",
+];
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn line_count(&self) -> usize {
+        self.0
+            .borrow()
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.borrow())
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+struct FlatMemory {
+    memory: Box<[u8; 0x10000]>,
+}
+
+impl Memory for FlatMemory {
+    fn load(&mut self, data: &[u8], address: u16) {
+        let start = usize::from(address);
+        self.memory[start..start + data.len()].copy_from_slice(data);
+    }
+
+    fn mem_read_u8(&mut self, address: u16) -> u8 {
+        self.memory[usize::from(address)]
+    }
+
+    fn mem_write_u8(&mut self, address: u16, byte: u8) {
+        self.memory[usize::from(address)] = byte
+    }
+
+    fn peek_u8(&self, address: u16) -> u8 {
+        self.memory[usize::from(address)]
+    }
+}
+
+fn read_rom(path: &str) -> Vec<u8> {
+    std::fs::read(Path::new(ROMS_DIR).join(path))
+        .unwrap_or_else(|error| panic!("{path}: {error}, run tests/roms/fetch.sh"))
+}
+
+/// Runs each ROM, prints one result line per ROM and fails with the report
+/// when any of them failed.
+fn run_suite(roms: &[&str], run: impl Fn(&[u8]) -> Result<String, String>) {
+    let mut failures = Vec::new();
+    for path in roms {
+        match run(&read_rom(path)) {
+            Ok(text) => println!("{path}: passed {}", text.trim()),
+            Err(error) => {
+                println!("{path}: FAILED {error}");
+                failures.push(format!("{path}: {error}"));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// The PPU column is left out, nestest.log counts the power-up dots.
+fn without_ppu_position(line: &str) -> String {
+    match (line.find("PPU:"), line.find("CYC:")) {
+        (Some(ppu), Some(cyc)) => format!("{}{}", &line[..ppu], line[cyc..].trim_end()),
+        _ => line.trim_end().to_string(),
+    }
+}
+
+/// Runs a ROM in the nestest automation mode and compares its trace to the log.
+fn run_nestest(rom: &[u8], log: &str) -> Result<String, String> {
+    let expected: Vec<&str> = log.lines().collect();
+    let buffer = SharedBuffer::default();
+    let mut nes = Nes::new();
+    nes.load_rom(rom).map_err(|error| error.to_string())?;
+    nes.set_program_counter(NESTEST_START);
+    nes.set_tracer(Some(Tracer::new(buffer.clone())));
+    for _ in 0..expected.len() * NESTEST_MAX_RUNS_PER_LINE {
+        if buffer.line_count() >= expected.len() {
+            break;
+        }
+        nes.run();
+    }
+    let actual = buffer.lines();
+    for (number, (expected, actual)) in expected.iter().zip(&actual).enumerate() {
+        let (expected, actual) = (without_ppu_position(expected), without_ppu_position(actual));
+        if expected != actual {
+            return Err(format!(
+                "line {}\nexpected: {expected}\n  actual: {actual}",
+                number + 1
+            ));
+        }
+    }
+    if actual.len() < expected.len() {
+        return Err(format!("stopped tracing after {} lines", actual.len()));
+    }
+    let results = [
+        nes.peek_u8(NESTEST_OFFICIAL_RESULT),
+        nes.peek_u8(NESTEST_UNOFFICIAL_RESULT),
+    ];
+    match results {
+        [0, 0] => Ok(format!("{} lines", expected.len())),
+        [official, unofficial] => Err(format!(
+            "error codes ${official:02X} (official) ${unofficial:02X} (unofficial)"
+        )),
+    }
+}
+
+/// Runs until the CPU is stuck on a `JMP *` or a branch to itself and
+/// returns the address of that trap.
+fn run_until_trap(memory: &mut FlatMemory, start: u16, max_cycles: u64) -> Result<u16, String> {
+    let mut cpu = Cpu::new(memory);
+    cpu.reset();
+    cpu.set_program_counter(start);
+    while cpu.cycles() < max_cycles {
+        let counter = cpu.program_counter();
+        cpu.run();
+        if cpu.program_counter() == counter {
+            return Ok(counter);
+        }
+    }
+    Err(format!(
+        "no trap after {max_cycles} cycles, last at ${:04X}",
+        cpu.program_counter()
+    ))
+}
+
+/// Runs a binary image of the whole address space until it traps.
+fn run_functional_test(image: &[u8], success: u16) -> Result<String, String> {
+    let mut memory = FlatMemory {
+        memory: Box::new([0; 0x10000]),
+    };
+    memory.load(image, 0);
+    match run_until_trap(
+        &mut memory,
+        FUNCTIONAL_TEST_START,
+        FUNCTIONAL_TEST_MAX_CYCLES,
+    )? {
+        trap if trap == success => Ok(format!("success trap at ${trap:04X}")),
+        trap => Err(format!(
+            "trapped at ${trap:04X}, test case ${:02X}",
+            memory.memory[0x0200]
+        )),
+    }
+}
+
+fn read_blargg_text(nes: &Nes) -> String {
+    let text = (BLARGG_TEXT..BLARGG_TEXT_END)
+        .map(|addr| nes.peek_u8(addr))
+        .take_while(|&byte| byte != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+/// Runs a ROM reporting through the $6000 status and text protocol.
+fn run_blargg(rom: &[u8]) -> Result<String, String> {
+    let mut nes = Nes::new();
+    nes.load_rom(rom).map_err(|error| error.to_string())?;
+    let mut reset_delay = None;
+    for _ in 0..BLARGG_MAX_FRAMES {
+        nes.run_frame();
+        let signature = [0, 1, 2].map(|i| nes.peek_u8(BLARGG_SIGNATURE + i));
+        if signature != BLARGG_SIGNATURE_BYTES {
+            continue;
+        }
+        match nes.peek_u8(BLARGG_STATUS) {
+            BLARGG_RUNNING => reset_delay = None,
+            BLARGG_RESET_REQUESTED => match reset_delay {
+                Some(0) => {
+                    nes.reset();
+                    reset_delay = None;
+                }
+                Some(frames) => reset_delay = Some(frames - 1),
+                None => reset_delay = Some(BLARGG_RESET_DELAY_FRAMES),
+            },
+            0 => return Ok(read_blargg_text(&nes)),
+            code => {
+                return Err(format!(
+                    "result code {code}: {}",
+                    read_blargg_text(&nes).trim()
+                ))
+            }
+        }
+    }
+    Err(format!("no result after {BLARGG_MAX_FRAMES} frames"))
+}
+
+#[test]
+fn test_nestest() {
+    let log = String::from_utf8_lossy(&read_rom("nestest.log")).into_owned();
+    run_suite(&["nestest.nes"], |rom| run_nestest(rom, &log));
+}
+
+#[test]
+fn test_6502_functional_test() {
+    let success = String::from_utf8_lossy(&read_rom(FUNCTIONAL_TEST_SUCCESS)).into_owned();
+    let success = u16::from_str_radix(success.trim(), 16)
+        .unwrap_or_else(|error| panic!("{FUNCTIONAL_TEST_SUCCESS}: {error}"));
+    run_suite(&["6502_functional_test.bin"], |image| {
+        run_functional_test(image, success)
+    });
+}
+
+#[test]
+fn test_blargg_instr_test() {
+    run_suite(&INSTR_TEST_ROMS, run_blargg);
+}
+
+#[test]
+fn test_blargg_cpu_timing() {
+    run_suite(&CPU_TIMING_ROMS, run_blargg);
+}
+
+/// NROM image whose program reports `code` and `text` through $6000.
+fn blargg_protocol_rom(code: u8, text: &str) -> Vec<u8> {
+    const LDA_IMMEDIATE: u8 = 0xA9;
+    const STA_ABSOLUTE: u8 = 0x8D;
+    const PRG_ROM_SIZE: usize = 0x4000;
+    let mut program = Vec::new();
+    let mut store = |addr: u16, byte: u8| {
+        let [lo, hi] = addr.to_le_bytes();
+        program.extend([LDA_IMMEDIATE, byte, STA_ABSOLUTE, lo, hi]);
+    };
+    store(BLARGG_STATUS, BLARGG_RUNNING);
+    for (i, byte) in BLARGG_SIGNATURE_BYTES.into_iter().enumerate() {
+        store(BLARGG_SIGNATURE + i as u16, byte);
+    }
+    for (i, byte) in text.bytes().chain([0]).enumerate() {
+        store(BLARGG_TEXT + i as u16, byte);
+    }
+    store(BLARGG_STATUS, code);
+    let trap = 0xC000 + program.len() as u16;
+    program.push(0x4C);
+    program.extend(trap.to_le_bytes());
+
+    let mut prg_rom = vec![0; PRG_ROM_SIZE];
+    prg_rom[..program.len()].copy_from_slice(&program);
+    prg_rom[PRG_ROM_SIZE - 4..PRG_ROM_SIZE - 2].copy_from_slice(&0xC000u16.to_le_bytes());
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    rom.extend(prg_rom);
+    rom.extend([0; 0x2000]);
+    rom
+}
+
+#[test]
+fn test_blargg_protocol_on_generated_rom() {
+    assert_eq!(
+        Ok("all good".to_string()),
+        run_blargg(&blargg_protocol_rom(0, "all good"))
+    );
+    assert_eq!(
+        Err("result code 3: broken".to_string()),
+        run_blargg(&blargg_protocol_rom(3, "broken\n"))
+    );
+}
+
+#[test]
+fn test_functional_traps_on_generated_image() {
+    let mut image = vec![0; 0x10000];
+    // LDX #$05 ; DEX ; BNE $0402 ; JMP $0405
+    image[0x0400..0x0408].copy_from_slice(&[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x4C, 0x05, 0x04]);
+    assert_eq!(
+        Ok("success trap at $0405".to_string()),
+        run_functional_test(&image, 0x0405)
+    );
+    // BEQ * with Z set by LDX #$00
+    image[0x0400..0x0404].copy_from_slice(&[0xA2, 0x00, 0xF0, 0xFE]);
+    assert!(run_functional_test(&image, 0x0405)
+        .unwrap_err()
+        .starts_with("trapped at $0402"));
+}